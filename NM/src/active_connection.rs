@@ -0,0 +1,45 @@
+use ActiveConnection;
+use Device;
+use IPConfig;
+use RemoteConnection;
+use ffi;
+use glib;
+use glib::object::IsA;
+use glib::translate::*;
+
+/// Getters of NMActiveConnection gir can't generate yet
+pub trait ActiveConnectionExtManual {
+    fn get_connection(&self) -> Option<RemoteConnection>;
+
+    fn get_devices(&self) -> Vec<Device>;
+
+    fn get_ip4_config(&self) -> Option<IPConfig>;
+
+    fn get_ip6_config(&self) -> Option<IPConfig>;
+}
+
+impl<O: IsA<ActiveConnection> + IsA<glib::object::Object>> ActiveConnectionExtManual for O {
+    fn get_connection(&self) -> Option<RemoteConnection> {
+        unsafe {
+            from_glib_none(ffi::nm_active_connection_get_connection(self.to_glib_none().0))
+        }
+    }
+
+    fn get_devices(&self) -> Vec<Device> {
+        unsafe {
+            FromGlibPtrContainer::from_glib_none(ffi::nm_active_connection_get_devices(self.to_glib_none().0))
+        }
+    }
+
+    fn get_ip4_config(&self) -> Option<IPConfig> {
+        unsafe {
+            from_glib_none(ffi::nm_active_connection_get_ip4_config(self.to_glib_none().0))
+        }
+    }
+
+    fn get_ip6_config(&self) -> Option<IPConfig> {
+        unsafe {
+            from_glib_none(ffi::nm_active_connection_get_ip6_config(self.to_glib_none().0))
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use ActiveConnection;
+use Client;
+use Device;
+#[cfg(any(feature = "v1_6", feature = "dox"))]
+use DnsEntry;
+use Error;
+use ffi;
+use glib::translate::*;
+use std::ptr;
+
+impl Client {
+    /// Create a new client and synchronously load the state of NetworkManager. Signals are
+    /// delivered on the thread default main context of the calling thread.
+    pub fn new() -> Result<Client, Error> {
+        unsafe {
+            let mut error = ptr::null_mut();
+            let ret = ffi::nm_client_new(ptr::null_mut(), &mut error);
+            if error.is_null() { Ok(from_glib_full(ret)) } else { Err(from_glib_full(error)) }
+        }
+    }
+
+    pub fn get_active_connections(&self) -> Vec<ActiveConnection> {
+        unsafe {
+            FromGlibPtrContainer::from_glib_none(ffi::nm_client_get_active_connections(self.to_glib_none().0))
+        }
+    }
+
+    pub fn get_devices(&self) -> Vec<Device> {
+        unsafe {
+            FromGlibPtrContainer::from_glib_none(ffi::nm_client_get_devices(self.to_glib_none().0))
+        }
+    }
+
+    /// DNS configuration as NetworkManager applies it, ordered by priority
+    #[cfg(any(feature = "v1_6", feature = "dox"))]
+    pub fn get_dns_configuration(&self) -> Vec<DnsEntry> {
+        unsafe {
+            FromGlibPtrContainer::from_glib_none(ffi::nm_client_get_dns_configuration(self.to_glib_none().0))
+        }
+    }
+}
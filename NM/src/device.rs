@@ -0,0 +1,59 @@
+use IPConfig;
+use ffi;
+use glib;
+use glib::object::IsA;
+use glib::translate::*;
+
+glib_wrapper! {
+    /// NMDevice, only the parts needed for DNS configuration are bound so far
+    pub struct Device(Object<ffi::NMDevice, ffi::NMDeviceClass>);
+
+    match fn {
+        get_type => || ffi::nm_device_get_type(),
+    }
+}
+
+pub trait DeviceExt {
+    /// Raw `NMDeviceType` value
+    fn get_device_type(&self) -> i32;
+
+    fn get_iface(&self) -> Option<String>;
+
+    fn get_ip_iface(&self) -> Option<String>;
+
+    fn get_ip4_config(&self) -> Option<IPConfig>;
+
+    fn get_ip6_config(&self) -> Option<IPConfig>;
+}
+
+impl<O: IsA<Device> + IsA<glib::object::Object>> DeviceExt for O {
+    fn get_device_type(&self) -> i32 {
+        unsafe {
+            ffi::nm_device_get_device_type(self.to_glib_none().0)
+        }
+    }
+
+    fn get_iface(&self) -> Option<String> {
+        unsafe {
+            from_glib_none(ffi::nm_device_get_iface(self.to_glib_none().0))
+        }
+    }
+
+    fn get_ip_iface(&self) -> Option<String> {
+        unsafe {
+            from_glib_none(ffi::nm_device_get_ip_iface(self.to_glib_none().0))
+        }
+    }
+
+    fn get_ip4_config(&self) -> Option<IPConfig> {
+        unsafe {
+            from_glib_none(ffi::nm_device_get_ip4_config(self.to_glib_none().0))
+        }
+    }
+
+    fn get_ip6_config(&self) -> Option<IPConfig> {
+        unsafe {
+            from_glib_none(ffi::nm_device_get_ip6_config(self.to_glib_none().0))
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use ffi;
+use glib::translate::*;
+use gobject_ffi;
+
+glib_wrapper! {
+    /// NMDnsEntry, one entry of NetworkManager's DNS configuration
+    pub struct DnsEntry(Boxed<ffi::NMDnsEntry>);
+
+    match fn {
+        copy => |ptr| gobject_ffi::g_boxed_copy(ffi::nm_dns_entry_get_type(), ptr as *mut _) as *mut ffi::NMDnsEntry,
+        free => |ptr| gobject_ffi::g_boxed_free(ffi::nm_dns_entry_get_type(), ptr as *mut _),
+        get_type => || ffi::nm_dns_entry_get_type(),
+    }
+}
+
+impl DnsEntry {
+    /// Domains the entry is used for, routing-only domains start with `~`
+    #[cfg(any(feature = "v1_6", feature = "dox"))]
+    pub fn get_domains(&self) -> Vec<String> {
+        unsafe {
+            FromGlibPtrContainer::from_glib_none(ffi::nm_dns_entry_get_domains(mut_override(self.to_glib_none().0)))
+        }
+    }
+
+    #[cfg(any(feature = "v1_6", feature = "dox"))]
+    pub fn get_interface(&self) -> Option<String> {
+        unsafe {
+            from_glib_none(ffi::nm_dns_entry_get_interface(mut_override(self.to_glib_none().0)))
+        }
+    }
+
+    #[cfg(any(feature = "v1_6", feature = "dox"))]
+    pub fn get_nameservers(&self) -> Vec<String> {
+        unsafe {
+            FromGlibPtrContainer::from_glib_none(ffi::nm_dns_entry_get_nameservers(mut_override(self.to_glib_none().0)))
+        }
+    }
+
+    /// Lower value means higher priority, negative values exclude entries with higher values
+    #[cfg(any(feature = "v1_6", feature = "dox"))]
+    pub fn get_priority(&self) -> i32 {
+        unsafe {
+            ffi::nm_dns_entry_get_priority(mut_override(self.to_glib_none().0))
+        }
+    }
+
+    #[cfg(any(feature = "v1_6", feature = "dox"))]
+    pub fn get_vpn(&self) -> bool {
+        unsafe {
+            from_glib(ffi::nm_dns_entry_get_vpn(mut_override(self.to_glib_none().0)))
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use Error;
+use ffi;
+use glib::translate::*;
+use std::ptr;
+
+glib_wrapper! {
+    /// NMIPAddress, one address of an IP configuration
+    pub struct IPAddress(Shared<ffi::NMIPAddress>);
+
+    match fn {
+        ref => |ptr| ffi::nm_ip_address_ref(ptr),
+        unref => |ptr| ffi::nm_ip_address_unref(ptr),
+        get_type => || ffi::nm_ip_address_get_type(),
+    }
+}
+
+impl IPAddress {
+    /// `family` is `AF_INET` or `AF_INET6`, the address must belong to it
+    pub fn new(family: i32, addr: &str, prefix: u32) -> Result<IPAddress, Error> {
+        unsafe {
+            let mut error = ptr::null_mut();
+            let ret = ffi::nm_ip_address_new(family, addr.to_glib_none().0, prefix, &mut error);
+            if error.is_null() { Ok(from_glib_full(ret)) } else { Err(from_glib_full(error)) }
+        }
+    }
+
+    pub fn get_address(&self) -> Option<String> {
+        unsafe {
+            from_glib_none(ffi::nm_ip_address_get_address(self.to_glib_none().0))
+        }
+    }
+
+    /// Address family, `AF_INET` or `AF_INET6`
+    pub fn get_family(&self) -> i32 {
+        unsafe {
+            ffi::nm_ip_address_get_family(self.to_glib_none().0)
+        }
+    }
+
+    pub fn get_prefix(&self) -> u32 {
+        unsafe {
+            ffi::nm_ip_address_get_prefix(self.to_glib_none().0)
+        }
+    }
+}
+
+#[test]
+fn test_ip_address() {
+    use libc::{AF_INET, AF_INET6};
+
+    let address = IPAddress::new(AF_INET, "10.40.0.6", 22).unwrap();
+    assert_eq!(address.get_address(), Some("10.40.0.6".to_string()));
+    assert_eq!(address.get_family(), AF_INET);
+    assert_eq!(address.get_prefix(), 22);
+
+    let address = IPAddress::new(AF_INET6, "2001:db8::10", 64).unwrap();
+    assert_eq!(address.get_address(), Some("2001:db8::10".to_string()));
+    assert_eq!(address.get_family(), AF_INET6);
+
+    assert!(IPAddress::new(AF_INET, "2001:db8::10", 64).is_err());
+}
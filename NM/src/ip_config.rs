@@ -0,0 +1,75 @@
+use IPAddress;
+use IPRoute;
+use ffi;
+use glib;
+use glib::object::IsA;
+use glib::translate::*;
+
+glib_wrapper! {
+    /// NMIPConfig, IPv4 or IPv6 configuration of an active connection or a device
+    pub struct IPConfig(Object<ffi::NMIPConfig, ffi::NMIPConfigClass>);
+
+    match fn {
+        get_type => || ffi::nm_ip_config_get_type(),
+    }
+}
+
+pub trait IPConfigExt {
+    fn get_addresses(&self) -> Vec<IPAddress>;
+
+    fn get_domains(&self) -> Vec<String>;
+
+    fn get_family(&self) -> i32;
+
+    fn get_gateway(&self) -> Option<String>;
+
+    fn get_nameservers(&self) -> Vec<String>;
+
+    fn get_routes(&self) -> Vec<IPRoute>;
+
+    fn get_searches(&self) -> Vec<String>;
+}
+
+impl<O: IsA<IPConfig> + IsA<glib::object::Object>> IPConfigExt for O {
+    fn get_addresses(&self) -> Vec<IPAddress> {
+        unsafe {
+            FromGlibPtrContainer::from_glib_none(ffi::nm_ip_config_get_addresses(self.to_glib_none().0))
+        }
+    }
+
+    fn get_domains(&self) -> Vec<String> {
+        unsafe {
+            FromGlibPtrContainer::from_glib_none(ffi::nm_ip_config_get_domains(self.to_glib_none().0))
+        }
+    }
+
+    fn get_family(&self) -> i32 {
+        unsafe {
+            ffi::nm_ip_config_get_family(self.to_glib_none().0)
+        }
+    }
+
+    fn get_gateway(&self) -> Option<String> {
+        unsafe {
+            from_glib_none(ffi::nm_ip_config_get_gateway(self.to_glib_none().0))
+        }
+    }
+
+    fn get_nameservers(&self) -> Vec<String> {
+        unsafe {
+            FromGlibPtrContainer::from_glib_none(ffi::nm_ip_config_get_nameservers(self.to_glib_none().0))
+        }
+    }
+
+    fn get_routes(&self) -> Vec<IPRoute> {
+        unsafe {
+            FromGlibPtrContainer::from_glib_none(ffi::nm_ip_config_get_routes(self.to_glib_none().0))
+        }
+    }
+
+    fn get_searches(&self) -> Vec<String> {
+        unsafe {
+            FromGlibPtrContainer::from_glib_none(ffi::nm_ip_config_get_searches(self.to_glib_none().0))
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use Error;
+use ffi;
+use glib::translate::*;
+use std::ptr;
+
+glib_wrapper! {
+    /// NMIPRoute, one route of an IP configuration
+    pub struct IPRoute(Shared<ffi::NMIPRoute>);
+
+    match fn {
+        ref => |ptr| ffi::nm_ip_route_ref(ptr),
+        unref => |ptr| ffi::nm_ip_route_unref(ptr),
+        get_type => || ffi::nm_ip_route_get_type(),
+    }
+}
+
+impl IPRoute {
+    /// `family` is `AF_INET` or `AF_INET6`, a `metric` of `-1` leaves it unset
+    pub fn new(family: i32, dest: &str, prefix: u32, next_hop: Option<&str>, metric: i64) -> Result<IPRoute, Error> {
+        unsafe {
+            let mut error = ptr::null_mut();
+            let ret = ffi::nm_ip_route_new(family, dest.to_glib_none().0, prefix, next_hop.to_glib_none().0, metric,
+                                           &mut error);
+            if error.is_null() { Ok(from_glib_full(ret)) } else { Err(from_glib_full(error)) }
+        }
+    }
+
+    pub fn get_dest(&self) -> Option<String> {
+        unsafe {
+            from_glib_none(ffi::nm_ip_route_get_dest(self.to_glib_none().0))
+        }
+    }
+
+    /// Address family, `AF_INET` or `AF_INET6`
+    pub fn get_family(&self) -> i32 {
+        unsafe {
+            ffi::nm_ip_route_get_family(self.to_glib_none().0)
+        }
+    }
+
+    /// Metric of the route, `-1` if it is not set
+    pub fn get_metric(&self) -> i64 {
+        unsafe {
+            ffi::nm_ip_route_get_metric(self.to_glib_none().0)
+        }
+    }
+
+    pub fn get_next_hop(&self) -> Option<String> {
+        unsafe {
+            from_glib_none(ffi::nm_ip_route_get_next_hop(self.to_glib_none().0))
+        }
+    }
+
+    pub fn get_prefix(&self) -> u32 {
+        unsafe {
+            ffi::nm_ip_route_get_prefix(self.to_glib_none().0)
+        }
+    }
+}
+
+#[test]
+fn test_ip_route() {
+    use libc::AF_INET;
+
+    let route = IPRoute::new(AF_INET, "66.187.233.0", 24, Some("10.40.0.1"), 50).unwrap();
+    assert_eq!(route.get_dest(), Some("66.187.233.0".to_string()));
+    assert_eq!(route.get_family(), AF_INET);
+    assert_eq!(route.get_prefix(), 24);
+    assert_eq!(route.get_next_hop(), Some("10.40.0.1".to_string()));
+    assert_eq!(route.get_metric(), 50);
+
+    let route = IPRoute::new(AF_INET, "10.0.0.0", 8, None, -1).unwrap();
+    assert_eq!(route.get_next_hop(), None);
+    assert_eq!(route.get_metric(), -1);
+}
@@ -0,0 +1,46 @@
+//! Rust bindings for libnm
+//!
+//! Most of the API lives in `auto` which is generated by gir. Functions gir can't handle yet are
+//! written by hand in the other modules.
+
+#![allow(deprecated)]
+
+extern crate nm_sys as ffi;
+extern crate glib_sys as glib_ffi;
+extern crate gobject_sys as gobject_ffi;
+#[macro_use]
+extern crate glib;
+extern crate libc;
+
+pub use glib::Error;
+
+mod auto;
+pub use auto::*;
+pub use auto::traits::*;
+
+mod active_connection;
+pub use active_connection::ActiveConnectionExtManual;
+mod client;
+mod device;
+pub use device::{Device, DeviceExt};
+mod dns_entry;
+pub use dns_entry::DnsEntry;
+mod ip_address;
+pub use ip_address::IPAddress;
+mod ip_config;
+pub use ip_config::{IPConfig, IPConfigExt};
+mod ip_route;
+pub use ip_route::IPRoute;
+mod remote_connection;
+pub use remote_connection::{RemoteConnection, RemoteConnectionExt};
+mod setting_ip_config;
+pub use setting_ip_config::{SettingIPConfig, SettingIPConfigExt};
+
+pub mod prelude {
+    pub use auto::traits::*;
+    pub use active_connection::ActiveConnectionExtManual;
+    pub use device::DeviceExt;
+    pub use ip_config::IPConfigExt;
+    pub use remote_connection::RemoteConnectionExt;
+    pub use setting_ip_config::SettingIPConfigExt;
+}
@@ -0,0 +1,37 @@
+use SettingIPConfig;
+use ffi;
+use glib;
+use glib::object::IsA;
+use glib::translate::*;
+
+glib_wrapper! {
+    /// NMRemoteConnection, a connection profile exported by NetworkManager
+    pub struct RemoteConnection(Object<ffi::NMRemoteConnection, ffi::NMRemoteConnectionClass>);
+
+    match fn {
+        get_type => || ffi::nm_remote_connection_get_type(),
+    }
+}
+
+/// Getters of the NMConnection interface, only the IP settings are bound so far
+pub trait RemoteConnectionExt {
+    fn get_setting_ip4_config(&self) -> Option<SettingIPConfig>;
+
+    fn get_setting_ip6_config(&self) -> Option<SettingIPConfig>;
+}
+
+impl<O: IsA<RemoteConnection> + IsA<glib::object::Object>> RemoteConnectionExt for O {
+    fn get_setting_ip4_config(&self) -> Option<SettingIPConfig> {
+        unsafe {
+            let connection = self.to_glib_none().0 as *mut ffi::NMConnection;
+            from_glib_none(ffi::nm_connection_get_setting_ip4_config(connection) as *mut ffi::NMSettingIPConfig)
+        }
+    }
+
+    fn get_setting_ip6_config(&self) -> Option<SettingIPConfig> {
+        unsafe {
+            let connection = self.to_glib_none().0 as *mut ffi::NMConnection;
+            from_glib_none(ffi::nm_connection_get_setting_ip6_config(connection) as *mut ffi::NMSettingIPConfig)
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use ffi;
+use glib;
+use glib::object::IsA;
+use glib::translate::*;
+
+glib_wrapper! {
+    /// NMSettingIPConfig, the ipv4 or ipv6 setting of a connection profile
+    pub struct SettingIPConfig(Object<ffi::NMSettingIPConfig, ffi::NMSettingIPConfigClass>);
+
+    match fn {
+        get_type => || ffi::nm_setting_ip_config_get_type(),
+    }
+}
+
+pub trait SettingIPConfigExt {
+    fn get_method(&self) -> Option<String>;
+
+    fn get_never_default(&self) -> bool;
+}
+
+impl<O: IsA<SettingIPConfig> + IsA<glib::object::Object>> SettingIPConfigExt for O {
+    fn get_method(&self) -> Option<String> {
+        unsafe {
+            from_glib_none(ffi::nm_setting_ip_config_get_method(self.to_glib_none().0))
+        }
+    }
+
+    fn get_never_default(&self) -> bool {
+        unsafe {
+            from_glib(ffi::nm_setting_ip_config_get_never_default(self.to_glib_none().0))
+        }
+    }
+}
@@ -2,44 +2,85 @@ use failure::Error;
 
 use serde_json;
 
-use std::net::Ipv4Addr;
-use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use zone_tree::ZoneTree;
+
+use nm_connection::{parse_prefix, Config, Identity};
+pub use nm_connection::{ConnectionType, Domain, Domains};
 
 /// Weakly typed connection
 /// This should eventually go away
 #[derive(Debug, Serialize, Deserialize)]
 struct ConnectionWeak {
     id: String,
+    #[serde(default)]
+    uuid: String,
+    /// IP interfaces of the devices the connection is active on
+    #[serde(default)]
+    interfaces: Vec<String>,
     #[serde(rename = "type")]
     con_type: String,
     default: bool,
     addresses: Vec<String>,
     nameservers: Vec<String>,
+    /// Domains in the NetworkManager syntax, routing-only domains are prefixed with `~`
     domains: Vec<String>,
+    /// The `never-default` setting of the connection, missing means the default route is allowed
+    #[serde(default)]
+    never_default: bool,
+    /// Routes in the `dest/prefix` form, both IPv4 and IPv6
+    #[serde(default)]
+    routes: Vec<String>,
 }
 
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
-pub enum ConnectionType {
-    Ethernet = 0,
-    VPN = 1,
-    WiFi = 2,
-    Other = 3,
-}
-
-pub type Domain = String;
-
 /// Strongly typed connection
 #[derive(Debug)]
 pub struct Connection {
     id: String,
+    uuid: String,
+    interfaces: Vec<String>,
     con_type: ConnectionType,
+    /// Type exactly as NetworkManager reports it, the exclusion rules match against it
+    nm_type: String,
     default: bool,
     addresses: Vec<(Ipv4Addr, u8)>,
     nameservers: Vec<Ipv4Addr>,
-    domains: Vec<Domain>,
+    domains: Domains,
+    /// The connection must never get the default route
+    never_default: bool,
+    /// Destinations of routes pushed by the connection
+    routes: Vec<(IpAddr, u8)>,
 }
 
-#[derive(Debug)]
+impl Connection {
+    /// VPN or overlay that routes all traffic, so it should receive all queries as well. Tunnels with
+    /// `never-default` are split tunnels even when they ask for `~.`.
+    fn is_full_tunnel(&self) -> bool {
+        self.con_type.is_tunnel() && !self.never_default && (self.default || self.domains.catch_all)
+    }
+}
+
+impl Identity for Connection {
+    fn nm_type(&self) -> &str {
+        &self.nm_type
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ForwardZone {
     pub domain: Domain,
     pub nameservers: Vec<Ipv4Addr>,
@@ -47,9 +88,9 @@ pub struct ForwardZone {
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ReverseZone {
-    pub zone: &'static str,
+    pub zone: String,
     pub nameservers: Vec<Ipv4Addr>,
     pub con_type: ConnectionType,
 }
@@ -69,46 +110,266 @@ fn ipv4_to_reverse_zone(addr: &Ipv4Addr) -> Option<&'static str> {
     }
 }
 
+/// Largest blocks for which reverse zones are derived from routes, as prefix lengths. Shorter
+/// routes, most notably the default route, are ignored.
+#[derive(Debug, Clone, Copy)]
+pub struct RouteZoneLimits {
+    pub ipv4: u8,
+    pub ipv6: u8,
+}
+
+impl Default for RouteZoneLimits {
+    fn default() -> Self {
+        RouteZoneLimits {
+            ipv4: 8,
+            ipv6: 32,
+        }
+    }
+}
+
+/// Reverse zones covering exactly the given IPv4 prefix. Zones are delegated on octet boundaries,
+/// so e.g. a /22 turns into four /24 zones.
+fn ipv4_prefix_to_reverse_zones(net: Ipv4Addr, prefix: u8) -> Vec<String> {
+    if prefix == 0 || prefix > 32 {
+        return vec![];
+    }
+    let octets = u32::from(prefix).div_ceil(8);
+    let zone_prefix = octets * 8;
+    let base = u32::from(net) & (u32::MAX << (32 - u32::from(prefix)));
+    (0..1u32 << (zone_prefix - u32::from(prefix)))
+        .map(|i| {
+            let addr = Ipv4Addr::from(base | i.checked_shl(32 - zone_prefix).unwrap_or(0));
+            let mut labels: Vec<String> = addr.octets()[..octets as usize].iter().map(|o| o.to_string()).collect();
+            labels.reverse();
+            format!("{}.in-addr.arpa", labels.join("."))
+        })
+        .collect()
+}
+
+/// Reverse zones covering exactly the given IPv6 prefix. Zones are delegated on nibble boundaries.
+fn ipv6_prefix_to_reverse_zones(net: Ipv6Addr, prefix: u8) -> Vec<String> {
+    if prefix == 0 || prefix > 128 {
+        return vec![];
+    }
+    let nibbles = u32::from(prefix).div_ceil(4);
+    let zone_prefix = nibbles * 4;
+    let base = u128::from(net) & (u128::MAX << (128 - u32::from(prefix)));
+    (0..1u128 << (zone_prefix - u32::from(prefix)))
+        .map(|i| {
+            let addr = base | i.checked_shl(128 - zone_prefix).unwrap_or(0);
+            let labels: Vec<String> = (0..nibbles).rev()
+                .map(|n| format!("{:x}", (addr >> (124 - 4 * n)) & 0xf))
+                .collect();
+            format!("{}.ip6.arpa", labels.join("."))
+        })
+        .collect()
+}
+
+/// Reverse zones for a routed prefix, unless the block is larger than the limits allow
+fn route_to_reverse_zones(dest: IpAddr, prefix: u8, limits: RouteZoneLimits) -> Vec<String> {
+    match dest {
+        IpAddr::V4(net) if prefix >= limits.ipv4 => ipv4_prefix_to_reverse_zones(net, prefix),
+        IpAddr::V6(net) if prefix >= limits.ipv6 => ipv6_prefix_to_reverse_zones(net, prefix),
+        _ => vec![],
+    }
+}
+
 pub fn parse_connections(input: &str) -> Result<Vec<Connection>, Error> {
     let connections: Vec<ConnectionWeak> = serde_json::from_str(input)?;
     let connections: Vec<Connection> = connections.into_iter()
         .filter_map(|c| {
             let id = c.id;
-            let con_type = if c.con_type.contains("ethernet") { ConnectionType::Ethernet }
-                else if c.con_type.contains("wireless") { ConnectionType::WiFi }
-                    else if c.con_type.contains("vpn") { ConnectionType::VPN }
-                        else { ConnectionType::Other };
+            let uuid = c.uuid;
+            let interfaces = c.interfaces;
+            let con_type = ConnectionType::from_nm(&c.con_type);
+            let nm_type = c.con_type;
             let default = c.default;
+            let never_default = c.never_default;
             let addresses: Vec<(Ipv4Addr, u8)> = c.addresses.iter()
-                .filter_map(|a| {
-                    let split: Vec<&str> = a.split('/').collect();
-                    if split.len() < 2 {
-                        return None
-                    }
-                    let addr = if let Ok(a) = split[0].parse::<Ipv4Addr>() {a} else {return None};
-                    let prefix = if let Ok(a) = split[1].parse::<u8>() {a} else {return None};
-                    Some((addr, prefix))
-                })
+                .filter_map(|a| parse_prefix(a))
+                .collect();
+            let routes = c.routes.iter()
+                .filter_map(|r| parse_prefix(r))
                 .collect();
             let nameservers = c.nameservers.iter()
                 .filter_map(|a| a.parse::<Ipv4Addr>().ok() )
                 .collect();
-            let domains = c.domains;
+            let domains = c.domains.iter().map(|d| d.as_str()).collect();
             Some(Connection {
-                id, con_type, default, addresses, nameservers, domains
+                id, uuid, interfaces, con_type, nm_type, default, addresses, nameservers, domains,
+                never_default, routes,
             })
         })
         .collect();
     Ok(connections)
 }
 
+/// Drop connections excluded by the configuration, none of their zones reach the resolver.
+/// Returns the ids of the dropped connections.
+pub fn remove_excluded(connections: &mut Vec<Connection>, config: &Config) -> Vec<String> {
+    let mut excluded = vec![];
+    connections.retain(|c| {
+        if config.is_excluded(c) {
+            excluded.push(c.id.clone());
+            false
+        } else {
+            true
+        }
+    });
+    excluded
+}
+
+/// Why a nameserver would make the resolver forward queries back to this host
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LocalNameserver {
+    /// The address kresd listens on, forwarding there is an immediate loop
+    Resolver,
+    /// Loopback address, e.g. NetworkManager's dnsmasq on 127.0.0.1 or systemd-resolved's stub
+    /// on 127.0.0.53, which in turn forwards to the resolver the system points to
+    Loopback,
+    /// Address of one of the connections, some local service answers on it
+    HostAddress,
+}
+
+impl fmt::Display for LocalNameserver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LocalNameserver::Resolver => "the local resolver itself",
+            LocalNameserver::Loopback => "a loopback address",
+            LocalNameserver::HostAddress => "an address of this host",
+        })
+    }
+}
+
+/// Nameservers dropped from a connection to prevent a query loop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopReport {
+    pub connection: String,
+    pub dropped: Vec<(Ipv4Addr, LocalNameserver)>,
+    /// No nameserver is left, so none of the connection's zones is forwarded
+    pub unforwarded: bool,
+}
+
+impl fmt::Display for LoopReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dropped: Vec<String> = self.dropped.iter()
+            .map(|(addr, reason)| format!("{} is {}", addr, reason))
+            .collect();
+        write!(f, "Connection {}: {}", self.connection, dropped.join(", "))?;
+        if self.unforwarded {
+            write!(f, "; its zones are not forwarded as it has no other nameserver")?;
+        }
+        Ok(())
+    }
+}
+
+fn local_nameserver(addr: Ipv4Addr, resolver: Ipv4Addr, host_addresses: &[Ipv4Addr]) -> Option<LocalNameserver> {
+    if addr == resolver {
+        Some(LocalNameserver::Resolver)
+    } else if addr.is_loopback() || addr.is_unspecified() {
+        Some(LocalNameserver::Loopback)
+    } else if host_addresses.contains(&addr) {
+        Some(LocalNameserver::HostAddress)
+    } else {
+        None
+    }
+}
+
+/// Drop nameservers pointing back to this host, forwarding queries to them would make them come
+/// back to the resolver. Connections left with no nameservers are skipped by the calculation of
+/// zones. Returns a report for every connection that lost a nameserver.
+pub fn remove_local_nameservers(connections: &mut [Connection], resolver: Ipv4Addr) -> Vec<LoopReport> {
+    let host_addresses: Vec<Ipv4Addr> = connections.iter()
+        .flat_map(|c| c.addresses.iter().map(|(addr, _)| *addr))
+        .collect();
+    let mut reports = vec![];
+    for c in connections.iter_mut() {
+        let mut dropped = vec![];
+        c.nameservers.retain(|ns| match local_nameserver(*ns, resolver, &host_addresses) {
+            Some(reason) => {
+                dropped.push((*ns, reason));
+                false
+            },
+            None => true,
+        });
+        if !dropped.is_empty() {
+            reports.push(LoopReport {
+                connection: c.id.clone(),
+                dropped,
+                unforwarded: c.nameservers.is_empty(),
+            });
+        }
+    }
+    reports
+}
+
+/// Common view of forward and reverse zones for resolving their nesting
+trait Zone: Clone {
+    fn name(&self) -> &str;
+    fn with_name(self, name: String) -> Self;
+    fn con_type(&self) -> ConnectionType;
+    fn nameservers(&self) -> &[Ipv4Addr];
+}
+
+impl Zone for ForwardZone {
+    fn name(&self) -> &str {
+        &self.domain
+    }
+
+    fn with_name(self, domain: String) -> Self {
+        ForwardZone { domain, ..self }
+    }
+
+    fn con_type(&self) -> ConnectionType {
+        self.con_type
+    }
+
+    fn nameservers(&self) -> &[Ipv4Addr] {
+        &self.nameservers
+    }
+}
+
+impl Zone for ReverseZone {
+    fn name(&self) -> &str {
+        &self.zone
+    }
+
+    fn with_name(self, zone: String) -> Self {
+        ReverseZone { zone, ..self }
+    }
+
+    fn con_type(&self) -> ConnectionType {
+        self.con_type
+    }
+
+    fn nameservers(&self) -> &[Ipv4Addr] {
+        &self.nameservers
+    }
+}
+
+/// Decide who owns each zone and drop the zones that don't need a rule of their own. A zone
+/// claimed by several connections goes to the one with the highest priority. A zone forwarded to
+/// the same nameservers as its closest enclosing zone is covered by the parent's rule. The result
+/// is ordered longest suffix first.
+fn resolve_nesting<Z: Zone>(zones: Vec<Z>) -> Vec<Z> {
+    let mut tree = ZoneTree::new();
+    for zone in zones {
+        let name = zone.name().to_string();
+        tree.insert(&name, zone, |old, new| new.con_type().priority() < old.con_type().priority());
+    }
+    tree.zones().into_iter()
+        .filter(|z| z.parent.is_none_or(|p| p.nameservers() != z.owner.nameservers()))
+        .map(|z| z.owner.clone().with_name(z.zone))
+        .collect()
+}
+
 pub fn get_forward_zones(connections: &Vec<Connection>) -> Vec<ForwardZone> {
     let forward_zones: Vec<ForwardZone> = connections.iter()
-        .filter(|c| c.domains.len() != 0)
+        .filter(|c| !c.nameservers.is_empty())
         .flat_map(|c| {
-            c.domains.iter().map(move |d| {
+            c.domains.all().cloned().map(move |d| {
                 ForwardZone {
-                    domain: d.clone(),
+                    domain: d,
                     nameservers: c.nameservers.clone(),
                     con_type: c.con_type,
                 }
@@ -116,78 +377,64 @@ pub fn get_forward_zones(connections: &Vec<Connection>) -> Vec<ForwardZone> {
         })
         .collect();
 
-    let indexes;
-    {
-        let mut forward_zones_unique: HashMap<&String, (usize, &ForwardZone)> = HashMap::new();
-        for (i, zone) in forward_zones.iter().enumerate() {
-            let insert;
-            {
-                let fwd_zone = forward_zones_unique.entry(&zone.domain).or_insert((i, zone));
-                insert = fwd_zone.1.con_type > zone.con_type;
-            }
-            if insert {
-                forward_zones_unique.insert(&zone.domain, (i, zone));
-            }
-        }
+    resolve_nesting(forward_zones)
+}
 
-        indexes = forward_zones_unique.into_iter()
-            .map(|(_, (i, _))| i)
-            .collect::<Vec<_>>();
-    }
+/// Zone for all queries that don't match any forward or reverse zone. Full-tunnel VPNs take
+/// precedence, then connections asking for all queries with `~.`. `None` means that the queries
+/// go to the public resolver.
+pub fn get_catch_all_zone(connections: &[Connection]) -> Option<ForwardZone> {
+    connections.iter()
+        .filter(|c| !c.nameservers.is_empty())
+        .filter(|c| c.is_full_tunnel() || (c.domains.catch_all && !c.never_default))
+        .min_by_key(|c| (!c.is_full_tunnel(), c.con_type.priority()))
+        .map(|c| ForwardZone {
+            domain: ".".to_string(),
+            nameservers: c.nameservers.clone(),
+            con_type: c.con_type,
+        })
+}
 
-    forward_zones.into_iter()
-        .enumerate()
-        .filter(|(i, _)| indexes.contains(&i))
-        .map(|(_, zone)| zone)
-        .collect()
+/// Search list made of search domains of all connections, connections with higher priority come
+/// first. Routing-only domains are never part of it.
+pub fn get_search_domains(connections: &[Connection]) -> Vec<Domain> {
+    let mut sorted: Vec<&Connection> = connections.iter().collect();
+    sorted.sort_by_key(|c| c.con_type.priority());
+    let mut search: Vec<Domain> = vec![];
+    for domain in sorted.iter().flat_map(|c| c.domains.search.iter()) {
+        if !search.contains(domain) {
+            search.push(domain.clone());
+        }
+    }
+    search
 }
 
-pub fn get_reverse_zones(connections: &Vec<Connection>) -> Vec<ReverseZone> {
+/// Reverse zones for the private ranges the connections have addresses in and for every prefix
+/// they route
+pub fn get_reverse_zones(connections: &Vec<Connection>, limits: RouteZoneLimits) -> Vec<ReverseZone> {
     let reverse_zones: Vec<ReverseZone> = connections.iter()
-        .filter(|c| c.addresses.len() != 0)
+        .filter(|c| !c.nameservers.is_empty())
         .flat_map(|c| {
-            c.addresses.iter()
-                .filter_map(move |a| {
-                    Some(ReverseZone {
-                        zone: if let Some(zone) = ipv4_to_reverse_zone(&a.0) {
-                            zone
-                        } else {
-                            return None
-                        },
-                        nameservers: c.nameservers.clone(),
-                        con_type: c.con_type,
-                    })
-                })
+            let own = c.addresses.iter()
+                .filter_map(|a| ipv4_to_reverse_zone(&a.0))
+                .map(|zone| zone.to_string());
+            let routed = c.routes.iter()
+                .flat_map(move |&(dest, prefix)| route_to_reverse_zones(dest, prefix, limits));
+            own.chain(routed).map(move |zone| {
+                ReverseZone {
+                    zone,
+                    nameservers: c.nameservers.clone(),
+                    con_type: c.con_type,
+                }
+            })
         })
         .collect();
 
-    let indexes;
-    {
-        let mut reverse_zones_unique: HashMap<&str, (usize, &ReverseZone)> = HashMap::new();
-        for (i, zone) in reverse_zones.iter().enumerate() {
-            let insert;
-            {
-                let fwd_zone = reverse_zones_unique.entry(&zone.zone).or_insert((i, zone));
-                insert = fwd_zone.1.con_type > zone.con_type;
-            }
-            if insert {
-                reverse_zones_unique.insert(&zone.zone, (i, zone));
-            }
-        }
-
-        indexes = reverse_zones_unique.into_iter()
-            .map(|(_, (i, _))| i)
-            .collect::<Vec<_>>();
-    }
-
-    reverse_zones.into_iter()
-        .enumerate()
-        .filter(|(i, _)| indexes.contains(&i))
-        .map(|(_, zone)| zone)
-        .collect()
+    resolve_nesting(reverse_zones)
 }
 
-//static TESTING_INPUT: &'static str = r#"[{"id": "enp0s25", "type": "802-3-ethernet", "default": true, "addresses": ["10.10.0.10/24"], "nameservers": ["10.10.0.99", "10.10.0.88"], "domains": ["afk.redhat.com", "redhat.com"]}, {"id": "Red Hat WIFI", "type": "802-11-wireless", "default": false, "addresses": ["10.111.111.111/21"], "nameservers": ["10.111.111.110", "10.111.111.109", "10.111.111.108"], "domains": ["redhat.com"]}, {"id": "Red Hat VPN", "type": "vpn", "default": false, "addresses": ["10.11.111.111/22"], "nameservers": ["10.11.111.10", "10.11.111.11"], "domains": ["redhat.com"]}, {"id": "tun0", "type": "tun", "default": false, "addresses": ["10.40.0.6/22"], "nameservers": [], "domains": []}]"#;
+#[cfg(test)]
+static TESTING_INPUT: &str = r#"[{"id": "enp0s25", "type": "802-3-ethernet", "default": true, "addresses": ["10.10.0.10/24"], "nameservers": ["10.10.0.99", "10.10.0.88"], "domains": ["afk.redhat.com", "redhat.com"]}, {"id": "Red Hat WIFI", "type": "802-11-wireless", "default": false, "addresses": ["10.111.111.111/21"], "nameservers": ["10.111.111.110", "10.111.111.109", "10.111.111.108"], "domains": ["redhat.com"]}, {"id": "Red Hat VPN", "type": "vpn", "default": false, "addresses": ["10.11.111.111/22"], "nameservers": ["10.11.111.10", "10.11.111.11"], "domains": ["redhat.com"]}, {"id": "tun0", "type": "tun", "default": false, "addresses": ["10.40.0.6/22"], "nameservers": [], "domains": []}]"#;
 
 #[test]
 fn reverse_zone_class_a() {
@@ -201,3 +448,139 @@ fn reverse_zone_class_c_none() {
     assert_eq!(ipv4_to_reverse_zone(&addr), None)
 }
 
+
+#[test]
+fn routing_and_search_domains() {
+    let input = r#"[{"id": "Red Hat VPN", "type": "vpn", "default": false, "addresses": ["10.11.111.111/22"], "nameservers": ["10.11.111.10"], "domains": ["redhat.com", "~corp.redhat.com", "~."]}, {"id": "Home", "type": "802-11-wireless", "default": true, "addresses": ["192.168.1.10/24"], "nameservers": ["192.168.1.1"], "domains": ["home"]}]"#;
+    let connections = parse_connections(input).unwrap();
+    assert_eq!(connections[0].domains.search, vec!["redhat.com".to_string()]);
+    assert_eq!(connections[0].domains.routing, vec!["corp.redhat.com".to_string()]);
+    assert!(connections[0].domains.catch_all);
+    assert!(!connections[1].domains.catch_all);
+
+    assert_eq!(get_search_domains(&connections), vec!["redhat.com".to_string(), "home".to_string()]);
+    let zones: Vec<String> = get_forward_zones(&connections).into_iter().map(|z| z.domain).collect();
+    // corp.redhat.com goes to the same nameservers as redhat.com, so it needs no rule of its own
+    assert_eq!(zones, vec!["redhat.com", "home"]);
+}
+
+#[test]
+fn catch_all_goes_to_full_tunnel_vpn() {
+    let split = r#"[{"id": "Red Hat VPN", "type": "vpn", "default": false, "addresses": ["10.11.111.111/22"], "nameservers": ["10.11.111.10"], "domains": ["redhat.com"]}, {"id": "Home", "type": "802-11-wireless", "default": true, "addresses": ["192.168.1.10/24"], "nameservers": ["192.168.1.1"], "domains": ["home"]}]"#;
+    assert!(get_catch_all_zone(&parse_connections(split).unwrap()).is_none());
+
+    let full = split.replace(r#""default": false"#, r#""default": true"#);
+    let zone = get_catch_all_zone(&parse_connections(&full).unwrap()).unwrap();
+    assert_eq!(zone.domain, ".");
+    assert_eq!(zone.nameservers, vec![Ipv4Addr::new(10, 11, 111, 10)]);
+
+    let never_default = split.replace(r#""domains": ["redhat.com"]"#, r#""domains": ["redhat.com", "~."], "never_default": true"#);
+    assert!(get_catch_all_zone(&parse_connections(&never_default).unwrap()).is_none());
+
+    let routing = split.replace(r#""domains": ["redhat.com"]"#, r#""domains": ["redhat.com", "~."]"#);
+    let zone = get_catch_all_zone(&parse_connections(&routing).unwrap()).unwrap();
+    assert_eq!(zone.nameservers, vec![Ipv4Addr::new(10, 11, 111, 10)]);
+}
+
+#[test]
+fn reverse_zones_of_routed_prefixes() {
+    assert_eq!(ipv4_prefix_to_reverse_zones(Ipv4Addr::new(10, 40, 0, 0), 22),
+               vec!["0.40.10.in-addr.arpa", "1.40.10.in-addr.arpa", "2.40.10.in-addr.arpa", "3.40.10.in-addr.arpa"]);
+    assert_eq!(ipv4_prefix_to_reverse_zones(Ipv4Addr::new(66, 187, 233, 0), 24), vec!["233.187.66.in-addr.arpa"]);
+    assert_eq!(ipv4_prefix_to_reverse_zones(Ipv4Addr::new(10, 0, 0, 0), 8), vec!["10.in-addr.arpa"]);
+    assert_eq!(ipv6_prefix_to_reverse_zones("2620:52::".parse().unwrap(), 32), vec!["2.5.0.0.0.2.6.2.ip6.arpa"]);
+    assert_eq!(ipv6_prefix_to_reverse_zones("2001:db8::".parse().unwrap(), 31),
+               vec!["8.b.d.0.1.0.0.2.ip6.arpa", "9.b.d.0.1.0.0.2.ip6.arpa"]);
+
+    let input = r#"[{"id": "Red Hat VPN", "type": "vpn", "default": false, "addresses": ["10.11.111.111/22"], "nameservers": ["10.11.111.10"], "domains": ["redhat.com"], "routes": ["0.0.0.0/0", "66.187.233.0/24", "2620:52::/32"]}]"#;
+    let connections = parse_connections(input).unwrap();
+    let zones: Vec<String> = get_reverse_zones(&connections, RouteZoneLimits::default()).into_iter()
+        .map(|z| z.zone)
+        .collect();
+    assert_eq!(zones, vec!["2.5.0.0.0.2.6.2.ip6.arpa", "233.187.66.in-addr.arpa", "10.in-addr.arpa"]);
+
+    let strict = RouteZoneLimits { ipv4: 28, ipv6: 64 };
+    let zones: Vec<String> = get_reverse_zones(&connections, strict).into_iter().map(|z| z.zone).collect();
+    assert_eq!(zones, vec!["10.in-addr.arpa"]);
+}
+
+#[test]
+fn nested_forward_zones() {
+    // Ethernet has the highest priority, so it owns redhat.com and afk.redhat.com needs no rule
+    let zones: Vec<(String, ConnectionType)> = get_forward_zones(&parse_connections(TESTING_INPUT).unwrap()).into_iter()
+        .map(|z| (z.domain, z.con_type))
+        .collect();
+    assert_eq!(zones, vec![("redhat.com".to_string(), ConnectionType::Ethernet)]);
+
+    let input = TESTING_INPUT.replace(r#""domains": ["afk.redhat.com", "redhat.com"]"#, r#""domains": ["afk.redhat.com"]"#);
+    let connections = parse_connections(&input).unwrap();
+    let zones: Vec<(String, ConnectionType)> = get_forward_zones(&connections).into_iter()
+        .map(|z| (z.domain, z.con_type))
+        .collect();
+    // redhat.com is claimed by Wi-Fi and VPN, the VPN wins. afk.redhat.com stays on Ethernet and
+    // must come first, otherwise the rule for redhat.com would catch it.
+    assert_eq!(zones, vec![("afk.redhat.com".to_string(), ConnectionType::Ethernet),
+                           ("redhat.com".to_string(), ConnectionType::VPN)]);
+    for _ in 0..10 {
+        let again: Vec<String> = get_forward_zones(&connections).into_iter().map(|z| z.domain).collect();
+        assert_eq!(again, vec!["afk.redhat.com", "redhat.com"]);
+    }
+}
+
+#[test]
+fn overlay_wins_its_domains_over_wifi() {
+    let input = r#"[{"id": "Home", "type": "802-11-wireless", "default": true, "addresses": ["192.168.1.10/24"], "nameservers": ["192.168.1.1"], "domains": ["home", "ts.net"]}, {"id": "tailscale0", "type": "tun", "default": false, "addresses": ["100.101.102.103/32"], "nameservers": ["100.100.100.100"], "domains": ["~ts.net"]}, {"id": "Phone", "type": "gsm", "default": false, "addresses": ["10.64.0.2/30"], "nameservers": ["10.64.0.1"], "domains": ["home"]}]"#;
+    let zones: Vec<(String, ConnectionType)> = get_forward_zones(&parse_connections(input).unwrap()).into_iter()
+        .map(|z| (z.domain, z.con_type))
+        .collect();
+    assert_eq!(zones, vec![("ts.net".to_string(), ConnectionType::Tun),
+                           ("home".to_string(), ConnectionType::WiFi)]);
+}
+
+#[test]
+fn local_nameservers_are_dropped() {
+    let input = r#"[{"id": "Home", "type": "802-11-wireless", "default": true, "addresses": ["192.168.1.10/24"], "nameservers": ["127.0.0.1"], "domains": ["home"]}, {"id": "enp0s25", "type": "802-3-ethernet", "default": false, "addresses": ["10.10.0.10/24"], "nameservers": ["127.0.0.53", "10.10.0.99", "127.0.0.2", "192.168.1.10"], "domains": ["redhat.com"]}]"#;
+    let mut connections = parse_connections(input).unwrap();
+    let reports = remove_local_nameservers(&mut connections, Ipv4Addr::new(127, 0, 0, 2));
+    assert_eq!(reports, vec![
+        LoopReport {
+            connection: "Home".to_string(),
+            dropped: vec![(Ipv4Addr::new(127, 0, 0, 1), LocalNameserver::Loopback)],
+            unforwarded: true,
+        },
+        LoopReport {
+            connection: "enp0s25".to_string(),
+            dropped: vec![(Ipv4Addr::new(127, 0, 0, 53), LocalNameserver::Loopback),
+                          (Ipv4Addr::new(127, 0, 0, 2), LocalNameserver::Resolver),
+                          (Ipv4Addr::new(192, 168, 1, 10), LocalNameserver::HostAddress)],
+            unforwarded: false,
+        },
+    ]);
+    assert_eq!(reports[0].to_string(),
+               "Connection Home: 127.0.0.1 is a loopback address; its zones are not forwarded as it has no other nameserver");
+
+    let zones: Vec<(String, Vec<Ipv4Addr>)> = get_forward_zones(&connections).into_iter()
+        .map(|z| (z.domain, z.nameservers))
+        .collect();
+    assert_eq!(zones, vec![("redhat.com".to_string(), vec![Ipv4Addr::new(10, 10, 0, 99)])]);
+}
+
+#[test]
+fn excluded_connections_get_no_zones() {
+    let input = r#"[{"id": "enp0s25", "uuid": "a3b1c2d4-0000-4000-8000-000000000001", "interfaces": ["enp0s25"], "type": "802-3-ethernet", "default": true, "addresses": ["10.10.0.10/24"], "nameservers": ["10.10.0.99"], "domains": ["redhat.com"]}, {"id": "virbr0", "interfaces": ["virbr0"], "type": "bridge", "default": false, "addresses": ["192.168.122.1/24"], "nameservers": ["192.168.122.1"], "domains": ["~."]}, {"id": "podman", "interfaces": ["podman1"], "type": "802-3-ethernet", "default": false, "addresses": ["10.88.0.1/16"], "nameservers": ["10.88.0.1"], "domains": ["dns.podman"]}]"#;
+    let mut connections = parse_connections(input).unwrap();
+    assert_eq!(remove_excluded(&mut connections, &Config::default()), vec!["virbr0", "podman"]);
+    let zones: Vec<String> = get_forward_zones(&connections).into_iter().map(|z| z.domain).collect();
+    assert_eq!(zones, vec!["redhat.com"]);
+    let reverse: Vec<String> = get_reverse_zones(&connections, RouteZoneLimits::default()).into_iter()
+        .map(|z| z.zone)
+        .collect();
+    assert_eq!(reverse, vec!["10.in-addr.arpa"]);
+    assert!(get_catch_all_zone(&connections).is_none());
+
+    // Rules match the NetworkManager type, the id and the UUID as well
+    let config: Config = serde_json::from_str(r#"{"exclude": [{"type": "802-3-ethernet"}], "include": [{"uuid": "a3b1*"}]}"#).unwrap();
+    let mut connections = parse_connections(input).unwrap();
+    assert_eq!(remove_excluded(&mut connections, &config), vec!["podman"]);
+    assert_eq!(get_catch_all_zone(&connections).unwrap().nameservers, vec![Ipv4Addr::new(192, 168, 122, 1)]);
+}
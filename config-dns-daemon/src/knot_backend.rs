@@ -1,101 +1,382 @@
 use connections::{ForwardZone, ReverseZone};
 use failure::Error;
 use regex::Regex;
+use std::io;
 use std::io::prelude::*;
 use std::io::{BufRead, BufReader};
+use std::net::Ipv4Addr;
 use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use zone_tree::longest_suffix_first;
 
-fn read_response_from_socket<T: BufRead>(reader: &mut T) {
+/// Control socket of the kresd@1 instance
+pub const CONTROL_SOCKET: &str = "/run/knot-resolver/control@1";
+
+fn eof_error() -> Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "kresd closed the control socket").into()
+}
+
+fn read_response_from_socket<T: BufRead>(reader: &mut T) -> Result<(), Error> {
     let mut line;
     loop {
         line = String::new();
-        let len = reader.read_line(&mut line).unwrap();
+        let len = reader.read_line(&mut line)?;
+        if len == 0 {
+            return Err(eof_error());
+        }
         print!("line [{}]:{}", len, line);
         if line == "\n" {
             break;
         }
     }
+    Ok(())
 }
 
-pub fn apply_rules(fwd_zones: Vec<ForwardZone>, reverse_zones: Vec<ReverseZone>) -> Result<(), Error> {
-    let mut stream = UnixStream::connect("/run/knot-resolver/control@1")?;
-    for i in &fwd_zones {
-        let policy_rule = format!("policy.add(policy.suffix(policy.STUB('{}'), {{todname('{}')}}))\n", i.nameservers[0], i.domain);
-        print!("{}", policy_rule);
-        stream.write_all(policy_rule.as_bytes())?;
-        let mut reader = BufReader::new(&stream);
-        read_response_from_socket(&mut reader);
+/// What the resolver is supposed to contain
+#[derive(Debug, PartialEq, Eq)]
+enum Applied {
+    /// No update so far, the resolver is left alone
+    Nothing,
+    /// The last update failed or the resolver went away, whatever it has is not what we want
+    Incomplete,
+    /// Ids of the rules listed by the resolver right after the last update
+    Rules(Vec<String>),
+}
+
+/// Remembers the rules applied to the resolver, so that they can be applied again once kresd
+/// restarts and forgets them.
+pub struct KnotBackend {
+    control_socket: PathBuf,
+    fwd_zones: Vec<ForwardZone>,
+    reverse_zones: Vec<ReverseZone>,
+    /// Where the queries not matching any zone go, public DNS-over-TLS resolver if not set
+    catch_all: Option<ForwardZone>,
+    applied: Applied,
+}
+
+impl KnotBackend {
+    pub fn new<P: AsRef<Path>>(control_socket: P) -> Self {
+        KnotBackend {
+            control_socket: control_socket.as_ref().to_path_buf(),
+            fwd_zones: vec![],
+            reverse_zones: vec![],
+            catch_all: None,
+            applied: Applied::Nothing,
+        }
+    }
+
+    /// Replace all rules in the resolver with the new view.
+    pub fn set(&mut self, fwd_zones: Vec<ForwardZone>, reverse_zones: Vec<ReverseZone>, catch_all: Option<ForwardZone>)
+               -> Result<(), Error> {
+        self.fwd_zones = fwd_zones;
+        self.reverse_zones = reverse_zones;
+        self.catch_all = catch_all;
+        self.reapply()
+    }
+
+    /// Make sure the resolver still has exactly the rules from the last update. If the control
+    /// socket is gone, kresd is not running and nothing can be done until it comes back. If any
+    /// rule vanished, was replaced or added, kresd was restarted or somebody else changed it in
+    /// the meantime, so apply the rules again.
+    pub fn check(&mut self) -> Result<(), Error> {
+        if self.applied == Applied::Nothing {
+            return Ok(());
+        }
+        let rules = match UnixStream::connect(&self.control_socket) {
+            Ok(mut stream) => list_rules(&mut stream)?,
+            Err(e) => {
+                // Whatever was applied is lost together with the resolver
+                self.applied = Applied::Incomplete;
+                return Err(e.into());
+            },
+        };
+        if self.applied != Applied::Rules(rules.clone()) {
+            warn!("Resolver has rules {:?} instead of the applied {:?}. Applying them again.", rules, self.applied);
+            self.reapply()?;
+        }
+        Ok(())
+    }
+
+    fn reapply(&mut self) -> Result<(), Error> {
+        // Until the rules are applied successfully, whatever is in the resolver is incomplete
+        self.applied = Applied::Incomplete;
+        remove_all_rules(&self.control_socket)?;
+        apply_rules(&self.control_socket, &self.fwd_zones, &self.reverse_zones, self.catch_all.as_ref())?;
+        let mut stream = UnixStream::connect(&self.control_socket)?;
+        self.applied = Applied::Rules(list_rules(&mut stream)?);
+        Ok(())
     }
-    for i in reverse_zones {
-        let policy_rule = format!("policy.add(policy.suffix(policy.STUB('{}'), {{todname('{}')}}))", i.nameservers[0], i.zone);
+}
+
+/// Policy rules for the resolver in the order they must be added. kresd uses the first matching
+/// rule, so zones are sorted longest suffix first and the catch-all comes last.
+fn policy_rules(fwd_zones: &[ForwardZone], reverse_zones: &[ReverseZone], catch_all: Option<&ForwardZone>)
+                -> Vec<String> {
+    let mut zones: Vec<(&str, Ipv4Addr)> = fwd_zones.iter()
+        .map(|z| (z.domain.as_str(), z.nameservers[0]))
+        .chain(reverse_zones.iter().map(|z| (z.zone.as_str(), z.nameservers[0])))
+        .collect();
+    zones.sort_by(|a, b| longest_suffix_first(a.0, b.0));
+
+    let mut rules: Vec<String> = zones.into_iter()
+        .map(|(zone, nameserver)| format!("policy.add(policy.suffix(policy.STUB('{}'), {{todname('{}')}}))\n", nameserver, zone))
+        .collect();
+    rules.push(match catch_all {
+        // Full-tunnel VPN, nothing may leak outside of it
+        Some(zone) => format!("policy.add(policy.all(policy.STUB('{}')))\n", zone.nameservers[0]),
+        None => "policy.add(policy.all(policy.TLS_FORWARD({{'1.1.1.1', hostname='cloudflare-dns.com', ca_file='/etc/pki/tls/certs/ca-bundle.crt'}})))\n".to_string(),
+    });
+    rules
+}
+
+pub fn apply_rules(control_socket: &Path, fwd_zones: &[ForwardZone], reverse_zones: &[ReverseZone],
+                   catch_all: Option<&ForwardZone>) -> Result<(), Error> {
+    let mut stream = UnixStream::connect(control_socket)?;
+    for policy_rule in policy_rules(fwd_zones, reverse_zones, catch_all) {
         print!("{}", policy_rule);
         stream.write_all(policy_rule.as_bytes())?;
         let mut reader = BufReader::new(&stream);
-        read_response_from_socket(&mut reader);
+        read_response_from_socket(&mut reader)?;
     }
-
-    let policy_rule =
-        format!("policy.add(policy.all(policy.TLS_FORWARD({{{{'1.1.1.1', hostname='cloudflare-dns.com', ca_file='/etc/pki/tls/certs/ca-bundle.crt'}}}})))");
-    print!("{}", policy_rule);
-    stream.write_all(policy_rule.as_bytes())?;
-    let mut reader = BufReader::new(&stream);
-    read_response_from_socket(&mut reader);
-
     Ok(())
 }
 
-fn read_response_from_socket_to_string<T: BufRead>(reader: &mut T) -> String {
+fn read_response_from_socket_to_string<T: BufRead>(reader: &mut T) -> Result<String, Error> {
     let mut line;
     let mut ret = String::new();
     loop {
         line = String::new();
-        let len = reader.read_line(&mut line).unwrap();
+        let len = reader.read_line(&mut line)?;
+        if len == 0 {
+            return Err(eof_error());
+        }
         print!("line [{}]:{}", len, line);
         ret += &line;
         if line == "\n" || line == "> \n" {
             break;
         }
     }
-    ret
+    Ok(ret)
 }
 
-pub fn remove_all_rules() -> Result<(), Error> {
+/// Query ids of all policy rules loaded in the resolver
+fn list_rules(stream: &mut UnixStream) -> Result<Vec<String>, Error> {
+    info!("Running query");
+    let query = "policy.rules\n";
+    stream.write_all(query.as_bytes())?;
+    let mut reader = BufReader::new(&*stream);
+    let ret = read_response_from_socket_to_string(&mut reader)?;
+    info!("Return value: {:?}", ret);
+    let re = Regex::new(r"\[id\] => (\d+)").unwrap();
+    let rules = ret.lines()
+        .filter_map(|l| re.captures(l).map(|c| c.get(1).unwrap().as_str().to_string()))
+        .collect::<Vec<_>>();
+    Ok(rules)
+}
+
+pub fn remove_all_rules(control_socket: &Path) -> Result<(), Error> {
     info!("Running remove all rules");
-    let mut stream = UnixStream::connect("/run/knot-resolver/control@1")?;
+    let mut stream = UnixStream::connect(control_socket)?;
     //let mut stream = UnixStream::connect("control")?;
-    let ret;
-    let rules;
-    {
-        info!("Running query");
-        let query = "policy.rules\n";
-        stream.write_all(query.as_bytes())?;
-        let mut reader = BufReader::new(&stream);
-        ret = read_response_from_socket_to_string(&mut reader);
-        info!("Return value: {:?}", ret);
-        let re = Regex::new(r"\[id\] => (\d+)").unwrap();
-        rules = ret.lines()
-            .filter_map(|l| {
-                if let Some(c) = re.captures(l) {
-                    Some(c.get(1).unwrap().as_str())
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
-    }
+    let rules = list_rules(&mut stream)?;
     println!("Rules: {:?}", rules);
     for i in rules.iter().rev() {
         let policy_rule = format!("policy.del({})\n", i);
         print!("{}", policy_rule);
         stream.write_all(policy_rule.as_bytes())?;
         let mut reader = BufReader::new(&stream);
-        read_response_from_socket(&mut reader);
+        read_response_from_socket(&mut reader)?;
     }
 
     Ok(())
 }
 
+#[test]
+fn rules_longest_suffix_first() {
+    use connections::ConnectionType;
+
+    let zone = |domain: &str, ns: [u8; 4]| ForwardZone {
+        domain: domain.to_string(),
+        nameservers: vec![Ipv4Addr::from(ns)],
+        con_type: ConnectionType::VPN,
+    };
+    let reverse = ReverseZone {
+        zone: "233.187.66.in-addr.arpa".to_string(),
+        nameservers: vec![Ipv4Addr::new(10, 0, 0, 1)],
+        con_type: ConnectionType::VPN,
+    };
+    let rules = policy_rules(&[zone("redhat.com", [10, 0, 0, 1]), zone("afk.redhat.com", [10, 0, 0, 2])],
+                             &[reverse], None);
+    assert_eq!(rules, vec![
+        "policy.add(policy.suffix(policy.STUB('10.0.0.1'), {todname('233.187.66.in-addr.arpa')}))\n".to_string(),
+        "policy.add(policy.suffix(policy.STUB('10.0.0.2'), {todname('afk.redhat.com')}))\n".to_string(),
+        "policy.add(policy.suffix(policy.STUB('10.0.0.1'), {todname('redhat.com')}))\n".to_string(),
+        "policy.add(policy.all(policy.TLS_FORWARD({{'1.1.1.1', hostname='cloudflare-dns.com', ca_file='/etc/pki/tls/certs/ca-bundle.crt'}})))\n".to_string(),
+    ]);
+}
+
 #[test]
 fn run_remove() {
-    remove_all_rules().unwrap();
-}
\ No newline at end of file
+    remove_all_rules(Path::new(CONTROL_SOCKET)).unwrap();
+}
+/// Id of the next rule and the rules with their ids
+#[cfg(test)]
+type FakeRules = ::std::sync::Arc<::std::sync::Mutex<(u32, Vec<(u32, String)>)>>;
+
+/// Just enough of the kresd control socket to add, delete and list policy rules
+#[cfg(test)]
+struct FakeKresd {
+    state: FakeRules,
+}
+
+#[cfg(test)]
+impl FakeKresd {
+    fn start(path: &Path) -> FakeKresd {
+        use std::os::unix::net::UnixListener;
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+
+        let _ = ::std::fs::remove_file(path);
+        let listener = UnixListener::bind(path).unwrap();
+        let state = Arc::new(Mutex::new((1, vec![])));
+        let shared = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let state = shared.clone();
+                let stream = stream.unwrap();
+                thread::spawn(move || FakeKresd::serve(stream, state));
+            }
+        });
+        FakeKresd { state }
+    }
+
+    fn serve(stream: UnixStream, state: FakeRules) {
+        let mut writer = stream.try_clone().unwrap();
+        for line in BufReader::new(stream).lines() {
+            let line = line.unwrap();
+            let mut state = state.lock().unwrap();
+            let response = if line.starts_with("policy.add(") {
+                let id = state.0;
+                state.0 += 1;
+                state.1.push((id, format!("{}\n", line)));
+                "\n".to_string()
+            } else if line.starts_with("policy.del(") {
+                let id: u32 = line.trim_start_matches("policy.del(").trim_end_matches(')').parse().unwrap();
+                state.1.retain(|&(i, _)| i != id);
+                "true\n\n".to_string()
+            } else if line == "policy.rules" {
+                let rules: String = state.1.iter()
+                    .enumerate()
+                    .map(|(n, &(id, _))| format!("[{}] => {{\n  [count] => 0\n  [id] => {}\n}}\n", n + 1, id))
+                    .collect();
+                rules + "\n"
+            } else {
+                "unknown command\n\n".to_string()
+            };
+            if writer.write_all(response.as_bytes()).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn rules(&self) -> Vec<String> {
+        self.state.lock().unwrap().1.iter().map(|(_, rule)| rule.clone()).collect()
+    }
+
+    /// Somebody else replaces the first rule, the number of rules stays the same
+    fn replace_first(&self, rule: &str) {
+        let mut state = self.state.lock().unwrap();
+        let id = state.0;
+        state.0 += 1;
+        state.1[0] = (id, rule.to_string());
+    }
+
+    /// kresd restarted and lost all rules
+    fn restart(&self) {
+        *self.state.lock().unwrap() = (1, vec![]);
+    }
+}
+
+#[cfg(test)]
+fn test_zones() -> (Vec<ForwardZone>, Vec<ReverseZone>) {
+    use connections::ConnectionType;
+
+    let fwd = vec![ForwardZone {
+        domain: "redhat.com".to_string(),
+        nameservers: vec![Ipv4Addr::new(10, 0, 0, 1)],
+        con_type: ConnectionType::VPN,
+    }];
+    let reverse = vec![ReverseZone {
+        zone: "10.in-addr.arpa".to_string(),
+        nameservers: vec![Ipv4Addr::new(10, 0, 0, 1)],
+        con_type: ConnectionType::VPN,
+    }];
+    (fwd, reverse)
+}
+
+#[cfg(test)]
+fn test_socket(name: &str) -> PathBuf {
+    ::std::env::temp_dir().join(format!("config-dns-daemon-{}-{}", ::std::process::id(), name))
+}
+
+#[test]
+fn check_reapplies_changed_rules() {
+    let path = test_socket("changed");
+    let kresd = FakeKresd::start(&path);
+    let (fwd, reverse) = test_zones();
+    let expected = policy_rules(&fwd, &reverse, None);
+    let mut backend = KnotBackend::new(&path);
+    backend.set(fwd, reverse, None).unwrap();
+    assert_eq!(kresd.rules(), expected);
+    let applied = Applied::Rules(vec!["1".to_string(), "2".to_string(), "3".to_string()]);
+    assert_eq!(backend.applied, applied);
+
+    // Nothing changed, nothing to do
+    backend.check().unwrap();
+    assert_eq!(backend.applied, applied);
+
+    // Same number of rules, but one of them is not ours
+    kresd.replace_first("policy.add(policy.all(policy.DENY))\n");
+    backend.check().unwrap();
+    assert_eq!(kresd.rules(), expected);
+    assert_eq!(backend.applied, Applied::Rules(vec!["5".to_string(), "6".to_string(), "7".to_string()]));
+    let _ = ::std::fs::remove_file(&path);
+}
+
+#[test]
+fn check_reapplies_rules_after_restart() {
+    let path = test_socket("restart");
+    let kresd = FakeKresd::start(&path);
+    let (fwd, reverse) = test_zones();
+    let expected = policy_rules(&fwd, &reverse, None);
+    let mut backend = KnotBackend::new(&path);
+    backend.set(fwd, reverse, None).unwrap();
+
+    kresd.restart();
+    assert!(kresd.rules().is_empty());
+    backend.check().unwrap();
+    assert_eq!(kresd.rules(), expected);
+    let _ = ::std::fs::remove_file(&path);
+}
+
+#[test]
+fn check_waits_for_the_resolver() {
+    let path = test_socket("missing");
+    let _ = ::std::fs::remove_file(&path);
+    let (fwd, reverse) = test_zones();
+    let expected = policy_rules(&fwd, &reverse, None);
+    let mut backend = KnotBackend::new(&path);
+    // Nothing was applied yet, so there is nothing to check
+    backend.check().unwrap();
+
+    assert!(backend.set(fwd, reverse, None).is_err());
+    assert_eq!(backend.applied, Applied::Incomplete);
+    assert!(backend.check().is_err());
+
+    // kresd comes up
+    let kresd = FakeKresd::start(&path);
+    backend.check().unwrap();
+    assert_eq!(kresd.rules(), expected);
+    assert_eq!(backend.applied, Applied::Rules(vec!["1".to_string(), "2".to_string(), "3".to_string()]));
+    let _ = ::std::fs::remove_file(&path);
+}
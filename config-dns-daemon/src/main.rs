@@ -2,6 +2,7 @@ extern crate docopt;
 extern crate env_logger;
 extern crate failure;
 #[macro_use] extern crate log;
+extern crate nm_connection;
 extern crate regex;
 #[macro_use] extern crate serde_derive;
 extern crate serde_json;
@@ -13,14 +14,27 @@ use log::LevelFilter;
 
 use std::io::prelude::*;
 use std::io::BufReader;
+use std::net::Ipv4Addr;
 use std::os::unix::net::{UnixStream, UnixListener};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use nm_connection::Config;
 
 mod connections;
 mod knot_backend;
+mod zone_tree;
 
 use connections::*;
 use knot_backend::*;
 
+/// How often to check that the resolver did not lose the rules (e.g. because it was restarted)
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Address kresd listens on, see README of config-dns
+const RESOLVER_ADDRESS: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 2);
+
 #[cfg(test)]
 const BINARY_NAME: &'static str = "config-dns-daemon";
 const USAGE: &'static str = "
@@ -28,7 +42,7 @@ Daemon for automatic DNS resolver configuration
 triggered by changes in network setup.
 
 Usage:
-  config-dns-daemon [--verbosity=<level> | --socket=<path>]
+  config-dns-daemon [--verbosity=<level> | --socket=<path>] [--config=<path>] [--resolv-conf=<path>] [--route-prefix-v4=<len>] [--route-prefix-v6=<len>]
   config-dns-daemon (-h | --help)
   config-dns-daemon (-v | --version)
 
@@ -36,12 +50,20 @@ Options:
   -h, --help            Show this screen.
   --socket=<path>      Path to the Unix domain socket used for IPC with control script
   --verbosity=<level>  Level of verbosity (TODO range).
+  --config=<path>       Read connection exclusion and inclusion rules from a JSON file
+  --resolv-conf=<path>  Write nameserver and search list into this file (e.g. /etc/resolv.conf)
+  --route-prefix-v4=<len>  Shortest routed IPv4 prefix to create reverse zones for [default: 8]
+  --route-prefix-v6=<len>  Shortest routed IPv6 prefix to create reverse zones for [default: 32]
   -v, --version         Show version.
 ";
 
 #[derive(Debug, Deserialize)]
 struct Args {
+    flag_config: Option<String>,
     flag_help: bool,
+    flag_resolv_conf: Option<String>,
+    flag_route_prefix_v4: u8,
+    flag_route_prefix_v6: u8,
     flag_socket: Option<String>,
     flag_verbosity: Option<String>,
     flag_version: bool,
@@ -57,7 +79,35 @@ fn handle_control_connection(stream: UnixStream) -> Result<String, Error> {
     Ok(line)
 }
 
-fn run_control_socket(socket_path: &str) -> Result<(), Error> {
+fn run_watchdog(backend: Arc<Mutex<KnotBackend>>) {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(CHECK_INTERVAL);
+            if let Err(e) = backend.lock().unwrap().check() {
+                warn!("Resolver check failed: {}", e);
+            }
+        }
+    });
+}
+
+/// resolv.conf pointing the system to the local resolver, with the search list
+fn resolv_conf(search_domains: &[Domain]) -> String {
+    let mut content = format!("# Generated by config-dns-daemon\nnameserver {}\n", RESOLVER_ADDRESS);
+    if !search_domains.is_empty() {
+        content += &format!("search {}\n", search_domains.join(" "));
+    }
+    content
+}
+
+fn write_resolv_conf(path: &str, search_domains: &[Domain]) -> Result<(), Error> {
+    std::fs::write(path, resolv_conf(search_domains))?;
+    Ok(())
+}
+
+fn run_control_socket(socket_path: &str, config: &Config, resolv_conf: Option<&str>, limits: RouteZoneLimits) -> Result<(), Error> {
+    let backend = Arc::new(Mutex::new(KnotBackend::new(CONTROL_SOCKET)));
+    run_watchdog(backend.clone());
+
     info!("Removing socket at path: {}", socket_path);
     let _ = std::fs::remove_file(socket_path);
     info!("Starting socket at path: {}", socket_path);
@@ -69,15 +119,27 @@ fn run_control_socket(socket_path: &str) -> Result<(), Error> {
                 info!("Connection established! Reading until the end of line.");
                 match handle_control_connection(stream) {
                     Ok(line) => {
-                        if let Ok(connections) = parse_connections(&line) {
+                        if let Ok(mut connections) = parse_connections(&line) {
+                            for id in remove_excluded(&mut connections, config) {
+                                info!("Ignoring connection {}", id);
+                            }
+                            for report in remove_local_nameservers(&mut connections, RESOLVER_ADDRESS) {
+                                warn!("{}", report);
+                            }
                             let fwd_zones = get_forward_zones(&connections);
-                            let reverse_zones = get_reverse_zones(&connections);
+                            let reverse_zones = get_reverse_zones(&connections, limits);
+                            let search_domains = get_search_domains(&connections);
+                            let catch_all = get_catch_all_zone(&connections);
                             info!("Forward zones: {:?}", fwd_zones);
                             info!("Reverse zones: {:?}", reverse_zones);
-                            if let Err(e) = remove_all_rules() {
-                                error!("Failed to remove old rules from the resolver: {}", e);
+                            info!("Catch-all zone: {:?}", catch_all);
+                            info!("Search domains: {:?}", search_domains);
+                            if let Some(path) = resolv_conf {
+                                if let Err(e) = write_resolv_conf(path, &search_domains) {
+                                    error!("Failed to write {}: {}", path, e);
+                                }
                             }
-                            if let Err(e) = apply_rules(fwd_zones, reverse_zones) {
+                            if let Err(e) = backend.lock().unwrap().set(fwd_zones, reverse_zones, catch_all) {
                                 error!("Failed to apply forwarding rules to the resolver: {}", e);
                             }
                         } else {
@@ -109,7 +171,21 @@ fn main() {
         .unwrap_or_else(|e| e.exit());
 
     let socket_path = &(args.flag_socket).unwrap_or("./socket".to_string());
-    if let Err(e) = run_control_socket(socket_path) {
+    let limits = RouteZoneLimits {
+        ipv4: args.flag_route_prefix_v4,
+        ipv6: args.flag_route_prefix_v6,
+    };
+    let config = match args.flag_config {
+        Some(ref path) => match Config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Failed to read configuration {}: {}", path, e);
+                return;
+            },
+        },
+        None => Config::default(),
+    };
+    if let Err(e) = run_control_socket(socket_path, &config, args.flag_resolv_conf.as_deref(), limits) {
         error!("Failed with {}", e);
     }
 }
@@ -139,3 +215,12 @@ fn parse_verbosity_arg() {
         .and_then(|d| d.argv(argv().into_iter()).deserialize())
         .unwrap();
 }
+
+#[test]
+fn search_list_in_resolv_conf() {
+    let input = r#"[{"id": "Red Hat VPN", "type": "vpn", "default": false, "addresses": ["10.11.111.111/22"], "nameservers": ["10.11.111.10"], "domains": ["redhat.com", "~corp.redhat.com"]}, {"id": "Home", "type": "802-11-wireless", "default": true, "addresses": ["192.168.1.10/24"], "nameservers": ["192.168.1.1"], "domains": ["home"]}]"#;
+    let connections = parse_connections(input).unwrap();
+    assert_eq!(resolv_conf(&get_search_domains(&connections)),
+               "# Generated by config-dns-daemon\nnameserver 127.0.0.2\nsearch redhat.com home\n");
+    assert_eq!(resolv_conf(&[]), "# Generated by config-dns-daemon\nnameserver 127.0.0.2\n");
+}
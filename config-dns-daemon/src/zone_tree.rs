@@ -0,0 +1,151 @@
+//! Ownership of DNS zones.
+//!
+//! Zones nest (`afk.redhat.com` is inside `redhat.com`) and the resolver has to send each query to
+//! the owner of the longest zone containing the name. The tree makes the nesting explicit instead
+//! of relying on the order in which rules happen to be added to the resolver.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// Labels of a domain name starting from the top level one, the root zone has none
+fn labels(domain: &str) -> Vec<String> {
+    domain.trim_end_matches('.')
+        .split('.')
+        .filter(|l| !l.is_empty())
+        .rev()
+        .map(|l| l.to_lowercase())
+        .collect()
+}
+
+/// Order of resolver rules: zones with more labels first, so that the most specific zone matches
+/// before its parents. Ties are broken by name to keep the output the same for the same input.
+pub fn longest_suffix_first(a: &str, b: &str) -> Ordering {
+    labels(b).len().cmp(&labels(a).len()).then_with(|| a.cmp(b))
+}
+
+#[derive(Debug)]
+struct Node<T> {
+    owner: Option<T>,
+    children: BTreeMap<String, Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new() -> Self {
+        Node {
+            owner: None,
+            children: BTreeMap::new(),
+        }
+    }
+
+    fn collect<'a>(&'a self, name: &mut Vec<&'a str>, parent: Option<&'a T>, out: &mut Vec<OwnedZone<'a, T>>) {
+        let parent = match self.owner {
+            Some(ref owner) => {
+                let zone = if name.is_empty() {
+                    ".".to_string()
+                } else {
+                    name.iter().rev().cloned().collect::<Vec<_>>().join(".")
+                };
+                out.push(OwnedZone { zone, owner, parent });
+                Some(owner)
+            },
+            None => parent,
+        };
+        for (label, child) in &self.children {
+            name.push(label);
+            child.collect(name, parent, out);
+            name.pop();
+        }
+    }
+}
+
+/// Zone with an owner as returned by `ZoneTree::zones`
+#[derive(Debug)]
+pub struct OwnedZone<'a, T: 'a> {
+    /// Normalized zone name without the trailing dot, `.` for the root zone
+    pub zone: String,
+    pub owner: &'a T,
+    /// Owner of the closest enclosing zone
+    pub parent: Option<&'a T>,
+}
+
+/// Suffix trie of domain labels
+#[derive(Debug)]
+pub struct ZoneTree<T> {
+    root: Node<T>,
+}
+
+impl<T> ZoneTree<T> {
+    pub fn new() -> Self {
+        ZoneTree {
+            root: Node::new(),
+        }
+    }
+
+    /// Set the owner of a zone. If the zone is already owned, `prefer_new(existing, new)` decides
+    /// which one keeps it.
+    pub fn insert<F>(&mut self, domain: &str, owner: T, prefer_new: F)
+        where F: FnOnce(&T, &T) -> bool
+    {
+        let mut node = &mut self.root;
+        for label in labels(domain) {
+            node = node.children.entry(label).or_insert_with(Node::new);
+        }
+        if node.owner.as_ref().is_none_or(|existing| prefer_new(existing, &owner)) {
+            node.owner = Some(owner);
+        }
+    }
+
+    /// All owned zones, longest suffix first
+    pub fn zones(&self) -> Vec<OwnedZone<'_, T>> {
+        let mut zones = vec![];
+        self.root.collect(&mut vec![], None, &mut zones);
+        zones.sort_by(|a, b| longest_suffix_first(&a.zone, &b.zone));
+        zones
+    }
+}
+
+#[test]
+fn zones_are_normalized() {
+    let mut tree = ZoneTree::new();
+    tree.insert("redhat.com", "vpn", |_, _| false);
+    tree.insert("AFK.RedHat.com.", "ethernet", |_, _| false);
+    // Same zone as the first one, the existing owner stays
+    tree.insert("RedHat.com.", "wifi", |_, _| false);
+    let zones: Vec<(String, &str, Option<&str>)> = tree.zones().into_iter()
+        .map(|z| (z.zone, *z.owner, z.parent.cloned()))
+        .collect();
+    assert_eq!(zones, vec![
+        ("afk.redhat.com".to_string(), "ethernet", Some("vpn")),
+        ("redhat.com".to_string(), "vpn", None),
+    ]);
+
+    // The root zone comes last and encloses everything else
+    tree.insert(".", "public", |_, _| false);
+    let zones: Vec<(String, Option<&str>)> = tree.zones().into_iter()
+        .map(|z| (z.zone, z.parent.cloned()))
+        .collect();
+    assert_eq!(zones, vec![
+        ("afk.redhat.com".to_string(), Some("vpn")),
+        ("redhat.com".to_string(), Some("public")),
+        (".".to_string(), None),
+    ]);
+}
+
+#[test]
+fn zones_are_ordered_and_know_their_parent() {
+    let mut tree = ZoneTree::new();
+    for (zone, owner) in &[("redhat.com", 1), ("b.example", 2), ("afk.redhat.com", 3), ("a.example", 4), ("x.afk.redhat.com", 5)] {
+        tree.insert(zone, *owner, |_, _| false);
+    }
+    tree.insert("redhat.com", 6, |old, new| new < old);
+    let zones: Vec<(String, i32, Option<i32>)> = tree.zones().into_iter()
+        .map(|z| (z.zone, *z.owner, z.parent.cloned()))
+        .collect();
+    assert_eq!(zones, vec![
+        ("x.afk.redhat.com".to_string(), 5, Some(3)),
+        ("afk.redhat.com".to_string(), 3, Some(1)),
+        ("a.example".to_string(), 4, None),
+        ("b.example".to_string(), 2, None),
+        ("redhat.com".to_string(), 1, None),
+    ]);
+}
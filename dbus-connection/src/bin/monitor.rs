@@ -1,8 +1,8 @@
 extern crate dbus;
+extern crate dbus_connection;
 
-use dbus::{BusType, Connection, arg};
-
-use std::collections::HashMap;
+use dbus::{BusType, Connection};
+use dbus_connection::nm::{self, PropertyMap};
 
 #[derive(PartialEq, Eq)]
 enum MonitorState {
@@ -34,16 +34,12 @@ fn main() {
         if let Some(msg) = c.incoming(state.next_timeout()).next() {
             state = MonitorState::Watching;
             println!("{:?}", msg);
-            let get: Option<HashMap<String, arg::Variant<Box<arg::RefArg + 'static>>>> = msg.get1();
+            let get: Option<PropertyMap> = msg.get1();
             println!("{:?}", get);
-            if let Some(hashmap) = get {
-                for (k, _) in hashmap {
-                    if k == "ActiveConnections" {
-                        println!("Active connections changed");
-                        println!("Debouncing");
-                        state = MonitorState::Debouncing;
-                    }
-                }
+            if let Some(paths) = get.as_ref().and_then(|h| h.get("ActiveConnections")).and_then(nm::object_paths) {
+                println!("Active connections changed: {:?}", paths);
+                println!("Debouncing");
+                state = MonitorState::Debouncing;
             }
         } else {
             if state == MonitorState::Debouncing {
@@ -0,0 +1,541 @@
+//! Scripted NetworkManager for tests.
+//!
+//! `TestBus` runs a private `dbus-daemon`, `FakeNetworkManager` owns the
+//! `org.freedesktop.NetworkManager` name on it and exposes active connections together with their
+//! IP4Config, IP6Config and DHCP4Config objects and profiles. Every scripted change emits `PropertiesChanged` the same way
+//! NetworkManager does, so monitors can be tested without touching the system bus.
+
+use dbus::{self, arg, Connection, Message, NameFlag, Path};
+use dbus::tree::{Factory, Interface, MTFn, Property, Tree};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use nm::{NM_BUS_NAME, NM_INTERFACE, NM_PATH};
+
+type Fn = MTFn<()>;
+
+/// How long the service waits for incoming method calls before it looks at the script again
+const POLL_TIMEOUT: u32 = 10;
+
+fn other_error<E: ToString>(e: E) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+/// Private instance of dbus-daemon which is killed when dropped
+pub struct TestBus {
+    daemon: Child,
+    address: String,
+}
+
+impl TestBus {
+    pub fn start() -> io::Result<TestBus> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut address = String::new();
+        if let Some(stdout) = daemon.stdout.as_mut() {
+            BufReader::new(stdout).read_line(&mut address)?;
+        }
+        let address = address.trim().to_string();
+        if address.is_empty() {
+            let _ = daemon.kill();
+            return Err(other_error("dbus-daemon did not print its address"));
+        }
+        Ok(TestBus {
+            daemon,
+            address,
+        })
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Open a new connection to the bus
+    pub fn connect(&self) -> Result<Connection, dbus::Error> {
+        connect(&self.address)
+    }
+}
+
+impl Drop for TestBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+fn connect(address: &str) -> Result<Connection, dbus::Error> {
+    let c = Connection::open_private(address)?;
+    c.register()?;
+    Ok(c)
+}
+
+/// Content of the IP4Config object of a fake connection
+#[derive(Debug, Clone, Default)]
+pub struct FakeIp4Config {
+    pub addresses: Vec<(Ipv4Addr, u8)>,
+    /// Destinations of routes
+    pub routes: Vec<(Ipv4Addr, u8)>,
+    pub nameservers: Vec<Ipv4Addr>,
+    pub domains: Vec<String>,
+    pub searches: Vec<String>,
+}
+
+/// Content of the IP6Config object of a fake connection
+#[derive(Debug, Clone, Default)]
+pub struct FakeIp6Config {
+    pub addresses: Vec<(Ipv6Addr, u8)>,
+    /// Destinations of routes
+    pub routes: Vec<(Ipv6Addr, u8)>,
+    pub nameservers: Vec<Ipv6Addr>,
+    pub domains: Vec<String>,
+    pub searches: Vec<String>,
+}
+
+/// Active connection as exposed by the fake NetworkManager
+#[derive(Debug, Clone, Default)]
+pub struct FakeConnection {
+    pub id: String,
+    /// Connection type as reported by NetworkManager, e.g. `802-11-wireless` or `vpn`
+    pub con_type: String,
+    pub default: bool,
+    pub vpn: bool,
+    /// `never-default` of both IP families of the profile
+    pub never_default: bool,
+    /// Name of the interface of the connection's device, no device is exposed if empty
+    pub interface: String,
+    /// The IP family is disabled in the profile and has no config object if `None`
+    pub ip4: Option<FakeIp4Config>,
+    pub ip6: Option<FakeIp6Config>,
+    /// Options of the DHCPv4 lease, no DHCP4Config object is exposed if empty
+    pub dhcp4_options: Vec<(String, String)>,
+}
+
+enum Script {
+    Activate(FakeConnection),
+    Deactivate(String),
+    SetIp4Config(String, FakeIp4Config),
+    ReplaceIp4Config(String, FakeIp4Config),
+    RenewDhcp4(String, FakeIp4Config, Vec<(String, String)>),
+}
+
+/// Handle to the fake NetworkManager service running in its own thread
+pub struct FakeNetworkManager {
+    script: Option<Sender<Script>>,
+    service: Option<JoinHandle<()>>,
+}
+
+impl FakeNetworkManager {
+    /// Connect to the bus at the given address, acquire the NetworkManager name and start serving.
+    pub fn start(address: &str) -> io::Result<FakeNetworkManager> {
+        Self::start_with(address, vec![])
+    }
+
+    /// Like `start`, but the connections are already active when the name is acquired, e.g.
+    /// NetworkManager being restarted while connected.
+    pub fn start_with(address: &str, active: Vec<FakeConnection>) -> io::Result<FakeNetworkManager> {
+        let (script, script_rx) = channel();
+        let (ready, ready_rx) = channel();
+        let address = address.to_string();
+        let service = thread::spawn(move || {
+            let service = Service::new(&address, active);
+            let _ = ready.send(service.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+            if let Ok(service) = service {
+                service.run(script_rx);
+            }
+        });
+        ready_rx.recv().map_err(other_error)?.map_err(other_error)?;
+        Ok(FakeNetworkManager {
+            script: Some(script),
+            service: Some(service),
+        })
+    }
+
+    fn send(&self, s: Script) {
+        if let Some(script) = self.script.as_ref() {
+            script.send(s).expect("Fake NetworkManager service died");
+        }
+    }
+
+    /// Add a new active connection, e.g. Wi-Fi getting connected or VPN going up
+    pub fn activate(&self, con: FakeConnection) {
+        self.send(Script::Activate(con));
+    }
+
+    /// Remove the active connection with the given id
+    pub fn deactivate(&self, id: &str) {
+        self.send(Script::Deactivate(id.to_string()));
+    }
+
+    /// Change the IP4Config of an active connection without touching the list of active
+    /// connections, e.g. DHCP renewal
+    pub fn set_ip4_config(&self, id: &str, ip4: FakeIp4Config) {
+        self.send(Script::SetIp4Config(id.to_string(), ip4));
+    }
+
+    /// Replace the IP4Config of an active connection by a new object at a new path, e.g. the
+    /// connection being reapplied. The other config objects move along. Only the properties of
+    /// the active connection that point to them are announced, the new objects never are.
+    pub fn replace_ip4_config(&self, id: &str, ip4: FakeIp4Config) {
+        self.send(Script::ReplaceIp4Config(id.to_string(), ip4));
+    }
+
+    /// Renew the DHCPv4 lease of an active connection. The IP4Config changes silently, only the
+    /// `Options` of the DHCP4Config object are announced, so a monitor has to follow that object
+    /// to notice the new configuration.
+    pub fn renew_dhcp4(&self, id: &str, ip4: FakeIp4Config, options: Vec<(String, String)>) {
+        self.send(Script::RenewDhcp4(id.to_string(), ip4, options));
+    }
+}
+
+impl Drop for FakeNetworkManager {
+    fn drop(&mut self) {
+        // Closing the channel stops the service
+        self.script.take();
+        if let Some(service) = self.service.take() {
+            let _ = service.join();
+        }
+    }
+}
+
+fn active_path(n: u32) -> Path<'static> {
+    Path::from(format!("{}/ActiveConnection/{}", NM_PATH, n))
+}
+
+fn ip4_path(n: u32) -> Path<'static> {
+    Path::from(format!("{}/IP4Config/{}", NM_PATH, n))
+}
+
+fn ip6_path(n: u32) -> Path<'static> {
+    Path::from(format!("{}/IP6Config/{}", NM_PATH, n))
+}
+
+fn dhcp4_path(n: u32) -> Path<'static> {
+    Path::from(format!("{}/DHCP4Config/{}", NM_PATH, n))
+}
+
+fn settings_path(n: u32) -> Path<'static> {
+    Path::from(format!("{}/Settings/{}", NM_PATH, n))
+}
+
+fn device_path(n: u32) -> Path<'static> {
+    Path::from(format!("{}/Devices/{}", NM_PATH, n))
+}
+
+/// Read-only property with a fixed value. The tree is rebuilt after every change, so the values
+/// never go stale.
+fn constant<T>(f: &Factory<Fn, ()>, name: &str, value: T) -> Property<Fn, ()>
+    where T: arg::Arg + arg::Append + Clone + 'static
+{
+    f.property::<T, _>(name, ())
+        .on_get(move |a, _| {
+            a.append(value.clone());
+            Ok(())
+        })
+}
+
+/// `AddressData` or `RouteData`, `key` is the name of the address field
+fn address_data<A: ToString>(key: &'static str, addresses: &[(A, u8)])
+                -> Vec<HashMap<&'static str, arg::Variant<Box<dyn arg::RefArg>>>> {
+    addresses.iter()
+        .map(|&(ref address, prefix)| {
+            let mut entry: HashMap<&'static str, arg::Variant<Box<dyn arg::RefArg>>> = HashMap::new();
+            entry.insert(key, arg::Variant(Box::new(address.to_string())));
+            entry.insert("prefix", arg::Variant(Box::new(prefix as u32)));
+            entry
+        })
+        .collect()
+}
+
+/// Path of a config object, or `/` if the object doesn't exist
+fn config_path(exists: bool, path: Path<'static>) -> Path<'static> {
+    if exists {
+        path
+    } else {
+        Path::from("/")
+    }
+}
+
+/// `c` numbers the config objects, it differs from `n` once they have been replaced
+fn active_interface(f: &Factory<Fn, ()>, n: u32, c: u32, con: &FakeConnection) -> Interface<Fn, ()> {
+    f.interface("org.freedesktop.NetworkManager.Connection.Active", ())
+        .add_p(constant(f, "Connection", settings_path(n)))
+        .add_p(constant(f, "Id", con.id.clone()))
+        .add_p(constant(f, "Uuid", format!("00000000-0000-0000-0000-{:012}", n)))
+        .add_p(constant(f, "Type", con.con_type.clone()))
+        .add_p(constant(f, "Devices", if con.interface.is_empty() { vec![] } else { vec![device_path(n)] }))
+        // NM_ACTIVE_CONNECTION_STATE_ACTIVATED
+        .add_p(constant(f, "State", 2u32))
+        .add_p(constant(f, "Default", con.default))
+        .add_p(constant(f, "Default6", false))
+        .add_p(constant(f, "Vpn", con.vpn))
+        .add_p(constant(f, "Ip4Config", config_path(con.ip4.is_some(), ip4_path(c))))
+        .add_p(constant(f, "Dhcp4Config", config_path(!con.dhcp4_options.is_empty(), dhcp4_path(c))))
+        .add_p(constant(f, "Ip6Config", config_path(con.ip6.is_some(), ip6_path(c))))
+        .add_p(constant(f, "Dhcp6Config", Path::from("/")))
+}
+
+/// Settings.Connection with just the parts of the profile the monitors read
+fn settings_interface(f: &Factory<Fn, ()>, con: &FakeConnection) -> Interface<Fn, ()> {
+    let never_default = con.never_default;
+    let method = |enabled: bool| if enabled { "auto" } else { "disabled" };
+    let (ipv4, ipv6) = (method(con.ip4.is_some()), method(con.ip6.is_some()));
+    f.interface("org.freedesktop.NetworkManager.Settings.Connection", ())
+        .add_m(f.method("GetSettings", (), move |m| {
+            let setting = |method: &str, never_default: bool| {
+                let mut s: HashMap<&str, arg::Variant<Box<dyn arg::RefArg>>> = HashMap::new();
+                s.insert("method", arg::Variant(Box::new(method.to_string())));
+                s.insert("never-default", arg::Variant(Box::new(never_default)));
+                s
+            };
+            let mut settings = HashMap::new();
+            settings.insert("ipv4", setting(ipv4, never_default));
+            settings.insert("ipv6", setting(ipv6, never_default));
+            Ok(vec![m.msg.method_return().append1(settings)])
+        }).outarg::<HashMap<&str, HashMap<&str, arg::Variant<u32>>>, _>("settings"))
+}
+
+fn device_interface(f: &Factory<Fn, ()>, con: &FakeConnection) -> Interface<Fn, ()> {
+    f.interface("org.freedesktop.NetworkManager.Device", ())
+        .add_p(constant(f, "Interface", con.interface.clone()))
+        .add_p(constant(f, "IpInterface", con.interface.clone()))
+        // NM_DEVICE_TYPE_UNKNOWN, nobody looks at it so far
+        .add_p(constant(f, "DeviceType", 0u32))
+}
+
+fn ip4_interface(f: &Factory<Fn, ()>, ip4: &FakeIp4Config) -> Interface<Fn, ()> {
+    let addresses = ip4.addresses.clone();
+    let routes = ip4.routes.clone();
+    let nameservers: Vec<u32> = ip4.nameservers.iter()
+        .map(|a| u32::from_ne_bytes(a.octets()))
+        .collect();
+    f.interface("org.freedesktop.NetworkManager.IP4Config", ())
+        .add_p(f.property::<Vec<HashMap<&str, arg::Variant<u32>>>, _>("AddressData", ())
+            .on_get(move |a, _| {
+                a.append(address_data("address", &addresses));
+                Ok(())
+            }))
+        .add_p(f.property::<Vec<HashMap<&str, arg::Variant<u32>>>, _>("RouteData", ())
+            .on_get(move |a, _| {
+                a.append(address_data("dest", &routes));
+                Ok(())
+            }))
+        .add_p(constant(f, "Nameservers", nameservers))
+        .add_p(constant(f, "Domains", ip4.domains.clone()))
+        .add_p(constant(f, "Searches", ip4.searches.clone()))
+}
+
+fn ip6_interface(f: &Factory<Fn, ()>, ip6: &FakeIp6Config) -> Interface<Fn, ()> {
+    let addresses = ip6.addresses.clone();
+    let routes = ip6.routes.clone();
+    let nameservers: Vec<Vec<u8>> = ip6.nameservers.iter()
+        .map(|a| a.octets().to_vec())
+        .collect();
+    f.interface("org.freedesktop.NetworkManager.IP6Config", ())
+        .add_p(f.property::<Vec<HashMap<&str, arg::Variant<u32>>>, _>("AddressData", ())
+            .on_get(move |a, _| {
+                a.append(address_data("address", &addresses));
+                Ok(())
+            }))
+        .add_p(f.property::<Vec<HashMap<&str, arg::Variant<u32>>>, _>("RouteData", ())
+            .on_get(move |a, _| {
+                a.append(address_data("dest", &routes));
+                Ok(())
+            }))
+        .add_p(constant(f, "Nameservers", nameservers))
+        .add_p(constant(f, "Domains", ip6.domains.clone()))
+        .add_p(constant(f, "Searches", ip6.searches.clone()))
+}
+
+/// `Options` of the lease as `a{sv}` with string values, the way NetworkManager exposes them
+fn dhcp4_options(options: &[(String, String)]) -> HashMap<String, arg::Variant<String>> {
+    options.iter()
+        .map(|(k, v)| (k.clone(), arg::Variant(v.clone())))
+        .collect()
+}
+
+fn dhcp4_interface(f: &Factory<Fn, ()>, options: &[(String, String)]) -> Interface<Fn, ()> {
+    let options = options.to_vec();
+    f.interface("org.freedesktop.NetworkManager.DHCP4Config", ())
+        .add_p(f.property::<HashMap<&str, arg::Variant<u32>>, _>("Options", ())
+            .on_get(move |a, _| {
+                a.append(dhcp4_options(&options));
+                Ok(())
+            }))
+}
+
+struct Service {
+    conn: Connection,
+    factory: Factory<Fn, ()>,
+    tree: Tree<Fn, ()>,
+    active: Vec<(u32, FakeConnection)>,
+    /// Number of the config objects of an active connection if they have been replaced
+    configs: HashMap<u32, u32>,
+    next: u32,
+}
+
+impl Service {
+    fn new(address: &str, active: Vec<FakeConnection>) -> Result<Service, dbus::Error> {
+        let conn = connect(address)?;
+        let factory = Factory::new_fn::<()>();
+        let tree = factory.tree(());
+        let mut service = Service {
+            conn,
+            factory,
+            tree,
+            next: active.len() as u32 + 1,
+            active: (1..).zip(active).collect(),
+            configs: HashMap::new(),
+        };
+        // The objects must be there by the time anybody sees the name
+        service.rebuild();
+        service.conn.register_name(NM_BUS_NAME, NameFlag::ReplaceExisting as u32)?;
+        Ok(service)
+    }
+
+    fn rebuild(&mut self) {
+        let f = &self.factory;
+        let paths: Vec<Path<'static>> = self.active.iter().map(|&(n, _)| active_path(n)).collect();
+        let primary = self.active.iter()
+            .find(|(_, con)| con.default)
+            .map_or_else(|| Path::from("/"), |&(n, _)| active_path(n));
+        let manager = f.interface(NM_INTERFACE, ())
+            .add_p(constant(f, "ActiveConnections", paths))
+            .add_p(constant(f, "PrimaryConnection", primary));
+
+        let mut tree = f.tree(()).add(f.object_path(NM_PATH, ()).introspectable().add(manager));
+        for &(n, ref con) in &self.active {
+            let c = self.config(n);
+            tree = tree.add(f.object_path(active_path(n), ()).introspectable().add(active_interface(f, n, c, con)));
+            if let Some(ip4) = con.ip4.as_ref() {
+                tree = tree.add(f.object_path(ip4_path(c), ()).introspectable().add(ip4_interface(f, ip4)));
+            }
+            if let Some(ip6) = con.ip6.as_ref() {
+                tree = tree.add(f.object_path(ip6_path(c), ()).introspectable().add(ip6_interface(f, ip6)));
+            }
+            if !con.dhcp4_options.is_empty() {
+                tree = tree.add(f.object_path(dhcp4_path(c), ()).introspectable()
+                    .add(dhcp4_interface(f, &con.dhcp4_options)));
+            }
+            tree = tree.add(f.object_path(settings_path(n), ()).introspectable().add(settings_interface(f, con)));
+            if !con.interface.is_empty() {
+                tree = tree.add(f.object_path(device_path(n), ()).introspectable().add(device_interface(f, con)));
+            }
+        }
+
+        let _ = self.tree.set_registered(&self.conn, false);
+        tree.set_registered(&self.conn, true).expect("Failed to register fake NetworkManager objects");
+        self.tree = tree;
+    }
+
+    /// Emit the standard org.freedesktop.DBus.Properties.PropertiesChanged signal
+    fn emit_changed(&self, path: Path<'static>, interface: &str, changed: HashMap<&str, arg::Variant<Box<dyn arg::RefArg>>>) {
+        let msg = Message::signal(&path, &"org.freedesktop.DBus.Properties".into(), &"PropertiesChanged".into())
+            .append3(interface, changed, Vec::<String>::new());
+        let _ = self.conn.send(msg);
+    }
+
+    fn emit_active_connections(&self) {
+        let paths: Vec<Path<'static>> = self.active.iter().map(|&(n, _)| active_path(n)).collect();
+        let mut changed: HashMap<&str, arg::Variant<Box<dyn arg::RefArg>>> = HashMap::new();
+        changed.insert("ActiveConnections", arg::Variant(Box::new(paths)));
+        self.emit_changed(Path::from(NM_PATH), NM_INTERFACE, changed);
+    }
+
+    fn config(&self, n: u32) -> u32 {
+        self.configs.get(&n).cloned().unwrap_or(n)
+    }
+
+    fn find(&mut self, id: &str) -> Option<&mut (u32, FakeConnection)> {
+        self.active.iter_mut().find(|&&mut (_, ref con)| con.id == id)
+    }
+
+    fn apply(&mut self, script: Script) {
+        match script {
+            Script::Activate(con) => {
+                self.active.push((self.next, con));
+                self.next += 1;
+                self.rebuild();
+                self.emit_active_connections();
+            },
+            Script::Deactivate(id) => {
+                self.active.retain(|(_, con)| con.id != id);
+                self.rebuild();
+                self.emit_active_connections();
+            },
+            Script::SetIp4Config(id, ip4) => {
+                let n = match self.find(&id) {
+                    Some(&mut (n, ref mut con)) => {
+                        con.ip4 = Some(ip4.clone());
+                        n
+                    },
+                    None => return,
+                };
+                self.rebuild();
+                let nameservers: Vec<u32> = ip4.nameservers.iter()
+                    .map(|a| u32::from_ne_bytes(a.octets()))
+                    .collect();
+                let mut changed: HashMap<&str, arg::Variant<Box<dyn arg::RefArg>>> = HashMap::new();
+                changed.insert("Nameservers", arg::Variant(Box::new(nameservers)));
+                changed.insert("Domains", arg::Variant(Box::new(ip4.domains)));
+                changed.insert("Searches", arg::Variant(Box::new(ip4.searches)));
+                self.emit_changed(ip4_path(self.config(n)), "org.freedesktop.NetworkManager.IP4Config", changed);
+            },
+            Script::ReplaceIp4Config(id, ip4) => {
+                let (n, ip6, dhcp4) = match self.find(&id) {
+                    Some(&mut (n, ref mut con)) => {
+                        con.ip4 = Some(ip4);
+                        (n, con.ip6.is_some(), !con.dhcp4_options.is_empty())
+                    },
+                    None => return,
+                };
+                let c = self.next;
+                self.next += 1;
+                self.configs.insert(n, c);
+                self.rebuild();
+                // All config objects of the connection move to new paths
+                let mut changed: HashMap<&str, arg::Variant<Box<dyn arg::RefArg>>> = HashMap::new();
+                changed.insert("Ip4Config", arg::Variant(Box::new(ip4_path(c))));
+                changed.insert("Ip6Config", arg::Variant(Box::new(config_path(ip6, ip6_path(c)))));
+                changed.insert("Dhcp4Config", arg::Variant(Box::new(config_path(dhcp4, dhcp4_path(c)))));
+                self.emit_changed(active_path(n), "org.freedesktop.NetworkManager.Connection.Active", changed);
+            },
+            Script::RenewDhcp4(id, ip4, options) => {
+                let n = match self.find(&id) {
+                    Some(&mut (n, ref mut con)) => {
+                        con.ip4 = Some(ip4);
+                        con.dhcp4_options = options.clone();
+                        n
+                    },
+                    None => return,
+                };
+                self.rebuild();
+                let mut changed: HashMap<&str, arg::Variant<Box<dyn arg::RefArg>>> = HashMap::new();
+                changed.insert("Options", arg::Variant(Box::new(dhcp4_options(&options))));
+                self.emit_changed(dhcp4_path(self.config(n)), "org.freedesktop.NetworkManager.DHCP4Config", changed);
+            },
+        }
+    }
+
+    fn run(mut self, script: Receiver<Script>) {
+        loop {
+            match script.try_recv() {
+                Ok(s) => self.apply(s),
+                Err(TryRecvError::Empty) => {},
+                Err(TryRecvError::Disconnected) => return,
+            }
+            if let Some(msg) = self.conn.incoming(POLL_TIMEOUT).next() {
+                if let Some(replies) = self.tree.handle(&msg) {
+                    for reply in replies {
+                        let _ = self.conn.send(reply);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//! D-Bus proxies for NetworkManager shared by the binaries in this repository.
+//!
+//! `nmdbus` and `nmdbus_objects` contain the raw proxies in the dbus-codegen-rust style, `nm` puts
+//! a typed layer on top of them.
+
+extern crate dbus;
+
+// Generated code, don't bother with warnings
+#[allow(deprecated, bare_trait_objects, clippy::all)]
+pub mod nmdbus;
+#[allow(clippy::type_complexity)]
+pub mod nmdbus_objects;
+pub mod nm;
+#[cfg(feature = "fake-nm")]
+pub mod fake_nm;
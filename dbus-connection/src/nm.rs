@@ -0,0 +1,335 @@
+//! Typed view of the NetworkManager objects relevant for DNS configuration.
+//!
+//! NetworkManager exposes a lot of its data in weakly typed containers (`aa{sv}` dictionaries,
+//! IPv4 addresses as `u32` in network byte order, `/` as a null object path). All of the decoding
+//! happens here, so that the binaries only ever see Rust types.
+
+use dbus::{self, arg, Path};
+use dbus::arg::RefArg;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use nmdbus_objects::OrgFreedesktopNetworkManager as NetworkManagerProxy;
+use nmdbus_objects::OrgFreedesktopNetworkManagerConnectionActive as ActiveProxy;
+use nmdbus_objects::OrgFreedesktopNetworkManagerDevice as DeviceProxy;
+use nmdbus_objects::OrgFreedesktopNetworkManagerIP4Config as Ip4Proxy;
+use nmdbus_objects::OrgFreedesktopNetworkManagerIP6Config as Ip6Proxy;
+use nmdbus_objects::OrgFreedesktopNetworkManagerSettingsConnection as SettingsProxy;
+
+pub const NM_BUS_NAME: &str = "org.freedesktop.NetworkManager";
+pub const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+pub const NM_INTERFACE: &str = "org.freedesktop.NetworkManager";
+
+/// Timeout of D-Bus method calls in milliseconds
+const TIMEOUT: i32 = 5000;
+
+pub type PropertyMap = HashMap<String, arg::Variant<Box<dyn arg::RefArg + 'static>>>;
+
+/// Settings of a connection profile, `a{sa{sv}}` keyed by the setting name (`ipv4`, `ipv6`, ...)
+pub type SettingsMap = HashMap<String, PropertyMap>;
+
+/// One entry of the `AddressData` property
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressData {
+    pub address: IpAddr,
+    pub prefix: u8,
+}
+
+/// One entry of the `RouteData` property
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteData {
+    pub dest: IpAddr,
+    pub prefix: u8,
+    pub next_hop: Option<IpAddr>,
+    pub metric: Option<u32>,
+}
+
+/// One entry of the `NameserverData` property
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameserverData {
+    pub address: IpAddr,
+}
+
+/// org.freedesktop.NetworkManager.Connection.Active
+#[derive(Debug, Clone)]
+pub struct ActiveConnection {
+    pub path: Path<'static>,
+    /// Settings.Connection object of the profile the connection was activated from
+    pub connection: Option<Path<'static>>,
+    pub id: String,
+    pub uuid: String,
+    pub con_type: String,
+    pub default: bool,
+    pub default6: bool,
+    pub vpn: bool,
+    pub devices: Vec<Path<'static>>,
+    pub ip4_config: Option<Path<'static>>,
+    pub ip6_config: Option<Path<'static>>,
+    pub dhcp4_config: Option<Path<'static>>,
+}
+
+/// org.freedesktop.NetworkManager.Settings.Connection, only the settings relevant for DNS
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionSettings {
+    pub never_default: bool,
+}
+
+/// org.freedesktop.NetworkManager.IP4Config
+#[derive(Debug, Clone, Default)]
+pub struct Ip4Config {
+    pub addresses: Vec<AddressData>,
+    pub routes: Vec<RouteData>,
+    pub nameservers: Vec<Ipv4Addr>,
+    pub domains: Vec<String>,
+    pub searches: Vec<String>,
+}
+
+/// org.freedesktop.NetworkManager.IP6Config
+#[derive(Debug, Clone, Default)]
+pub struct Ip6Config {
+    pub addresses: Vec<AddressData>,
+    pub routes: Vec<RouteData>,
+    pub nameservers: Vec<Ipv6Addr>,
+    pub domains: Vec<String>,
+    pub searches: Vec<String>,
+}
+
+/// org.freedesktop.NetworkManager.Device
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub path: Path<'static>,
+    pub interface: String,
+    pub ip_interface: String,
+    pub device_type: u32,
+}
+
+/// NetworkManager stores IPv4 addresses as `u32` in network byte order
+pub fn ipv4_from_nm(i: u32) -> Ipv4Addr {
+    Ipv4Addr::from(u32::from_be(i))
+}
+
+/// IPv6 addresses are arrays of 16 bytes in network byte order
+pub fn ipv6_from_nm(bytes: &[u8]) -> Option<Ipv6Addr> {
+    if bytes.len() != 16 {
+        return None;
+    }
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(bytes);
+    Some(Ipv6Addr::from(octets))
+}
+
+/// NetworkManager uses `/` for object paths that are not set
+pub fn optional_path(path: Path<'static>) -> Option<Path<'static>> {
+    if &*path == "/" {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+/// Decode a list of object paths wrapped in a variant, e.g. `ActiveConnections` from a
+/// PropertiesChanged signal.
+pub fn object_paths(value: &arg::Variant<Box<dyn arg::RefArg + 'static>>) -> Option<Vec<Path<'static>>> {
+    value.0.as_iter().map(|items| {
+        items.filter_map(|item| item.as_str())
+            .filter_map(|s| Path::new(s.to_string()).ok())
+            .collect()
+    })
+}
+
+fn get_ip(entry: &PropertyMap, key: &str) -> Option<IpAddr> {
+    entry.get(key)?.0.as_str()?.parse().ok()
+}
+
+fn get_u32(entry: &PropertyMap, key: &str) -> Option<u32> {
+    entry.get(key)?.0.as_u64().map(|i| i as u32)
+}
+
+fn get_bool(entry: &PropertyMap, key: &str) -> Option<bool> {
+    entry.get(key)?.0.as_any().downcast_ref::<bool>().cloned()
+}
+
+/// `ipv4.never-default` and `ipv6.never-default` of a profile. The connection never gets the
+/// default route if none of its enabled IP families may get it.
+pub fn never_default(settings: &SettingsMap) -> bool {
+    let enabled: Vec<&PropertyMap> = ["ipv4", "ipv6"].iter()
+        .filter_map(|family| settings.get(*family))
+        .filter(|s| !matches!(s.get("method").and_then(|m| m.0.as_str()), Some("disabled") | Some("ignore")))
+        .collect();
+    !enabled.is_empty() && enabled.iter().all(|s| get_bool(s, "never-default") == Some(true))
+}
+
+/// Decode the `AddressData` property. Entries which can't be decoded are skipped.
+pub fn address_data(data: &[PropertyMap]) -> Vec<AddressData> {
+    data.iter()
+        .filter_map(|entry| {
+            Some(AddressData {
+                address: get_ip(entry, "address")?,
+                prefix: get_u32(entry, "prefix")? as u8,
+            })
+        })
+        .collect()
+}
+
+/// Decode the `RouteData` property. Entries which can't be decoded are skipped.
+pub fn route_data(data: &[PropertyMap]) -> Vec<RouteData> {
+    data.iter()
+        .filter_map(|entry| {
+            Some(RouteData {
+                dest: get_ip(entry, "dest")?,
+                prefix: get_u32(entry, "prefix")? as u8,
+                next_hop: get_ip(entry, "next-hop"),
+                metric: get_u32(entry, "metric"),
+            })
+        })
+        .collect()
+}
+
+/// Decode the `NameserverData` property. Entries which can't be decoded are skipped.
+pub fn nameserver_data(data: &[PropertyMap]) -> Vec<NameserverData> {
+    data.iter()
+        .filter_map(|entry| Some(NameserverData { address: get_ip(entry, "address")? }))
+        .collect()
+}
+
+/// Query paths of all active connections
+pub fn active_connections(conn: &dbus::Connection) -> Result<Vec<Path<'static>>, dbus::Error> {
+    NetworkManagerProxy::get_active_connections(&conn.with_path(NM_BUS_NAME, NM_PATH, TIMEOUT))
+}
+
+impl ActiveConnection {
+    pub fn query(conn: &dbus::Connection, path: Path<'static>) -> Result<Self, dbus::Error> {
+        let p = conn.with_path(NM_BUS_NAME, path.clone(), TIMEOUT);
+        Ok(ActiveConnection {
+            connection: optional_path(ActiveProxy::get_connection(&p)?),
+            id: ActiveProxy::get_id(&p)?,
+            uuid: ActiveProxy::get_uuid(&p)?,
+            con_type: ActiveProxy::get_type(&p)?,
+            default: ActiveProxy::get_default(&p)?,
+            default6: ActiveProxy::get_default6(&p)?,
+            vpn: ActiveProxy::get_vpn(&p)?,
+            devices: ActiveProxy::get_devices(&p)?,
+            ip4_config: optional_path(ActiveProxy::get_ip4_config(&p)?),
+            ip6_config: optional_path(ActiveProxy::get_ip6_config(&p)?),
+            dhcp4_config: optional_path(ActiveProxy::get_dhcp4_config(&p)?),
+            path,
+        })
+    }
+}
+
+impl ConnectionSettings {
+    pub fn query(conn: &dbus::Connection, path: Path<'static>) -> Result<Self, dbus::Error> {
+        let p = conn.with_path(NM_BUS_NAME, path, TIMEOUT);
+        Ok(Self::from_settings(&SettingsProxy::get_settings(&p)?))
+    }
+
+    /// Pick the relevant settings out of the reply of `GetSettings`
+    pub fn from_settings(settings: &SettingsMap) -> Self {
+        ConnectionSettings {
+            never_default: never_default(settings),
+        }
+    }
+}
+
+impl Ip4Config {
+    pub fn query(conn: &dbus::Connection, path: Path<'static>) -> Result<Self, dbus::Error> {
+        let p = conn.with_path(NM_BUS_NAME, path, TIMEOUT);
+        // Older versions of NetworkManager don't have NameserverData
+        let nameservers = match Ip4Proxy::get_nameserver_data(&p) {
+            Ok(data) => nameserver_data(&data).into_iter()
+                .filter_map(|n| match n.address {
+                    IpAddr::V4(a) => Some(a),
+                    IpAddr::V6(_) => None,
+                })
+                .collect(),
+            Err(_) => Ip4Proxy::get_nameservers(&p)?.into_iter().map(ipv4_from_nm).collect(),
+        };
+        Ok(Ip4Config {
+            addresses: address_data(&Ip4Proxy::get_address_data(&p)?),
+            routes: route_data(&Ip4Proxy::get_route_data(&p)?),
+            nameservers,
+            domains: Ip4Proxy::get_domains(&p)?,
+            searches: Ip4Proxy::get_searches(&p)?,
+        })
+    }
+}
+
+impl Ip6Config {
+    pub fn query(conn: &dbus::Connection, path: Path<'static>) -> Result<Self, dbus::Error> {
+        let p = conn.with_path(NM_BUS_NAME, path, TIMEOUT);
+        Ok(Ip6Config {
+            addresses: address_data(&Ip6Proxy::get_address_data(&p)?),
+            routes: route_data(&Ip6Proxy::get_route_data(&p)?),
+            nameservers: Ip6Proxy::get_nameservers(&p)?.iter().filter_map(|n| ipv6_from_nm(n)).collect(),
+            domains: Ip6Proxy::get_domains(&p)?,
+            searches: Ip6Proxy::get_searches(&p)?,
+        })
+    }
+}
+
+impl Device {
+    pub fn query(conn: &dbus::Connection, path: Path<'static>) -> Result<Self, dbus::Error> {
+        let p = conn.with_path(NM_BUS_NAME, path.clone(), TIMEOUT);
+        Ok(Device {
+            interface: DeviceProxy::get_interface(&p)?,
+            ip_interface: DeviceProxy::get_ip_interface(&p)?,
+            device_type: DeviceProxy::get_device_type(&p)?,
+            path,
+        })
+    }
+}
+
+#[test]
+fn test_ipv4_from_nm() {
+    // 10.20.30.40 as stored by NetworkManager in memory
+    let input = u32::from_ne_bytes([10, 20, 30, 40]);
+    assert_eq!(ipv4_from_nm(input), Ipv4Addr::new(10, 20, 30, 40));
+}
+
+#[test]
+fn test_address_data() {
+    let mut entry: PropertyMap = HashMap::new();
+    entry.insert("address".to_string(), arg::Variant(Box::new("10.40.0.6".to_string())));
+    entry.insert("prefix".to_string(), arg::Variant(Box::new(22u32)));
+    let mut broken: PropertyMap = HashMap::new();
+    broken.insert("address".to_string(), arg::Variant(Box::new("not an address".to_string())));
+    broken.insert("prefix".to_string(), arg::Variant(Box::new(22u32)));
+
+    assert_eq!(address_data(&[entry, broken]),
+               vec![AddressData { address: "10.40.0.6".parse().unwrap(), prefix: 22 }]);
+}
+
+#[test]
+fn test_never_default() {
+    use dbus::Message;
+
+    type Setting = HashMap<&'static str, arg::Variant<Box<dyn arg::RefArg>>>;
+    let setting = |method: &str, never_default: Option<bool>| {
+        let mut s: Setting = HashMap::new();
+        s.insert("method", arg::Variant(Box::new(method.to_string())));
+        if let Some(n) = never_default {
+            s.insert("never-default", arg::Variant(Box::new(n)));
+        }
+        s
+    };
+    // Settings go through a message as `a{sa{sv}}`, the same way GetSettings returns them
+    let profile = |ipv4: Setting, ipv6: Setting| {
+        let mut settings: HashMap<&str, Setting> = HashMap::new();
+        settings.insert("connection", setting("ignored", None));
+        settings.insert("ipv4", ipv4);
+        settings.insert("ipv6", ipv6);
+        let msg = Message::signal(&"/org/freedesktop/NetworkManager/Settings/1".into(),
+                                  &"org.freedesktop.NetworkManager.Settings.Connection".into(),
+                                  &"Settings".into())
+            .append1(settings);
+        ConnectionSettings::from_settings(&msg.get1::<SettingsMap>().unwrap()).never_default
+    };
+
+    assert!(profile(setting("auto", Some(true)), setting("auto", Some(true))));
+    assert!(profile(setting("auto", Some(true)), setting("ignore", None)));
+    // IPv6 may still get the default route
+    assert!(!profile(setting("auto", Some(true)), setting("auto", None)));
+    assert!(!profile(setting("auto", Some(true)), setting("auto", Some(false))));
+    assert!(!profile(setting("manual", Some(false)), setting("disabled", None)));
+    assert!(!ConnectionSettings::from_settings(&HashMap::new()).never_default);
+}
@@ -0,0 +1,277 @@
+// Client side proxies for the NetworkManager objects that carry DNS configuration. They follow the
+// output of dbus-codegen-rust (see nmdbus.rs), but only cover what we actually read.
+
+#![allow(dead_code)]
+use dbus as dbus;
+use dbus::arg;
+
+pub trait OrgFreedesktopNetworkManager {
+    type Err;
+    fn get_active_connections(&self) -> Result<Vec<dbus::Path<'static>>, Self::Err>;
+    fn get_primary_connection(&self) -> Result<dbus::Path<'static>, Self::Err>;
+}
+
+impl<'a, C: ::std::ops::Deref<Target=dbus::Connection>> OrgFreedesktopNetworkManager for dbus::ConnPath<'a, C> {
+    type Err = dbus::Error;
+
+    fn get_active_connections(&self) -> Result<Vec<dbus::Path<'static>>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager", "ActiveConnections")
+    }
+
+    fn get_primary_connection(&self) -> Result<dbus::Path<'static>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager", "PrimaryConnection")
+    }
+}
+
+pub trait OrgFreedesktopNetworkManagerConnectionActive {
+    type Err;
+    fn get_connection(&self) -> Result<dbus::Path<'static>, Self::Err>;
+    fn get_id(&self) -> Result<String, Self::Err>;
+    fn get_uuid(&self) -> Result<String, Self::Err>;
+    fn get_type(&self) -> Result<String, Self::Err>;
+    fn get_devices(&self) -> Result<Vec<dbus::Path<'static>>, Self::Err>;
+    fn get_state(&self) -> Result<u32, Self::Err>;
+    fn get_default(&self) -> Result<bool, Self::Err>;
+    fn get_ip4_config(&self) -> Result<dbus::Path<'static>, Self::Err>;
+    fn get_dhcp4_config(&self) -> Result<dbus::Path<'static>, Self::Err>;
+    fn get_default6(&self) -> Result<bool, Self::Err>;
+    fn get_ip6_config(&self) -> Result<dbus::Path<'static>, Self::Err>;
+    fn get_dhcp6_config(&self) -> Result<dbus::Path<'static>, Self::Err>;
+    fn get_vpn(&self) -> Result<bool, Self::Err>;
+}
+
+impl<'a, C: ::std::ops::Deref<Target=dbus::Connection>> OrgFreedesktopNetworkManagerConnectionActive for dbus::ConnPath<'a, C> {
+    type Err = dbus::Error;
+
+    fn get_connection(&self) -> Result<dbus::Path<'static>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "Connection")
+    }
+
+    fn get_id(&self) -> Result<String, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "Id")
+    }
+
+    fn get_uuid(&self) -> Result<String, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "Uuid")
+    }
+
+    fn get_type(&self) -> Result<String, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "Type")
+    }
+
+    fn get_devices(&self) -> Result<Vec<dbus::Path<'static>>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "Devices")
+    }
+
+    fn get_state(&self) -> Result<u32, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "State")
+    }
+
+    fn get_default(&self) -> Result<bool, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "Default")
+    }
+
+    fn get_ip4_config(&self) -> Result<dbus::Path<'static>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "Ip4Config")
+    }
+
+    fn get_dhcp4_config(&self) -> Result<dbus::Path<'static>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "Dhcp4Config")
+    }
+
+    fn get_default6(&self) -> Result<bool, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "Default6")
+    }
+
+    fn get_ip6_config(&self) -> Result<dbus::Path<'static>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "Ip6Config")
+    }
+
+    fn get_dhcp6_config(&self) -> Result<dbus::Path<'static>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "Dhcp6Config")
+    }
+
+    fn get_vpn(&self) -> Result<bool, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Connection.Active", "Vpn")
+    }
+}
+
+pub trait OrgFreedesktopNetworkManagerIP4Config {
+    type Err;
+    fn get_addresses(&self) -> Result<Vec<Vec<u32>>, Self::Err>;
+    fn get_address_data(&self) -> Result<Vec<::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>>, Self::Err>;
+    fn get_gateway(&self) -> Result<String, Self::Err>;
+    fn get_route_data(&self) -> Result<Vec<::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>>, Self::Err>;
+    fn get_nameservers(&self) -> Result<Vec<u32>, Self::Err>;
+    fn get_nameserver_data(&self) -> Result<Vec<::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>>, Self::Err>;
+    fn get_domains(&self) -> Result<Vec<String>, Self::Err>;
+    fn get_searches(&self) -> Result<Vec<String>, Self::Err>;
+    fn get_dns_options(&self) -> Result<Vec<String>, Self::Err>;
+    fn get_dns_priority(&self) -> Result<i32, Self::Err>;
+}
+
+impl<'a, C: ::std::ops::Deref<Target=dbus::Connection>> OrgFreedesktopNetworkManagerIP4Config for dbus::ConnPath<'a, C> {
+    type Err = dbus::Error;
+
+    fn get_addresses(&self) -> Result<Vec<Vec<u32>>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP4Config", "Addresses")
+    }
+
+    fn get_address_data(&self) -> Result<Vec<::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP4Config", "AddressData")
+    }
+
+    fn get_gateway(&self) -> Result<String, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP4Config", "Gateway")
+    }
+
+    fn get_route_data(&self) -> Result<Vec<::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP4Config", "RouteData")
+    }
+
+    fn get_nameservers(&self) -> Result<Vec<u32>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP4Config", "Nameservers")
+    }
+
+    fn get_nameserver_data(&self) -> Result<Vec<::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP4Config", "NameserverData")
+    }
+
+    fn get_domains(&self) -> Result<Vec<String>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP4Config", "Domains")
+    }
+
+    fn get_searches(&self) -> Result<Vec<String>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP4Config", "Searches")
+    }
+
+    fn get_dns_options(&self) -> Result<Vec<String>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP4Config", "DnsOptions")
+    }
+
+    fn get_dns_priority(&self) -> Result<i32, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP4Config", "DnsPriority")
+    }
+}
+
+pub trait OrgFreedesktopNetworkManagerIP6Config {
+    type Err;
+    fn get_address_data(&self) -> Result<Vec<::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>>, Self::Err>;
+    fn get_gateway(&self) -> Result<String, Self::Err>;
+    fn get_route_data(&self) -> Result<Vec<::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>>, Self::Err>;
+    fn get_nameservers(&self) -> Result<Vec<Vec<u8>>, Self::Err>;
+    fn get_domains(&self) -> Result<Vec<String>, Self::Err>;
+    fn get_searches(&self) -> Result<Vec<String>, Self::Err>;
+    fn get_dns_options(&self) -> Result<Vec<String>, Self::Err>;
+    fn get_dns_priority(&self) -> Result<i32, Self::Err>;
+}
+
+impl<'a, C: ::std::ops::Deref<Target=dbus::Connection>> OrgFreedesktopNetworkManagerIP6Config for dbus::ConnPath<'a, C> {
+    type Err = dbus::Error;
+
+    fn get_address_data(&self) -> Result<Vec<::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP6Config", "AddressData")
+    }
+
+    fn get_gateway(&self) -> Result<String, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP6Config", "Gateway")
+    }
+
+    fn get_route_data(&self) -> Result<Vec<::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP6Config", "RouteData")
+    }
+
+    fn get_nameservers(&self) -> Result<Vec<Vec<u8>>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP6Config", "Nameservers")
+    }
+
+    fn get_domains(&self) -> Result<Vec<String>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP6Config", "Domains")
+    }
+
+    fn get_searches(&self) -> Result<Vec<String>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP6Config", "Searches")
+    }
+
+    fn get_dns_options(&self) -> Result<Vec<String>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP6Config", "DnsOptions")
+    }
+
+    fn get_dns_priority(&self) -> Result<i32, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.IP6Config", "DnsPriority")
+    }
+}
+
+pub trait OrgFreedesktopNetworkManagerDHCP4Config {
+    type Err;
+    fn get_options(&self) -> Result<::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>, Self::Err>;
+}
+
+impl<'a, C: ::std::ops::Deref<Target=dbus::Connection>> OrgFreedesktopNetworkManagerDHCP4Config for dbus::ConnPath<'a, C> {
+    type Err = dbus::Error;
+
+    fn get_options(&self) -> Result<::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.DHCP4Config", "Options")
+    }
+}
+
+pub trait OrgFreedesktopNetworkManagerDevice {
+    type Err;
+    fn get_interface(&self) -> Result<String, Self::Err>;
+    fn get_ip_interface(&self) -> Result<String, Self::Err>;
+    fn get_device_type(&self) -> Result<u32, Self::Err>;
+    fn get_managed(&self) -> Result<bool, Self::Err>;
+    fn get_ip4_config(&self) -> Result<dbus::Path<'static>, Self::Err>;
+    fn get_ip6_config(&self) -> Result<dbus::Path<'static>, Self::Err>;
+    fn get_active_connection(&self) -> Result<dbus::Path<'static>, Self::Err>;
+}
+
+impl<'a, C: ::std::ops::Deref<Target=dbus::Connection>> OrgFreedesktopNetworkManagerDevice for dbus::ConnPath<'a, C> {
+    type Err = dbus::Error;
+
+    fn get_interface(&self) -> Result<String, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Device", "Interface")
+    }
+
+    fn get_ip_interface(&self) -> Result<String, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Device", "IpInterface")
+    }
+
+    fn get_device_type(&self) -> Result<u32, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Device", "DeviceType")
+    }
+
+    fn get_managed(&self) -> Result<bool, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Device", "Managed")
+    }
+
+    fn get_ip4_config(&self) -> Result<dbus::Path<'static>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Device", "Ip4Config")
+    }
+
+    fn get_ip6_config(&self) -> Result<dbus::Path<'static>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Device", "Ip6Config")
+    }
+
+    fn get_active_connection(&self) -> Result<dbus::Path<'static>, Self::Err> {
+        <Self as dbus::stdintf::org_freedesktop_dbus::Properties>::get(self, "org.freedesktop.NetworkManager.Device", "ActiveConnection")
+    }
+}
+
+pub trait OrgFreedesktopNetworkManagerSettingsConnection {
+    type Err;
+    fn get_settings(&self) -> Result<::std::collections::HashMap<String, ::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>>, Self::Err>;
+}
+
+impl<'a, C: ::std::ops::Deref<Target=dbus::Connection>> OrgFreedesktopNetworkManagerSettingsConnection for dbus::ConnPath<'a, C> {
+    type Err = dbus::Error;
+
+    fn get_settings(&self) -> Result<::std::collections::HashMap<String, ::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>>, Self::Err> {
+        let mut m = self.method_call_with_args(&"org.freedesktop.NetworkManager.Settings.Connection".into(), &"GetSettings".into(), |_| {
+        })?;
+        m.as_result()?;
+        let mut i = m.iter_init();
+        let settings: ::std::collections::HashMap<String, ::std::collections::HashMap<String, arg::Variant<Box<dyn arg::RefArg>>>> = i.read()?;
+        Ok(settings)
+    }
+}
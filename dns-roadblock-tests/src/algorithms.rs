@@ -0,0 +1,199 @@
+//! Support of the individual DNSSEC signing algorithms.
+//!
+//! A resolver may validate RSA/SHA-256 and still fail on zones signed with a newer algorithm.
+//! [RFC 6840, section 5.2](https://tools.ietf.org/html/rfc6840#section-5.2) requires zones
+//! signed only with algorithms a validator doesn't implement to be treated as insecure, old
+//! resolvers turn them into SERVFAIL instead. For every algorithm the probe asks for the apex of
+//! a correctly signed zone and of a zone with bad signatures, `alg-<number>` and
+//! `alg-<number>-bogus` below the algorithm testing zone.
+
+use trust_dns::op::{Message, ResponseCode};
+use trust_dns::rr::{Name, RecordType};
+use trust_dns::rr::dnssec::Algorithm;
+
+use trust_dns_proto::DnsHandle;
+
+use futures::future;
+use futures::prelude::*;
+
+use signed_records::{dnssec_query, rrsigs};
+use super::TestResult;
+
+/// The algorithms probed: the deprecated RSA/SHA-1, then the recommended ones of
+/// [RFC 8624](https://tools.ietf.org/html/rfc8624#section-3.1)
+pub const ALGORITHMS: [Algorithm; 5] = [Algorithm::RSASHA1, Algorithm::RSASHA256, Algorithm::ECDSAP256SHA256,
+                                        Algorithm::ECDSAP384SHA384, Algorithm::ED25519];
+
+/// How a resolver handles zones signed with one algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlgorithmSupport {
+    /// Correct signatures get the AD bit, bad ones SERVFAIL
+    Validates,
+    /// Answers come back with their signatures, but unvalidated, which is fine for an algorithm
+    /// the resolver doesn't implement
+    PassesThrough,
+    /// Zones signed with the algorithm don't work through the resolver
+    Breaks(&'static str),
+}
+
+impl AlgorithmSupport {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlgorithmSupport::Validates => "validates",
+            AlgorithmSupport::PassesThrough => "passes-through",
+            AlgorithmSupport::Breaks(_) => "breaks",
+        }
+    }
+}
+
+/// Zone signed with the algorithm below the algorithm testing zone
+pub fn algorithm_zone(parent: &Name, algorithm: Algorithm, bogus: bool) -> Name {
+    let label = format!("alg-{}{}", u8::from(algorithm), if bogus { "-bogus" } else { "" });
+    Name::from_labels(vec![label]).append_domain(parent)
+}
+
+/// Support of the algorithm given the answers from the correct and the bogus zone, nothing if the
+/// zones don't exist
+fn algorithm_support(algorithm: Algorithm, valid: &Message, bogus: &Message) -> Option<AlgorithmSupport> {
+    match valid.response_code() {
+        ResponseCode::NoError => {},
+        ResponseCode::ServFail => return Some(AlgorithmSupport::Breaks("Correctly signed zone fails")),
+        _ => return None,
+    }
+    if !rrsigs(valid.answers()).any(|s| s.algorithm() == algorithm && s.type_covered() == RecordType::A) {
+        return Some(AlgorithmSupport::Breaks("Signatures missing"));
+    }
+    match (valid.authentic_data(), bogus.response_code(), bogus.authentic_data()) {
+        (_, ResponseCode::NoError, true) => Some(AlgorithmSupport::Breaks("Bad signatures accepted")),
+        (true, ResponseCode::ServFail, _) => Some(AlgorithmSupport::Validates),
+        (true, ResponseCode::NoError, false) => Some(AlgorithmSupport::Breaks("Bad signatures ignored")),
+        (false, ResponseCode::NoError, false) => Some(AlgorithmSupport::PassesThrough),
+        (false, ResponseCode::ServFail, _) => Some(AlgorithmSupport::Breaks("Correct signatures not validated")),
+        _ => None,
+    }
+}
+
+/// Query the correct and the bogus zone of every algorithm. Algorithms whose zones don't exist or
+/// didn't answer have no support.
+pub fn support_algorithms<DH>(dns_handle: &mut DH, parent: &Name)
+                              -> impl Future<Item=Vec<(Algorithm, Option<AlgorithmSupport>)>, Error=DH::Error>
+    where DH: DnsHandle
+{
+    let queries: Vec<_> = ALGORITHMS.iter()
+        .map(|algorithm| {
+            let mut query = |bogus| {
+                // None of the queries may be dropped before the client gives up on it
+                dns_handle.send(dnssec_query(algorithm_zone(parent, *algorithm, bogus), RecordType::A))
+                    .then(|r| Ok::<_, DH::Error>(r.ok()))
+            };
+            let (valid, bogus) = (query(false), query(true));
+            let algorithm = *algorithm;
+            valid.join(bogus).map(move |responses| match responses {
+                (Some(valid), Some(bogus)) => (algorithm, algorithm_support(algorithm, &valid, &bogus)),
+                _ => (algorithm, None),
+            })
+        })
+        .collect();
+    future::join_all(queries)
+}
+
+/// The probe fails with the first algorithm the resolver breaks
+pub fn algorithms_result(support: &[(Algorithm, Option<AlgorithmSupport>)]) -> TestResult {
+    support.iter()
+        .filter_map(|(_, s)| match s {
+            Some(AlgorithmSupport::Breaks(reason)) => Some(TestResult::Fail(reason)),
+            _ => None,
+        })
+        .next()
+        .unwrap_or(TestResult::Success)
+}
+
+#[test]
+fn algorithm_matrix() {
+    use std::str::FromStr;
+    use trust_dns::rr::{RData, Record};
+    use trust_dns::rr::rdata::{DNSSECRData, DNSSECRecordType, SIG};
+
+    let parent = Name::from_str("algorithms.example.").unwrap();
+    assert_eq!(algorithm_zone(&parent, Algorithm::ED25519, true), Name::from_str("alg-15-bogus.algorithms.example.").unwrap());
+
+    let response = |rcode, authentic, algorithm| {
+        let mut msg = Message::new();
+        msg.set_response_code(rcode).set_authentic_data(authentic);
+        if let Some(algorithm) = algorithm {
+            let sig = SIG::new(RecordType::A, algorithm, 2, 3600, 2, 1, 1234, parent.clone(), vec![]);
+            msg.add_answer(Record::from_rdata(parent.clone(), 3600, RecordType::DNSSEC(DNSSECRecordType::RRSIG),
+                                              RData::DNSSEC(DNSSECRData::SIG(sig))));
+        }
+        msg
+    };
+    let alg = Algorithm::ECDSAP256SHA256;
+    let support = |valid: &Message, bogus: &Message| algorithm_support(alg, valid, bogus);
+    let validated = response(ResponseCode::NoError, true, Some(alg));
+    let unvalidated = response(ResponseCode::NoError, false, Some(alg));
+    let servfail = response(ResponseCode::ServFail, false, None);
+    assert_eq!(support(&validated, &servfail), Some(AlgorithmSupport::Validates));
+    assert_eq!(support(&unvalidated, &unvalidated), Some(AlgorithmSupport::PassesThrough));
+    assert_eq!(support(&servfail, &servfail), Some(AlgorithmSupport::Breaks("Correctly signed zone fails")));
+    assert_eq!(support(&validated, &validated), Some(AlgorithmSupport::Breaks("Bad signatures accepted")));
+    assert_eq!(support(&response(ResponseCode::NoError, true, Some(Algorithm::RSASHA256)), &servfail),
+               Some(AlgorithmSupport::Breaks("Signatures missing")));
+    assert_eq!(support(&response(ResponseCode::NXDomain, false, None), &servfail), None);
+
+    assert_eq!(algorithms_result(&[(alg, Some(AlgorithmSupport::Validates)), (Algorithm::ED25519, None)]),
+               TestResult::Success);
+    assert_eq!(algorithms_result(&[(alg, Some(AlgorithmSupport::PassesThrough)),
+                                   (Algorithm::ED25519, Some(AlgorithmSupport::Breaks("Correctly signed zone fails")))]),
+               TestResult::Fail("Correctly signed zone fails"));
+}
+
+#[test]
+fn algorithm_support_matrix() {
+    use std::time::Duration;
+    use classification::Probe;
+    use fixture::{Behaviour, Server, Zones};
+    use runner::{run_probes, Options, Policy};
+    use self::AlgorithmSupport::{Breaks, PassesThrough, Validates};
+    use super::ALGORITHM_TESTING_ZONE;
+
+    let options = Options {
+        decoy: None,
+        policy: Policy { timeout: Duration::from_millis(500), retries: 0 },
+        probes: vec![Probe::Udp, Probe::Algorithms],
+        ..Default::default()
+    };
+    let matrix_of = |zones, behaviour| -> Vec<Option<AlgorithmSupport>> {
+        let server = Server::resolver(zones, behaviour);
+        let report = run_probes(&server.address().into(), &options).unwrap();
+        assert_eq!(report.algorithms.iter().map(|(a, _)| *a).collect::<Vec<_>>(), ALGORITHMS.to_vec());
+        report.algorithms.into_iter().map(|(_, s)| s).collect()
+    };
+    let matrix = |behaviour| matrix_of(Zones::testing(), behaviour);
+    let all = |support| vec![Some(support); ALGORITHMS.len()];
+    assert_eq!(matrix(Behaviour::validating()), all(Validates));
+    assert_eq!(matrix(Behaviour::default()), all(PassesThrough));
+    assert_eq!(matrix(Behaviour { strip_dnssec: true, ..Default::default() }), all(Breaks("Signatures missing")));
+    assert_eq!(matrix(Behaviour { trust_signatures: true, ..Behaviour::validating() }), all(Breaks("Bad signatures accepted")));
+
+    // Only the answers from the broken zone change, the validator finds out by checking them
+    let broken_zones = || {
+        let mut zones = Zones::testing();
+        let apex = algorithm_zone(&ALGORITHM_TESTING_ZONE, Algorithm::ECDSAP256SHA256, false);
+        zones.0.iter_mut().find(|z| *z.apex() == apex).unwrap().set_bogus(true);
+        zones
+    };
+    assert_eq!(matrix_of(broken_zones(), Behaviour::validating()),
+               vec![Some(Validates), Some(Validates), Some(Breaks("Correctly signed zone fails")), Some(Validates),
+                    Some(Validates)]);
+    assert_eq!(matrix_of(broken_zones(), Behaviour::default()), all(PassesThrough));
+
+    // A validator from before ECDSA and Ed25519, following RFC 6840 or not
+    let rsa_only = vec![Algorithm::ECDSAP256SHA256, Algorithm::ECDSAP384SHA384, Algorithm::ED25519];
+    assert_eq!(matrix(Behaviour { unsupported_algorithms: rsa_only.clone(), ..Behaviour::validating() }),
+               vec![Some(Validates), Some(Validates), Some(PassesThrough), Some(PassesThrough), Some(PassesThrough)]);
+    assert_eq!(matrix(Behaviour { broken_algorithms: rsa_only, ..Behaviour::validating() }),
+               vec![Some(Validates), Some(Validates), Some(Breaks("Correctly signed zone fails")),
+                    Some(Breaks("Correctly signed zone fails")), Some(Breaks("Correctly signed zone fails"))]);
+    // Zones that don't exist say nothing about the algorithm
+    assert_eq!(matrix_of(Zones(vec![]), Behaviour::validating()), vec![None; ALGORITHMS.len()]);
+}
@@ -0,0 +1,354 @@
+//! Classification of a resolver, [RFC 8027, section 4](https://tools.ietf.org/html/rfc8027#section-4).
+//!
+//! Individual probes say little on their own. What a client needs to know is whether it can use
+//! the resolver for DNSSEC and how. The results of a probe run are collected in `ProbeRun` and
+//! `classify` maps them to one of the categories of RFC 8027 together with the mitigation the
+//! RFC recommends for it.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use interception::FOREIGN_COOKIE;
+use super::TestResult;
+
+/// Every probe the classification knows about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Probe {
+    Udp,
+    Tcp,
+    /// Plain answers over DNS over TLS
+    Tls,
+    Edns0,
+    DoBit,
+    Rrsig,
+    Dnskey,
+    Ds,
+    NsecNxdomain,
+    NsecNodata,
+    Nsec3Nxdomain,
+    Nsec3Nodata,
+    UnknownType,
+    LargeUdp,
+    TcpFallback,
+    Validation,
+    /// Zones signed with each of the common algorithms work
+    Algorithms,
+    /// NXDOMAIN comes back as it is, not rewritten to an answer
+    NxdomainRewriting,
+    /// Answers come from someone else than the resolver
+    Interception,
+    /// EDNS cookies come back as sent
+    Cookie,
+    /// Unknown EDNS versions are rejected
+    EdnsVersion,
+}
+
+impl Probe {
+    pub fn all() -> &'static [Probe] {
+        &[Probe::Udp, Probe::Tcp, Probe::Tls, Probe::Edns0, Probe::DoBit, Probe::Rrsig, Probe::Dnskey, Probe::Ds,
+          Probe::NsecNxdomain, Probe::NsecNodata, Probe::Nsec3Nxdomain, Probe::Nsec3Nodata, Probe::UnknownType,
+          Probe::LargeUdp, Probe::TcpFallback, Probe::Validation, Probe::Algorithms, Probe::NxdomainRewriting,
+          Probe::Interception, Probe::Cookie, Probe::EdnsVersion]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Probe::Udp => "udp",
+            Probe::Tcp => "tcp",
+            Probe::Tls => "tls",
+            Probe::Edns0 => "edns0",
+            Probe::DoBit => "do-bit",
+            Probe::Rrsig => "rrsig",
+            Probe::Dnskey => "dnskey",
+            Probe::Ds => "ds",
+            Probe::NsecNxdomain => "nsec-nxdomain",
+            Probe::NsecNodata => "nsec-nodata",
+            Probe::Nsec3Nxdomain => "nsec3-nxdomain",
+            Probe::Nsec3Nodata => "nsec3-nodata",
+            Probe::UnknownType => "unknown-type",
+            Probe::LargeUdp => "large-udp",
+            Probe::TcpFallback => "tcp-fallback",
+            Probe::Validation => "validation",
+            Probe::Algorithms => "algorithms",
+            Probe::NxdomainRewriting => "nxdomain-rewriting",
+            Probe::Interception => "interception",
+            Probe::Cookie => "cookie",
+            Probe::EdnsVersion => "edns-version",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Probe> {
+        Probe::all().iter().cloned().find(|p| p.name() == name)
+    }
+
+    /// Probes that must pass for the resolver to be usable for DNSSEC, apart from the transport
+    /// of large responses where either of two ways is enough. Rewritten NXDOMAIN answers are bogus
+    /// to a validator, so a resolver that tampers with them is no forwarder either. Neither is one
+    /// that turns zones signed with an algorithm it doesn't know into SERVFAIL.
+    fn dnssec_required(&self) -> bool {
+        matches!(self, Probe::Edns0 | Probe::DoBit | Probe::Rrsig | Probe::Dnskey | Probe::Ds | Probe::NsecNxdomain
+                 | Probe::NsecNodata | Probe::Nsec3Nxdomain | Probe::Nsec3Nodata | Probe::UnknownType
+                 | Probe::Algorithms | Probe::NxdomainRewriting)
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// Results of the probes run against one resolver. Probes that were not run don't count either
+/// way, except that no resolver is considered DNSSEC-aware without the DO bit and RRSIG probes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProbeRun {
+    results: BTreeMap<Probe, TestResult>,
+}
+
+impl ProbeRun {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Record the result of a probe. A probe run several times (e.g. over UDP and over TCP)
+    /// keeps its first failure.
+    pub fn record(&mut self, probe: Probe, result: TestResult) {
+        if !self.failed(probe) {
+            self.results.insert(probe, result);
+        }
+    }
+
+    /// Record the outcome of a probe future, a query that failed altogether (e.g. timed out)
+    /// counts as a failed probe
+    pub fn record_response<E>(&mut self, probe: Probe, response: Result<TestResult, E>) {
+        self.record(probe, response.unwrap_or(TestResult::Fail("No response")));
+    }
+
+    pub fn get(&self, probe: Probe) -> Option<TestResult> {
+        self.results.get(&probe).cloned()
+    }
+
+    pub fn results(&self) -> impl Iterator<Item = (Probe, TestResult)> + '_ {
+        self.results.iter().map(|(p, r)| (*p, *r))
+    }
+
+    fn passed(&self, probe: Probe) -> bool {
+        self.get(probe) == Some(TestResult::Success)
+    }
+
+    fn failed(&self, probe: Probe) -> bool {
+        matches!(self.get(probe), Some(TestResult::Fail(_)))
+    }
+
+    pub fn failures(&self) -> Vec<(Probe, &'static str)> {
+        self.results.iter()
+            .filter_map(|(p, r)| match r {
+                TestResult::Fail(reason) => Some((*p, *reason)),
+                TestResult::Success => None,
+            })
+            .collect()
+    }
+}
+
+/// Categories of resolvers from RFC 8027, section 4
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    /// Validates DNSSEC itself and passes everything a validator needs
+    Validating,
+    /// Passes DNSSEC records through, but doesn't validate
+    DnssecAware,
+    /// Some DNSSEC records get through, others don't
+    Partial,
+    /// Plain DNS works, DNSSEC doesn't
+    DnssecBroken,
+    /// Not even plain DNS queries get an answer
+    NoDns,
+}
+
+impl Category {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Validating => "validating",
+            Category::DnssecAware => "dnssec-aware",
+            Category::Partial => "partial",
+            Category::DnssecBroken => "dnssec-broken",
+            Category::NoDns => "no-dns",
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How a client should use the resolver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mitigation {
+    /// Forward queries to the resolver and trust its validation
+    UseAsForwarder,
+    /// Forward queries to the resolver, but validate the answers locally
+    StubAndValidate,
+    /// Forward queries over TCP (or TLS), UDP can't carry the large responses
+    UseTcp,
+    /// Don't use the resolver, recurse from the root directly
+    Recurse,
+}
+
+impl Mitigation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mitigation::UseAsForwarder => "use-as-forwarder",
+            Mitigation::StubAndValidate => "stub-and-validate",
+            Mitigation::UseTcp => "use-tcp",
+            Mitigation::Recurse => "recurse",
+        }
+    }
+}
+
+impl fmt::Display for Mitigation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classification {
+    pub category: Category,
+    pub mitigation: Mitigation,
+    /// Failed probes that led to the category
+    pub failures: Vec<(Probe, &'static str)>,
+    /// The answers don't come from the resolver, but from something on the way to it. The
+    /// category is the one of whatever answers.
+    pub intercepted: bool,
+}
+
+impl Classification {
+    /// A resolver the client can forward queries to, with or without validating locally
+    pub fn is_usable(&self) -> bool {
+        self.mitigation != Mitigation::Recurse
+    }
+}
+
+impl fmt::Display for Classification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.category, self.mitigation)?;
+        if self.intercepted {
+            write!(f, ", intercepted")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn classify(run: &ProbeRun) -> Classification {
+    let failures = run.failures();
+    let (category, mitigation) = if ![Probe::Udp, Probe::Tcp, Probe::Tls].iter().any(|p| run.passed(*p)) {
+        (Category::NoDns, Mitigation::Recurse)
+    } else if !run.passed(Probe::DoBit) || !run.passed(Probe::Rrsig) {
+        (Category::DnssecBroken, Mitigation::Recurse)
+    } else {
+        let dnssec_failed = Probe::all().iter().any(|p| p.dnssec_required() && run.failed(*p));
+        let udp_carries_large = !run.failed(Probe::LargeUdp) && !run.failed(Probe::Udp);
+        let tcp_carries_large = !run.failed(Probe::TcpFallback) && (!run.failed(Probe::Tcp) || run.passed(Probe::Tls));
+        if dnssec_failed || (!udp_carries_large && !tcp_carries_large) {
+            (Category::Partial, Mitigation::Recurse)
+        } else if !udp_carries_large {
+            // Everything is there, only UDP responses get lost on the way
+            (Category::Partial, Mitigation::UseTcp)
+        } else if run.passed(Probe::Validation) {
+            (Category::Validating, Mitigation::UseAsForwarder)
+        } else {
+            (Category::DnssecAware, Mitigation::StubAndValidate)
+        }
+    };
+    Classification {
+        category,
+        mitigation,
+        failures,
+        intercepted: run.failed(Probe::Interception) || run.get(Probe::Cookie) == Some(TestResult::Fail(FOREIGN_COOKIE)),
+    }
+}
+
+#[cfg(test)]
+fn run_with(failed: &[Probe], skipped: &[Probe]) -> ProbeRun {
+    let mut run = ProbeRun::new();
+    for probe in Probe::all().iter().filter(|p| !skipped.contains(p)) {
+        let result = if failed.contains(probe) { TestResult::Fail("Failed") } else { TestResult::Success };
+        run.record(*probe, result);
+    }
+    run
+}
+
+#[test]
+fn classification_categories() {
+    let classify_with = |failed: &[Probe], skipped: &[Probe]| {
+        let c = classify(&run_with(failed, skipped));
+        (c.category, c.mitigation)
+    };
+    assert_eq!(classify_with(&[], &[]), (Category::Validating, Mitigation::UseAsForwarder));
+    assert_eq!(classify_with(&[Probe::Validation], &[]), (Category::DnssecAware, Mitigation::StubAndValidate));
+    assert_eq!(classify_with(&[], &[Probe::Validation]), (Category::DnssecAware, Mitigation::StubAndValidate));
+    assert_eq!(classify_with(&[Probe::LargeUdp], &[]), (Category::Partial, Mitigation::UseTcp));
+    assert_eq!(classify_with(&[Probe::LargeUdp, Probe::TcpFallback], &[]), (Category::Partial, Mitigation::Recurse));
+    assert_eq!(classify_with(&[Probe::Nsec3Nxdomain], &[]), (Category::Partial, Mitigation::Recurse));
+    assert_eq!(classify_with(&[Probe::NxdomainRewriting, Probe::Validation], &[]), (Category::Partial, Mitigation::Recurse));
+    assert_eq!(classify_with(&[Probe::Algorithms], &[]), (Category::Partial, Mitigation::Recurse));
+    assert_eq!(classify_with(&[Probe::Rrsig, Probe::Dnskey], &[]), (Category::DnssecBroken, Mitigation::Recurse));
+    assert_eq!(classify_with(&[], &[Probe::DoBit]), (Category::DnssecBroken, Mitigation::Recurse));
+    assert_eq!(classify_with(&[Probe::Udp], &[]), (Category::Partial, Mitigation::UseTcp));
+    assert_eq!(classify_with(&[Probe::Udp, Probe::Tcp], &[]), (Category::Partial, Mitigation::UseTcp));
+    assert_eq!(classify_with(&[Probe::Udp, Probe::Tcp, Probe::Tls], &[]), (Category::NoDns, Mitigation::Recurse));
+    assert_eq!(classify_with(&[Probe::Udp, Probe::Tcp], &[Probe::Tls]), (Category::NoDns, Mitigation::Recurse));
+
+    let mut run = run_with(&[Probe::Ds], &[]);
+    run.record(Probe::Ds, TestResult::Success);
+    run.record(Probe::Dnskey, TestResult::Success);
+    let c = classify(&run);
+    assert_eq!(c.failures, vec![(Probe::Ds, "Failed")]);
+    assert!(!c.is_usable());
+    assert!(!c.intercepted);
+    let c = classify(&run_with(&[Probe::Cookie, Probe::EdnsVersion], &[]));
+    assert_eq!((c.category, c.intercepted), (Category::Validating, false));
+    let mut run = run_with(&[], &[Probe::Cookie]);
+    run.record(Probe::Cookie, TestResult::Fail(FOREIGN_COOKIE));
+    assert!(classify(&run).intercepted);
+    assert!(classify(&run_with(&[Probe::Interception], &[])).intercepted);
+
+    for probe in Probe::all() {
+        assert_eq!(Probe::from_name(probe.name()), Some(*probe));
+    }
+}
+
+#[test]
+fn classification_of_stand_ins() {
+    use std::time::Duration;
+    use trust_dns::rr::dnssec::Algorithm;
+    use fixture::{Behaviour, Server, Zones};
+    use runner::{run_probes, Options, Policy};
+
+    let options = Options {
+        decoy: None,
+        policy: Policy { timeout: Duration::from_millis(500), retries: 0 },
+        ..Default::default()
+    };
+    let classify_with = |behaviour| {
+        let server = Server::resolver(Zones::testing(), behaviour);
+        let c = run_probes(&server.address().into(), &options).unwrap().classification();
+        (c.category, c.mitigation)
+    };
+    assert_eq!(classify_with(Behaviour::validating()), (Category::Validating, Mitigation::UseAsForwarder));
+    assert_eq!(classify_with(Behaviour::default()), (Category::DnssecAware, Mitigation::StubAndValidate));
+    assert_eq!(classify_with(Behaviour { drop_fragmented: true, ..Default::default() }),
+               (Category::Partial, Mitigation::UseTcp));
+    assert_eq!(classify_with(Behaviour { strip_denial: true, ..Default::default() }),
+               (Category::Partial, Mitigation::Recurse));
+    assert_eq!(classify_with(Behaviour { strip_dnssec: true, ..Default::default() }),
+               (Category::DnssecBroken, Mitigation::Recurse));
+    assert_eq!(classify_with(Behaviour { rewrite_nxdomain: true, ..Behaviour::validating() }),
+               (Category::Partial, Mitigation::Recurse));
+    assert_eq!(classify_with(Behaviour { unsupported_algorithms: vec![Algorithm::ED25519], ..Behaviour::validating() }),
+               (Category::Validating, Mitigation::UseAsForwarder));
+    assert_eq!(classify_with(Behaviour { broken_algorithms: vec![Algorithm::ED25519], ..Behaviour::validating() }),
+               (Category::Partial, Mitigation::Recurse));
+}
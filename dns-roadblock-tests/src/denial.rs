@@ -0,0 +1,387 @@
+//! Authenticated denial of existence, [RFC 8027, sections 3.1.8 and 3.1.9](https://tools.ietf.org/html/rfc8027#section-3.1.8).
+//!
+//! A validator can only trust NXDOMAIN and NODATA answers that carry the NSEC or NSEC3 records
+//! proving them, together with their signatures, in the authority section. Middleboxes often
+//! forward the response code and drop the rest. The probes query a random name that can't exist
+//! and a type that doesn't exist at the zone apex.
+
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use trust_dns::op::{Message, ResponseCode};
+use trust_dns::rr::{Name, RData, Record, RecordType};
+use trust_dns::rr::dnssec::Nsec3HashAlgorithm;
+use trust_dns::rr::rdata::{DNSSECRData, DNSSECRecordType, NSEC, NSEC3};
+
+use trust_dns_proto::DnsHandle;
+
+use futures::prelude::*;
+
+use super::TestResult;
+use signed_records::{check_signed_rrset, dnssec_query};
+
+/// Type that no zone is expected to have at its apex
+const ABSENT_TYPE: RecordType = RecordType::NULL;
+
+/// Label that is unique for every call, so that no resolver has the answer cached
+pub fn random_label() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    format!("roadblock-{:x}-{:x}", process::id(), nanos)
+}
+
+/// Random name right below the zone
+pub fn nonexistent_name(zone: &Name) -> Name {
+    Name::from_labels(vec![random_label()]).append_domain(zone)
+}
+
+fn nsecs(records: &[Record]) -> Vec<(&Name, &NSEC)> {
+    records.iter()
+        .filter_map(|r| match *r.rdata() {
+            RData::DNSSEC(DNSSECRData::NSEC(ref nsec)) => Some((r.name(), nsec)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn nsec3s(records: &[Record]) -> Vec<(&Name, &NSEC3)> {
+    records.iter()
+        .filter_map(|r| match *r.rdata() {
+            RData::DNSSEC(DNSSECRData::NSEC3(ref nsec3)) => Some((r.name(), nsec3)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Base 32 encoding with the extended hex alphabet used for NSEC3 owner names. It keeps the
+/// order of the hashes, so hashed names sort like the hashes themselves.
+pub fn base32hex(data: &[u8]) -> String {
+    let alphabet = b"0123456789abcdefghijklmnopqrstuv";
+    let mut out = String::new();
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for b in data {
+        acc = (acc << 8 | u32::from(*b)) & 0xffff;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(alphabet[(acc >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(alphabet[(acc << (5 - bits)) as usize & 31] as char);
+    }
+    out
+}
+
+/// Whether the name sorts between the owner and the next name of an NSEC or NSEC3 record. The
+/// last record of a chain points back to the first one.
+fn covers(owner: &Name, next: &Name, name: &Name) -> bool {
+    if owner < next {
+        owner < name && name < next
+    } else {
+        owner < name || name < next
+    }
+}
+
+/// Whether the NSEC record proves that the name does not exist
+fn nsec_covers(owner: &Name, nsec: &NSEC, name: &Name) -> bool {
+    covers(owner, nsec.next_domain_name(), name)
+}
+
+/// SHA-1, the only NSEC3 hash algorithm. trust-dns only has it with a crypto backend, which
+/// isn't among the dependencies.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (h, v) in h.iter_mut().zip(&[a, b, c, d, e]) {
+            *h = h.wrapping_add(*v);
+        }
+    }
+    let mut digest = [0; 20];
+    for (out, v) in digest.chunks_mut(4).zip(&h) {
+        out.copy_from_slice(&v.to_be_bytes());
+    }
+    digest
+}
+
+/// [RFC 5155, section 5](https://tools.ietf.org/html/rfc5155#section-5): SHA-1 of the name in
+/// canonical wire format and the salt, hashed again with the salt for every iteration. The names
+/// the probes hash are plain host names, so the labels can be taken from the text form.
+pub fn nsec3_hash(name: &Name, salt: &[u8], iterations: u16) -> Vec<u8> {
+    let mut wire = vec![];
+    for label in name.to_lowercase().to_string().split('.').filter(|l| !l.is_empty()) {
+        wire.push(label.len() as u8);
+        wire.extend_from_slice(label.as_bytes());
+    }
+    wire.push(0);
+    let mut hash = sha1(&[&wire[..], salt].concat()).to_vec();
+    for _ in 0..iterations {
+        hash = sha1(&[&hash[..], salt].concat()).to_vec();
+    }
+    hash
+}
+
+/// Hashed owner name of the name, with the parameters of the NSEC3 record. `None` if the hash
+/// algorithm isn't SHA-1.
+fn nsec3_owner(nsec3: &NSEC3, name: &Name, zone: &Name) -> Option<Name> {
+    if nsec3.hash_algorithm() != Nsec3HashAlgorithm::SHA1 {
+        return None;
+    }
+    let hash = nsec3_hash(name, nsec3.salt(), nsec3.iterations());
+    Some(Name::from_labels(vec![base32hex(&hash)]).append_domain(zone))
+}
+
+/// Common checks of a denial: the expected response code, no answer and a signed SOA
+fn check_denial(msg: &Message, zone: &Name, rcode: ResponseCode) -> Result<(), &'static str> {
+    if msg.response_code() != rcode {
+        return Err(if rcode == ResponseCode::NXDomain { "Expected NXDOMAIN" } else { "Expected NOERROR" });
+    }
+    if !msg.answers().is_empty() {
+        return Err("Answer section of a denial is not empty");
+    }
+    if msg.name_servers().is_empty() {
+        return Err("Empty authority section");
+    }
+    check_signed_rrset(msg.name_servers(), RecordType::SOA, zone).map_err(|_| "SOA missing or not signed")
+}
+
+fn nsec_nxdomain_result(msg: &Message, zone: &Name, name: &Name) -> Result<(), &'static str> {
+    check_denial(msg, zone, ResponseCode::NXDomain)?;
+    let nsecs = nsecs(msg.name_servers());
+    if nsecs.is_empty() {
+        return Err("No NSEC records in the authority section");
+    }
+    check_signed_rrset(msg.name_servers(), RecordType::DNSSEC(DNSSECRecordType::NSEC), zone)
+        .map_err(|_| "NSEC records not signed")?;
+    if !nsecs.iter().any(|&(owner, nsec)| nsec_covers(owner, nsec, name)) {
+        return Err("No NSEC record covers the name");
+    }
+    Ok(())
+}
+
+fn nsec_nodata_result(msg: &Message, zone: &Name) -> Result<(), &'static str> {
+    check_denial(msg, zone, ResponseCode::NoError)?;
+    let nsecs = nsecs(msg.name_servers());
+    if nsecs.is_empty() {
+        return Err("No NSEC records in the authority section");
+    }
+    check_signed_rrset(msg.name_servers(), RecordType::DNSSEC(DNSSECRecordType::NSEC), zone)
+        .map_err(|_| "NSEC records not signed")?;
+    match nsecs.iter().find(|&&(owner, _)| owner == zone) {
+        None => Err("No NSEC record for the queried name"),
+        Some(&(_, nsec)) if nsec.type_bit_maps().contains(&ABSENT_TYPE) => Err("NSEC type bitmap contains the queried type"),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Checks common to all NSEC3 denials: the records belong to the zone and use the same
+/// parameters
+fn nsec3_result(msg: &Message, zone: &Name, rcode: ResponseCode) -> Result<(), &'static str> {
+    check_denial(msg, zone, rcode)?;
+    let nsec3s = nsec3s(msg.name_servers());
+    if nsec3s.is_empty() {
+        return Err("No NSEC3 records in the authority section");
+    }
+    check_signed_rrset(msg.name_servers(), RecordType::DNSSEC(DNSSECRecordType::NSEC3), zone)
+        .map_err(|_| "NSEC3 records not signed")?;
+    if !nsec3s.iter().all(|&(owner, _)| owner.base_name() == *zone) {
+        return Err("NSEC3 records outside of the zone");
+    }
+    let (_, first) = nsec3s[0];
+    if !nsec3s.iter().all(|&(_, n)| n.salt() == first.salt() && n.iterations() == first.iterations()) {
+        return Err("NSEC3 records with different parameters");
+    }
+    Ok(())
+}
+
+/// [RFC 5155, section 8.4](https://tools.ietf.org/html/rfc5155#section-8.4): one NSEC3 must match
+/// the closest encloser, the longest existing ancestor of the name, and another one must cover
+/// the next closer name, the name one label longer than the closest encloser
+fn nsec3_nxdomain_result(msg: &Message, zone: &Name, name: &Name) -> Result<(), &'static str> {
+    nsec3_result(msg, zone, ResponseCode::NXDomain)?;
+    let nsec3s = nsec3s(msg.name_servers());
+    let (_, params) = nsec3s[0];
+    let hashed = |name: &Name| nsec3_owner(params, name, zone).ok_or("Unknown NSEC3 hash algorithm");
+    let mut labels = name.num_labels();
+    while labels > zone.num_labels() {
+        let encloser = hashed(&name.trim_to(labels as usize - 1))?;
+        if nsec3s.iter().any(|&(owner, _)| *owner == encloser) {
+            let next_closer = hashed(&name.trim_to(labels as usize))?;
+            let covered = nsec3s.iter().any(|&(owner, nsec3)| {
+                let next = Name::from_labels(vec![base32hex(nsec3.next_hashed_owner_name())]).append_domain(zone);
+                covers(owner, &next, &next_closer)
+            });
+            return if covered { Ok(()) } else { Err("No NSEC3 record covers the next closer name") };
+        }
+        labels -= 1;
+    }
+    Err("No NSEC3 record matches the closest encloser")
+}
+
+/// The NSEC3 matching the apex is the only one whose type bitmap has the SOA, which spares
+/// computing the hash of the name
+fn nsec3_nodata_result(msg: &Message, zone: &Name) -> Result<(), &'static str> {
+    nsec3_result(msg, zone, ResponseCode::NoError)?;
+    let nsec3s = nsec3s(msg.name_servers());
+    match nsec3s.iter().find(|&&(_, nsec3)| nsec3.type_bit_maps().contains(&RecordType::SOA)) {
+        None => Err("No NSEC3 record for the queried name"),
+        Some(&(_, nsec3)) if nsec3.type_bit_maps().contains(&ABSENT_TYPE) => Err("NSEC3 type bitmap contains the queried type"),
+        Some(_) => Ok(()),
+    }
+}
+
+fn to_test_result(result: Result<(), &'static str>) -> TestResult {
+    match result {
+        Ok(()) => TestResult::Success,
+        Err(reason) => TestResult::Fail(reason),
+    }
+}
+
+/// [RFC 8027, section 3.1.8](https://tools.ietf.org/html/rfc8027#section-3.1.8)
+///
+/// NXDOMAIN for a random name in an NSEC signed zone must come with a signed NSEC covering it.
+pub fn support_nsec_nxdomain<DH>(dns_handle: &mut DH, zone: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    let zone = zone.clone();
+    let name = nonexistent_name(&zone);
+    dns_handle
+        .send(dnssec_query(name.clone(), RecordType::A))
+        .map(move |msg| to_test_result(nsec_nxdomain_result(&msg, &zone, &name)))
+}
+
+/// [RFC 8027, section 3.1.8](https://tools.ietf.org/html/rfc8027#section-3.1.8)
+///
+/// NODATA at the apex of an NSEC signed zone must come with the apex NSEC whose type bitmap
+/// doesn't have the queried type.
+pub fn support_nsec_nodata<DH>(dns_handle: &mut DH, zone: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    let zone = zone.clone();
+    dns_handle
+        .send(dnssec_query(zone.clone(), ABSENT_TYPE))
+        .map(move |msg| to_test_result(nsec_nodata_result(&msg, &zone)))
+}
+
+/// [RFC 8027, section 3.1.9](https://tools.ietf.org/html/rfc8027#section-3.1.9)
+///
+/// NXDOMAIN for a random name in an NSEC3 signed zone must come with the signed NSEC3 records of
+/// the closest encloser proof.
+pub fn support_nsec3_nxdomain<DH>(dns_handle: &mut DH, zone: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    let zone = zone.clone();
+    let name = nonexistent_name(&zone);
+    dns_handle
+        .send(dnssec_query(name.clone(), RecordType::A))
+        .map(move |msg| to_test_result(nsec3_nxdomain_result(&msg, &zone, &name)))
+}
+
+/// [RFC 8027, section 3.1.9](https://tools.ietf.org/html/rfc8027#section-3.1.9)
+///
+/// NODATA at the apex of an NSEC3 signed zone must come with the signed NSEC3 of the apex whose
+/// type bitmap doesn't have the queried type.
+pub fn support_nsec3_nodata<DH>(dns_handle: &mut DH, zone: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    let zone = zone.clone();
+    dns_handle
+        .send(dnssec_query(zone.clone(), ABSENT_TYPE))
+        .map(move |msg| to_test_result(nsec3_nodata_result(&msg, &zone)))
+}
+
+#[test]
+fn nsec_coverage() {
+    use std::str::FromStr;
+
+    let name = |s| Name::from_str(s).unwrap();
+    let nsec = |next| NSEC::new(name(next), vec![RecordType::A]);
+    assert!(nsec_covers(&name("a.example."), &nsec("d.example."), &name("b.example.")));
+    assert!(nsec_covers(&name("a.example."), &nsec("d.example."), &name("x.b.example.")));
+    assert!(!nsec_covers(&name("a.example."), &nsec("d.example."), &name("e.example.")));
+    assert!(!nsec_covers(&name("a.example."), &nsec("d.example."), &name("a.example.")));
+    // The last record of the zone wraps around to the apex
+    assert!(nsec_covers(&name("z.example."), &nsec("example."), &name("zz.example.")));
+    assert!(!nsec_covers(&name("z.example."), &nsec("example."), &name("b.example.")));
+    // Hashed names wrap around to the smallest hash
+    assert!(covers(&name("v0.example."), &name("10.example."), &name("02.example.")));
+    assert!(!covers(&name("v0.example."), &name("10.example."), &name("20.example.")));
+
+    assert_eq!(base32hex(&[0xff; 5]), "vvvvvvvv");
+    assert_eq!(base32hex(b"f"), "co");
+
+    // RFC 5155, appendix A
+    let salt = [0xaa, 0xbb, 0xcc, 0xdd];
+    assert_eq!(base32hex(&nsec3_hash(&name("example."), &salt, 12)), "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom");
+    assert_eq!(base32hex(&nsec3_hash(&name("a.EXAMPLE."), &salt, 12)), "35mthgpgcu1qg68fab165klnsnk3dpvl");
+
+    let zone = name("example.");
+    let random = nonexistent_name(&zone);
+    assert_eq!(random.base_name(), zone);
+    assert!(random[0].starts_with("roadblock-"));
+}
+
+#[test]
+fn denial_probes() {
+    use trust_dns::rr::rdata::NULL;
+    use fixture::{probe, probe_server, Behaviour, Server, Zones};
+    use super::{NSEC3_TESTING_ZONE, NSEC_TESTING_ZONE};
+
+    let correct = Behaviour::default;
+    let stripped = || Behaviour { strip_denial: true, ..Default::default() };
+    assert_eq!(probe(correct(), |udp, _| support_nsec_nxdomain(udp, &NSEC_TESTING_ZONE)), TestResult::Success);
+    assert_eq!(probe(stripped(), |udp, _| support_nsec_nxdomain(udp, &NSEC_TESTING_ZONE)),
+               TestResult::Fail("No NSEC records in the authority section"));
+    assert_eq!(probe(correct(), |udp, _| support_nsec_nodata(udp, &NSEC_TESTING_ZONE)), TestResult::Success);
+    assert_eq!(probe(stripped(), |udp, _| support_nsec_nodata(udp, &NSEC_TESTING_ZONE)),
+               TestResult::Fail("No NSEC records in the authority section"));
+    assert_eq!(probe(correct(), |udp, _| support_nsec3_nxdomain(udp, &NSEC3_TESTING_ZONE)), TestResult::Success);
+    assert_eq!(probe(stripped(), |udp, _| support_nsec3_nxdomain(udp, &NSEC3_TESTING_ZONE)),
+               TestResult::Fail("No NSEC3 records in the authority section"));
+    // The closest encloser matches, but no NSEC3 covers the name below it
+    let mut zones = Zones::testing();
+    zones.0[0].set_short_nsec3(true);
+    let short = Server::resolver(zones, correct());
+    assert_eq!(probe_server(&short, |udp, _| support_nsec3_nxdomain(udp, &NSEC3_TESTING_ZONE)),
+               TestResult::Fail("No NSEC3 record covers the next closer name"));
+    assert_eq!(probe(correct(), |udp, _| support_nsec3_nodata(udp, &NSEC3_TESTING_ZONE)), TestResult::Success);
+    assert_eq!(probe(Behaviour { strip_dnssec: true, ..correct() }, |udp, _| support_nsec3_nodata(udp, &NSEC3_TESTING_ZONE)),
+               TestResult::Fail("SOA missing or not signed"));
+    // A filter that drops the records of the queried type forwards a proof that lists it
+    let mut zones = Zones::testing();
+    zones.0[0].add(&NSEC3_TESTING_ZONE, RecordType::NULL, RData::NULL(NULL::with(vec![0])));
+    let filtered = Server::resolver(zones, Behaviour { hide_types: vec![RecordType::NULL], ..correct() });
+    assert_eq!(probe_server(&filtered, |udp, _| support_nsec3_nodata(udp, &NSEC3_TESTING_ZONE)),
+               TestResult::Fail("NSEC3 type bitmap contains the queried type"));
+    // The zones don't mix up their denial methods
+    assert_eq!(probe(correct(), |udp, _| support_nsec_nodata(udp, &NSEC3_TESTING_ZONE)),
+               TestResult::Fail("No NSEC records in the authority section"));
+}
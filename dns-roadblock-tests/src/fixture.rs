@@ -0,0 +1,863 @@
+//! Offline fixture for the probes.
+//!
+//! The probes ask real resolvers about real zones, which can't be done in CI. The fixture
+//! generates small signed zones with the names the probes use (`org.` signed with NSEC3,
+//! `dnssec-tools.org.` and `isc.org.` with NSEC) and serves them on 127.0.0.x, either
+//! authoritatively or through a stand-in resolver that answers from the same data and imitates
+//! the misbehaviour of a middlebox.
+//!
+//! There's no crypto library among the dependencies, so signatures and DS digests are made up.
+//! The records are otherwise complete: key tags, signers, validity periods and the order of the
+//! NSEC and NSEC3 chains are right, which is all the probes can check. NSEC3 hashes are real
+//! SHA-1, the probes compute them as well to check closest encloser proofs. A validating stand-in
+//! checks the signatures by making them up again with the keys of the zone.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::collections::Bound::{Excluded, Unbounded};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use trust_dns::client::{BasicClientHandle, ClientConnection, ClientFuture};
+use trust_dns::op::{Edns, Message, MessageType, Query, ResponseCode};
+use trust_dns::rr::{DNSClass, Name, RData, Record, RecordType};
+use trust_dns::rr::dnssec::{Algorithm, DigestType, Nsec3HashAlgorithm};
+use trust_dns::rr::rdata::{DNSSECRData, DNSSECRecordType, DNSKEY, DS, NSEC, NSEC3, SIG, SOA, TXT};
+use trust_dns::rr::rdata::opt::EdnsOption;
+use trust_dns::tcp::TcpClientConnection;
+use trust_dns::udp::UdpClientConnection;
+
+use tokio_core::reactor::Core;
+
+use denial::{base32hex, nsec3_hash};
+use interception::COOKIE;
+use algorithms::{algorithm_zone, ALGORITHMS};
+use signed_records::{key_tag, EDNS_PAYLOAD};
+use large_response::CLASSIC_PAYLOAD;
+use super::{ALGORITHM_TESTING_ZONE, NSEC3_TESTING_ZONE, NSEC_TESTING_ZONE, TESTING_SERVER, WHOAMI_NAME};
+
+const TTL: u32 = 3600;
+/// Signatures are valid from an hour ago for 30 days
+const VALIDITY: u32 = 30 * 24 * 3600;
+/// Largest UDP response that fits into one Ethernet frame
+const UNFRAGMENTED_SIZE: usize = 1472;
+/// How often the server threads check whether they should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Deterministic stand-in for a hash function, FNV-1a stretched to the requested length
+fn fake_digest(data: &[u8], len: usize) -> Vec<u8> {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    (0..len)
+        .map(|i| {
+            for b in data.iter().chain(&[i as u8]) {
+                hash ^= u64::from(*b);
+                hash = hash.wrapping_mul(0x100_0000_01b3);
+            }
+            (hash >> 56) as u8
+        })
+        .collect()
+}
+
+/// Made-up signature of an RRset by the key, anyone with the key comes to the same one
+fn fake_signature(owner: &Name, record_type: RecordType, inception: u32, key: &DNSKEY) -> Vec<u8> {
+    let len = match key.algorithm() {
+        Algorithm::ECDSAP256SHA256 | Algorithm::ED25519 => 64,
+        Algorithm::ECDSAP384SHA384 => 96,
+        _ => key.public_key().len() - 4,
+    };
+    let seed = format!("{} {:?} {}", owner.to_lowercase(), record_type, inception);
+    fake_digest(&[seed.as_bytes(), key.public_key()].concat(), len)
+}
+
+fn now() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as u32).unwrap_or(0)
+}
+
+fn name(s: &str) -> Name {
+    s.parse().expect("Name building should never fail.")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denial {
+    Nsec,
+    Nsec3,
+}
+
+/// A signed zone. The NSEC or NSEC3 chain and the signatures are generated when answering.
+pub struct Zone {
+    apex: Name,
+    denial: Denial,
+    algorithm: Algorithm,
+    /// Signatures that don't match the records, a validator must reject every answer
+    bogus: bool,
+    /// The NSEC3 that should cover a name that doesn't exist ends before it
+    short_nsec3: bool,
+    /// KSK first, then the ZSK, then standby keys that only make the DNSKEY RRset larger
+    keys: Vec<DNSKEY>,
+    records: BTreeMap<Name, Vec<Record>>,
+}
+
+impl Zone {
+    /// Zone with SOA, NS, an address at the apex and two RSA/SHA-256 keys
+    pub fn new(apex: &Name, denial: Denial) -> Zone {
+        Zone::signed_with(apex, denial, Algorithm::RSASHA256)
+    }
+
+    /// Zone signed with two keys of the algorithm
+    pub fn signed_with(apex: &Name, denial: Denial, algorithm: Algorithm) -> Zone {
+        let mut zone = Zone {
+            apex: apex.clone(),
+            denial,
+            algorithm,
+            bogus: false,
+            short_nsec3: false,
+            keys: vec![],
+            records: BTreeMap::new(),
+        };
+        let ns = Name::from_labels(vec!["ns"]).append_domain(apex);
+        let soa = SOA::new(ns.clone(), Name::from_labels(vec!["hostmaster"]).append_domain(apex),
+                           1, 3600, 600, 86400, TTL);
+        zone.add(apex, RecordType::SOA, RData::SOA(soa));
+        zone.add(apex, RecordType::NS, RData::NS(ns.clone()));
+        zone.add(apex, RecordType::A, RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+        zone.add(&ns, RecordType::A, RData::A(Ipv4Addr::new(192, 0, 2, 53)));
+        zone.add_keys(true, 1);
+        zone.add_keys(false, 1);
+        zone
+    }
+
+    pub fn apex(&self) -> &Name {
+        &self.apex
+    }
+
+    /// Sign with signatures that don't match the records from now on
+    pub fn set_bogus(&mut self, bogus: bool) {
+        self.bogus = bogus;
+    }
+
+    /// Answer NXDOMAIN with an NSEC3 chain that skips the hash of the name, so that no record
+    /// of the proof covers it
+    pub fn set_short_nsec3(&mut self, short: bool) {
+        self.short_nsec3 = short;
+    }
+
+    pub fn add(&mut self, owner: &Name, record_type: RecordType, rdata: RData) {
+        self.records.entry(owner.clone())
+            .or_default()
+            .push(Record::from_rdata(owner.clone(), TTL, record_type, rdata));
+    }
+
+    /// Add keys to the DNSKEY RRset, with the SEP flag for key signing keys. RSA KSKs have 2048
+    /// bits and ZSKs 1024 bits, as in most zones signed with RSA.
+    pub fn add_keys(&mut self, sep: bool, count: usize) {
+        let len = match self.algorithm {
+            // exponent length, exponent 65537, modulus
+            Algorithm::RSASHA1 | Algorithm::RSASHA1NSEC3SHA1 | Algorithm::RSASHA256 | Algorithm::RSASHA512 => {
+                4 + if sep { 256 } else { 128 }
+            },
+            Algorithm::ECDSAP256SHA256 => 64,
+            Algorithm::ECDSAP384SHA384 => 96,
+            Algorithm::ED25519 => 32,
+        };
+        for _ in 0..count {
+            let seed = format!("{} {}", self.apex, self.keys.len());
+            let key = DNSKEY::new(true, sep, false, self.algorithm, fake_digest(seed.as_bytes(), len));
+            self.keys.push(key.clone());
+            let apex = self.apex.clone();
+            self.add(&apex, RecordType::DNSSEC(DNSSECRecordType::DNSKEY), RData::DNSSEC(DNSSECRData::DNSKEY(key)));
+        }
+    }
+
+    /// Delegate the child zone: NS and a DS of its key signing key
+    pub fn add_delegation(&mut self, child: &Zone) {
+        let ksk = &child.keys[0];
+        let ds = DS::new(key_tag(ksk).expect("Key tag of a generated key"), ksk.algorithm(), DigestType::SHA256,
+                         fake_digest(ksk.public_key(), 32));
+        let ns = Name::from_labels(vec!["ns"]).append_domain(&child.apex);
+        self.add(&child.apex, RecordType::NS, RData::NS(ns));
+        self.add(&child.apex, RecordType::DNSSEC(DNSSECRecordType::DS), RData::DNSSEC(DNSSECRData::DS(ds)));
+    }
+
+    fn rrset(&self, owner: &Name, record_type: RecordType) -> Vec<Record> {
+        self.records.get(owner)
+            .map(|records| records.iter().filter(|r| r.rr_type() == record_type).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn types_at(&self, owner: &Name) -> Vec<RecordType> {
+        let mut types = vec![];
+        for r in &self.records[owner] {
+            if !types.contains(&r.rr_type()) {
+                types.push(r.rr_type());
+            }
+        }
+        types.push(RecordType::DNSSEC(DNSSECRecordType::RRSIG));
+        types
+    }
+
+    fn is_delegation(&self, owner: &Name, record_type: RecordType) -> bool {
+        *owner != self.apex && record_type == RecordType::NS
+    }
+
+    fn rrsig(&self, owner: &Name, record_type: RecordType, key: &DNSKEY, signed_at: u32) -> Record {
+        let inception = signed_at.wrapping_sub(3600);
+        let mut signature = fake_signature(owner, record_type, inception, key);
+        if self.bogus {
+            signature = fake_digest(&signature, signature.len());
+        }
+        let sig = SIG::new(record_type, key.algorithm(), owner.num_labels(), TTL, signed_at.wrapping_add(VALIDITY),
+                           inception, key_tag(key).expect("Key tag of a generated key"), self.apex.clone(), signature);
+        Record::from_rdata(owner.clone(), TTL, RecordType::DNSSEC(DNSSECRecordType::RRSIG),
+                           RData::DNSSEC(DNSSECRData::SIG(sig)))
+    }
+
+    /// Add RRSIGs to every RRset among the records: the DNSKEY RRset is signed by the KSK and the
+    /// ZSK, everything else by the ZSK only, delegations aren't signed
+    fn sign(&self, mut records: Vec<Record>, signed_at: u32) -> Vec<Record> {
+        let mut rrsets: Vec<(Name, RecordType)> = vec![];
+        for r in &records {
+            let rrset = (r.name().clone(), r.rr_type());
+            if !rrsets.contains(&rrset) {
+                rrsets.push(rrset);
+            }
+        }
+        for (owner, record_type) in rrsets {
+            if self.is_delegation(&owner, record_type) {
+                continue;
+            }
+            let keys = if record_type == RecordType::DNSSEC(DNSSECRecordType::DNSKEY) { &self.keys[..2] } else { &self.keys[1..2] };
+            for key in keys {
+                records.push(self.rrsig(&owner, record_type, key, signed_at));
+            }
+        }
+        records
+    }
+
+    fn nsec(&self, owner: &Name) -> Record {
+        let next = self.records.range((Excluded(owner), Unbounded)).next().map_or(&self.apex, |(n, _)| n);
+        let mut types = self.types_at(owner);
+        types.push(RecordType::DNSSEC(DNSSECRecordType::NSEC));
+        Record::from_rdata(owner.clone(), TTL, RecordType::DNSSEC(DNSSECRecordType::NSEC),
+                           RData::DNSSEC(DNSSECRData::NSEC(NSEC::new(next.clone(), types))))
+    }
+
+    /// NSEC of the name that sorts right before the given one
+    fn covering_nsec(&self, name: &Name) -> Record {
+        let owner = self.records.range(..name.clone()).next_back().map_or(&self.apex, |(n, _)| n);
+        self.nsec(owner)
+    }
+
+    /// SHA-1 without salt and additional iterations, the parameters of every NSEC3 of the zone
+    fn nsec3_hash(name: &Name) -> Vec<u8> {
+        nsec3_hash(name, &[], 0)
+    }
+
+    /// Owners of the zone ordered by their hashes
+    fn hashed_owners(&self) -> Vec<(Vec<u8>, &Name)> {
+        let mut hashed: Vec<(Vec<u8>, &Name)> = self.records.keys().map(|n| (Zone::nsec3_hash(n), n)).collect();
+        hashed.sort();
+        hashed
+    }
+
+    /// NSEC3 of the `i`th owner. A short one ends right after its owner instead of at the next one.
+    fn nsec3(&self, hashed: &[(Vec<u8>, &Name)], i: usize, short: bool) -> Record {
+        let (ref hash, owner) = hashed[i];
+        let next = if short {
+            let mut next = hash.clone();
+            if let Some(last) = next.last_mut() {
+                *last = last.wrapping_add(1);
+            }
+            next
+        } else {
+            hashed[(i + 1) % hashed.len()].0.clone()
+        };
+        let nsec3 = NSEC3::new(Nsec3HashAlgorithm::SHA1, false, 0, vec![], next, self.types_at(owner));
+        Record::from_rdata(Name::from_labels(vec![base32hex(hash)]).append_domain(&self.apex), TTL,
+                           RecordType::DNSSEC(DNSSECRecordType::NSEC3), RData::DNSSEC(DNSSECRData::NSEC3(nsec3)))
+    }
+
+    /// Index of the owner whose hash matches the name, or of the one covering it if the name
+    /// doesn't exist
+    fn nsec3_index(hashed: &[(Vec<u8>, &Name)], name: &Name) -> usize {
+        let hash = Zone::nsec3_hash(name);
+        hashed.iter().rposition(|(h, _)| *h <= hash).unwrap_or(hashed.len() - 1)
+    }
+
+    fn nsec3_for(&self, name: &Name) -> Record {
+        let hashed = self.hashed_owners();
+        self.nsec3(&hashed, Zone::nsec3_index(&hashed, name), false)
+    }
+
+    fn nxdomain_proof(&self, name: &Name) -> Vec<Record> {
+        let wildcard = |encloser: &Name| Name::from_labels(vec!["*"]).append_domain(encloser);
+        let mut proof = match self.denial {
+            Denial::Nsec => vec![self.covering_nsec(name), self.covering_nsec(&wildcard(&self.apex))],
+            Denial::Nsec3 => {
+                // Closest encloser, the name right below it and the wildcard at it
+                let mut labels = name.num_labels() as usize;
+                while labels > self.apex.num_labels() as usize && !self.records.contains_key(&name.trim_to(labels - 1)) {
+                    labels -= 1;
+                }
+                let encloser = name.trim_to(labels - 1);
+                let hashed = self.hashed_owners();
+                let next_closer = Zone::nsec3_index(&hashed, &name.trim_to(labels));
+                [encloser.clone(), name.trim_to(labels), wildcard(&encloser)].iter()
+                    .map(|n| Zone::nsec3_index(&hashed, n))
+                    .map(|i| self.nsec3(&hashed, i, self.short_nsec3 && i == next_closer))
+                    .collect()
+            },
+        };
+        let mut owners = vec![];
+        proof.retain(|r| {
+            let new = !owners.contains(r.name());
+            owners.push(r.name().clone());
+            new
+        });
+        proof
+    }
+
+    fn nodata_proof(&self, name: &Name) -> Vec<Record> {
+        match self.denial {
+            Denial::Nsec => vec![self.nsec(name)],
+            Denial::Nsec3 => vec![self.nsec3_for(name)],
+        }
+    }
+
+    /// Response code, answer and authority section for the query. With `signed_at` the records
+    /// come signed at that time and denials carry their proofs.
+    pub fn lookup(&self, name: &Name, record_type: RecordType, signed_at: Option<u32>)
+                  -> (ResponseCode, Vec<Record>, Vec<Record>) {
+        self.lookup_answers(name, self.rrset(name, record_type), signed_at)
+    }
+
+    /// Same as `lookup`, as if the name had no records of the type. The proof of the NODATA is
+    /// the one of the zone, its type bitmap still lists the type.
+    pub fn lookup_hidden(&self, name: &Name, signed_at: Option<u32>) -> (ResponseCode, Vec<Record>, Vec<Record>) {
+        self.lookup_answers(name, vec![], signed_at)
+    }
+
+    fn lookup_answers(&self, name: &Name, answers: Vec<Record>, signed_at: Option<u32>)
+                      -> (ResponseCode, Vec<Record>, Vec<Record>) {
+        let (rcode, answers, proof) = if !self.records.contains_key(name) {
+            (ResponseCode::NXDomain, vec![], self.nxdomain_proof(name))
+        } else {
+            let proof = if answers.is_empty() { self.nodata_proof(name) } else { vec![] };
+            (ResponseCode::NoError, answers, proof)
+        };
+        let mut authority = vec![];
+        if answers.is_empty() {
+            authority = self.rrset(&self.apex, RecordType::SOA);
+        }
+        match signed_at {
+            Some(signed_at) => {
+                authority.extend(proof);
+                (rcode, self.sign(answers, signed_at), self.sign(authority, signed_at))
+            },
+            None => (rcode, answers, authority),
+        }
+    }
+}
+
+/// Zones served by one server
+pub struct Zones(pub Vec<Zone>);
+
+impl Zones {
+    /// The zones the probes query: `org.` signed with NSEC3 and a DNSKEY RRset large enough to be
+    /// fragmented, `dnssec-tools.org.` and `isc.org.` signed with NSEC, and a correct and a bogus
+    /// zone for every algorithm of the algorithm probe
+    pub fn testing() -> Self {
+        let mut org = Zone::new(&NSEC3_TESTING_ZONE, Denial::Nsec3);
+        org.add_keys(true, 1);
+        org.add_keys(false, 3);
+        let mut dnssec_tools = Zone::new(&TESTING_SERVER, Denial::Nsec);
+        let www = Name::from_labels(vec!["www"]).append_domain(&TESTING_SERVER);
+        dnssec_tools.add(&www, RecordType::A, RData::A(Ipv4Addr::new(192, 0, 2, 80)));
+        let isc = Zone::new(&NSEC_TESTING_ZONE, Denial::Nsec);
+        org.add_delegation(&dnssec_tools);
+        org.add_delegation(&isc);
+        let mut zones = vec![org, dnssec_tools, isc];
+        // Not delegated, no probe follows the chain of trust to them
+        for algorithm in &ALGORITHMS {
+            for bogus in &[false, true] {
+                let mut zone = Zone::signed_with(&algorithm_zone(&ALGORITHM_TESTING_ZONE, *algorithm, *bogus), Denial::Nsec,
+                                                 *algorithm);
+                zone.set_bogus(*bogus);
+                zones.push(zone);
+            }
+        }
+        Zones(zones)
+    }
+
+    /// The zone with the closest apex. DS records belong to the parent side of a delegation.
+    fn find(&self, name: &Name, parent_side: bool) -> Option<&Zone> {
+        self.0.iter()
+            .filter(|z| z.apex.zone_of(name) && !(parent_side && z.apex == *name))
+            .max_by_key(|z| z.apex.num_labels())
+    }
+}
+
+/// How a stand-in resolver deviates from a correct one. The default is a correct resolver that
+/// doesn't validate.
+#[derive(Debug, Clone, Default)]
+pub struct Behaviour {
+    /// Authoritative server: AA set, no recursion
+    pub authoritative: bool,
+    /// Set the AD bit on answers
+    pub validating: bool,
+    /// Clear the DO bit and drop all DNSSEC records
+    pub strip_dnssec: bool,
+    /// Drop NSEC and NSEC3 records from denials
+    pub strip_denial: bool,
+    /// Answer NODATA for these types, with the proof the zone has for the name, like a filter that
+    /// drops records and forwards the rest of the response
+    pub hide_types: Vec<RecordType>,
+    /// Answer DS queries from the child zone
+    pub ds_from_child: bool,
+    /// Replay signatures that expired long ago
+    pub stale_signatures: bool,
+    /// Answer without EDNS
+    pub no_edns: bool,
+    /// Reject queries for types it doesn't know
+    pub refuse_unknown_types: bool,
+    /// Never truncate UDP responses
+    pub ignore_payload_size: bool,
+    /// Lose UDP responses that would be fragmented
+    pub drop_fragmented: bool,
+    /// Lose all queries over UDP
+    pub no_udp: bool,
+    /// Lose all queries over TCP
+    pub no_tcp: bool,
+    /// Name of the server in the CHAOS class, which also determines its address in the answer to
+    /// the whoami name. Without one the server keeps both to itself.
+    pub identity: Option<&'static str>,
+    /// Address in the answer to the whoami name instead of the one that follows from the identity
+    pub egress: Option<Ipv4Addr>,
+    /// Return a server cookie along with the client cookie
+    pub cookies: bool,
+    /// Return cookies for a client cookie of its own, like a proxy that adds cookies
+    pub foreign_cookies: bool,
+    /// Answer queries with unknown EDNS versions instead of BADVERS
+    pub ignore_edns_version: bool,
+    /// Answer names that don't exist with the address of a search page
+    pub rewrite_nxdomain: bool,
+    /// Set the AD bit on signed answers without checking the signatures
+    pub trust_signatures: bool,
+    /// Algorithms a validating resolver doesn't implement, answers signed only with them are
+    /// insecure
+    pub unsupported_algorithms: Vec<Algorithm>,
+    /// Algorithms a validating resolver doesn't implement and fails answers signed with them
+    pub broken_algorithms: Vec<Algorithm>,
+}
+
+impl Behaviour {
+    pub fn validating() -> Self {
+        Behaviour { validating: true, ..Default::default() }
+    }
+}
+
+/// What a validating resolver makes of the records of an answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Validation {
+    Secure,
+    Insecure,
+    Bogus,
+}
+
+/// Check every signature among the records against the DNSKEY RRset of the zone, as far as the
+/// resolver implements the algorithm of the signature
+fn validate(zone: &Zone, behaviour: &Behaviour, records: &[Record]) -> Validation {
+    let keys: Vec<DNSKEY> = zone.rrset(&zone.apex, RecordType::DNSSEC(DNSSECRecordType::DNSKEY)).iter()
+        .filter_map(|r| match *r.rdata() {
+            RData::DNSSEC(DNSSECRData::DNSKEY(ref key)) => Some(key.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut validation = Validation::Insecure;
+    for r in records {
+        let sig = match *r.rdata() {
+            RData::DNSSEC(DNSSECRData::SIG(ref sig)) => sig,
+            _ => continue,
+        };
+        if behaviour.broken_algorithms.contains(&sig.algorithm()) {
+            return Validation::Bogus;
+        }
+        if behaviour.unsupported_algorithms.contains(&sig.algorithm()) {
+            continue;
+        }
+        let key = keys.iter().find(|k| k.algorithm() == sig.algorithm() && key_tag(k) == Some(sig.key_tag()));
+        match key {
+            Some(key) if behaviour.trust_signatures
+                || sig.sig() == &fake_signature(r.name(), sig.type_covered(), sig.sig_inception(), key)[..] => {
+                validation = Validation::Secure;
+            },
+            _ => return Validation::Bogus,
+        }
+    }
+    validation
+}
+
+fn is_denial(r: &Record) -> bool {
+    match *r.rdata() {
+        RData::DNSSEC(DNSSECRData::NSEC(_)) | RData::DNSSEC(DNSSECRData::NSEC3(_)) => true,
+        RData::DNSSEC(DNSSECRData::SIG(ref sig)) => matches!(sig.type_covered(),
+            RecordType::DNSSEC(DNSSECRecordType::NSEC) | RecordType::DNSSEC(DNSSECRecordType::NSEC3)),
+        _ => false,
+    }
+}
+
+fn answer(zones: &Zones, behaviour: &Behaviour, query: &Message) -> Message {
+    let mut response = Message::new();
+    response.set_id(query.id())
+        .set_message_type(MessageType::Response)
+        .set_op_code(query.op_code())
+        .set_authoritative(behaviour.authoritative)
+        .set_recursion_desired(query.recursion_desired())
+        .set_recursion_available(!behaviour.authoritative)
+        .add_queries(query.queries().to_vec());
+    let edns = query.edns().filter(|_| !behaviour.no_edns);
+    let dnssec_ok = edns.is_some_and(|e| e.dnssec_ok()) && !behaviour.strip_dnssec;
+    if let Some(query_edns) = edns {
+        let mut edns = Edns::new();
+        edns.set_max_payload(EDNS_PAYLOAD);
+        edns.set_dnssec_ok(dnssec_ok);
+        let client_cookie = query_edns.options().options().values()
+            .filter_map(|o| match *o {
+                EdnsOption::Unknown(COOKIE, ref data) if data.len() >= 8 => Some(data[..8].to_vec()),
+                _ => None,
+            })
+            .next();
+        if let Some(client_cookie) = client_cookie.filter(|_| behaviour.cookies || behaviour.foreign_cookies) {
+            let client_cookie = if behaviour.foreign_cookies { fake_digest(&client_cookie, 8) } else { client_cookie };
+            edns.set_option(EdnsOption::Unknown(COOKIE, [client_cookie, fake_digest(b"server", 8)].concat()));
+        }
+        let bad_version = query_edns.version() > 0 && !behaviour.ignore_edns_version;
+        if bad_version {
+            // BADVERS doesn't fit into the header, the upper bits go into the OPT record
+            edns.set_rcode_high(ResponseCode::BADVERS.high() as u8);
+        }
+        response.set_edns(edns);
+        if bad_version {
+            response.set_response_code(ResponseCode::BADVERS);
+            return response;
+        }
+    }
+
+    let q = match query.queries().first() {
+        Some(q) => q,
+        None => {
+            response.set_response_code(ResponseCode::FormErr);
+            return response;
+        },
+    };
+    if behaviour.refuse_unknown_types && matches!(q.query_type(), RecordType::Unknown(_)) {
+        response.set_response_code(ResponseCode::FormErr);
+        return response;
+    }
+    if q.query_class() == DNSClass::CH || *q.name() == *WHOAMI_NAME {
+        return answer_identity(behaviour, q, response);
+    }
+    let parent_side = q.query_type() == RecordType::DNSSEC(DNSSECRecordType::DS) && !behaviour.ds_from_child;
+    let zone = match zones.find(q.name(), parent_side) {
+        Some(zone) => zone,
+        None => {
+            response.set_response_code(ResponseCode::Refused);
+            return response;
+        },
+    };
+
+    // A validator checks the signed records whether or not the client asked for them
+    let validation = if behaviour.validating && !behaviour.authoritative {
+        let (_, answers, authority) = zone.lookup(q.name(), q.query_type(), Some(now()));
+        validate(zone, behaviour, &[answers, authority].concat())
+    } else {
+        Validation::Insecure
+    };
+    if validation == Validation::Bogus {
+        response.set_response_code(ResponseCode::ServFail);
+        return response;
+    }
+    let signed_at = if behaviour.stale_signatures { now() - 2 * VALIDITY } else { now() };
+    let signed_at = Some(signed_at).filter(|_| dnssec_ok);
+    let (mut rcode, mut answers, mut authority) = if behaviour.hide_types.contains(&q.query_type()) {
+        zone.lookup_hidden(q.name(), signed_at)
+    } else {
+        zone.lookup(q.name(), q.query_type(), signed_at)
+    };
+    if behaviour.rewrite_nxdomain && rcode == ResponseCode::NXDomain {
+        rcode = ResponseCode::NoError;
+        answers = vec![Record::from_rdata(q.name().clone(), 60, RecordType::A, RData::A(Ipv4Addr::new(192, 0, 2, 99)))];
+        authority.clear();
+    }
+    if behaviour.strip_dnssec {
+        answers.retain(|r| !matches!(r.rr_type(), RecordType::DNSSEC(_)));
+    }
+    if behaviour.strip_denial {
+        authority.retain(|r| !is_denial(r));
+    }
+    let authentic = validation == Validation::Secure && (dnssec_ok || query.authentic_data());
+    response.set_response_code(rcode)
+        .set_authentic_data(authentic)
+        .add_answers(answers)
+        .add_name_servers(authority);
+    response
+}
+
+/// Answer the CHAOS queries for the name of the server and the whoami name
+fn answer_identity(behaviour: &Behaviour, q: &Query, mut response: Message) -> Message {
+    let identity = match behaviour.identity {
+        Some(identity) => identity,
+        None => {
+            response.set_response_code(ResponseCode::Refused);
+            return response;
+        },
+    };
+    let chaos_names = [name("id.server."), name("hostname.bind.")];
+    let rdata = if q.query_class() == DNSClass::CH && q.query_type() == RecordType::TXT && chaos_names.contains(q.name()) {
+        RData::TXT(TXT::new(vec![identity.to_string()]))
+    } else if q.query_class() == DNSClass::IN && q.query_type() == RecordType::A && *q.name() == *WHOAMI_NAME {
+        RData::A(behaviour.egress.unwrap_or_else(|| Ipv4Addr::new(198, 51, 100, fake_digest(identity.as_bytes(), 1)[0])))
+    } else {
+        response.set_response_code(ResponseCode::Refused);
+        return response;
+    };
+    let mut record = Record::from_rdata(q.name().clone(), 0, q.query_type(), rdata);
+    record.set_dns_class(q.query_class());
+    response.add_answer(record);
+    response
+}
+
+/// Wire format of the response to a request, or nothing if the server doesn't answer
+fn respond(zones: &Zones, behaviour: &Behaviour, request: &[u8], udp: bool) -> Option<Vec<u8>> {
+    if (udp && behaviour.no_udp) || (!udp && behaviour.no_tcp) {
+        return None;
+    }
+    let query = Message::from_vec(request).ok()?;
+    let response = answer(zones, behaviour, &query);
+    let bytes = response.to_vec().ok()?;
+    if !udp {
+        return Some(bytes);
+    }
+    let payload = query.edns()
+        .filter(|_| !behaviour.no_edns)
+        .map_or(CLASSIC_PAYLOAD, |e| cmp::max(e.max_payload(), CLASSIC_PAYLOAD));
+    if !behaviour.ignore_payload_size && bytes.len() > payload as usize {
+        let mut truncated = response.truncate();
+        truncated.add_queries(query.queries().to_vec());
+        return truncated.to_vec().ok();
+    }
+    if behaviour.drop_fragmented && bytes.len() > UNFRAGMENTED_SIZE {
+        return None;
+    }
+    Some(bytes)
+}
+
+fn serve_udp(socket: &UdpSocket, zones: &Zones, behaviour: &Behaviour, stop: &AtomicBool) {
+    let mut buf = [0u8; 4096];
+    while !stop.load(Ordering::SeqCst) {
+        if let Ok((len, peer)) = socket.recv_from(&mut buf) {
+            if let Some(response) = respond(zones, behaviour, &buf[..len], true) {
+                let _ = socket.send_to(&response, peer);
+            }
+        }
+    }
+}
+
+/// Serve length prefixed messages until the client closes the connection
+fn serve_tcp_connection(mut stream: TcpStream, zones: &Zones, behaviour: &Behaviour, stop: &AtomicBool) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    loop {
+        let mut len = [0u8; 2];
+        match stream.read_exact(&mut len) {
+            Ok(()) => {},
+            Err(ref e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                if stop.load(Ordering::SeqCst) {
+                    return Ok(());
+                }
+                continue;
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        // The rest of the message may come in a later segment
+        let mut request = vec![0u8; (usize::from(len[0]) << 8) | usize::from(len[1])];
+        stream.set_read_timeout(None)?;
+        stream.read_exact(&mut request)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        if let Some(response) = respond(zones, behaviour, &request, false) {
+            stream.write_all(&[(response.len() >> 8) as u8, response.len() as u8])?;
+            stream.write_all(&response)?;
+        }
+    }
+}
+
+/// Loopback address for the next server, so that every server has its own
+fn next_loopback() -> Ipv4Addr {
+    static NEXT_HOST: AtomicUsize = AtomicUsize::new(0);
+    Ipv4Addr::new(127, 0, 0, (2 + NEXT_HOST.fetch_add(1, Ordering::SeqCst) % 250) as u8)
+}
+
+/// DNS server on a loopback address, UDP and TCP on the same port. Stops when dropped.
+pub struct Server {
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Server {
+    /// Authoritative server of the zones
+    pub fn authoritative(zones: Zones) -> Server {
+        Server::resolver(zones, Behaviour { authoritative: true, ..Default::default() })
+    }
+
+    /// Stand-in resolver answering from the zones as if it had resolved the names
+    pub fn resolver(zones: Zones, behaviour: Behaviour) -> Server {
+        let (udp, tcp) = (0..10)
+            .filter_map(|_| {
+                let udp = UdpSocket::bind((next_loopback(), 0)).ok()?;
+                let tcp = TcpListener::bind(udp.local_addr().ok()?).ok()?;
+                Some((udp, tcp))
+            })
+            .next()
+            .expect("No free port on the loopback");
+        let address = udp.local_addr().expect("Bound socket has an address");
+        udp.set_read_timeout(Some(POLL_INTERVAL)).expect("Setting a timeout should never fail.");
+        tcp.set_nonblocking(true).expect("Setting a socket non-blocking should never fail.");
+
+        let zones = Arc::new(zones);
+        let behaviour = Arc::new(behaviour);
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut threads = vec![];
+        {
+            let (zones, behaviour, stop) = (zones.clone(), behaviour.clone(), stop.clone());
+            threads.push(thread::spawn(move || serve_udp(&udp, &zones, &behaviour, &stop)));
+        }
+        let accepting = stop.clone();
+        threads.push(thread::spawn(move || {
+            while !accepting.load(Ordering::SeqCst) {
+                match tcp.accept() {
+                    Ok((stream, _)) => {
+                        let (zones, behaviour, stop) = (zones.clone(), behaviour.clone(), accepting.clone());
+                        thread::spawn(move || serve_tcp_connection(stream, &zones, &behaviour, &stop));
+                    },
+                    Err(_) => thread::sleep(POLL_INTERVAL),
+                }
+            }
+        }));
+        Server { address, stop, threads }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// UDP and TCP client handles to one server, sharing a reactor
+pub struct Clients {
+    pub core: Core,
+    pub udp: BasicClientHandle,
+    pub tcp: BasicClientHandle,
+}
+
+impl Clients {
+    /// Queries time out well after `RESPONSE_TIMEOUT`, `probe_server` gives up on them first
+    pub fn connect(address: SocketAddr) -> Clients {
+        let timeout = RESPONSE_TIMEOUT * 2;
+        let core = Core::new().expect("Reactor");
+        let (udp_stream, udp_stream_handle) = UdpClientConnection::new(address).and_then(|c| c.new_stream(&core.handle()))
+            .expect("UDP client");
+        let (tcp_stream, tcp_stream_handle) = TcpClientConnection::with_timeout(address, timeout)
+            .and_then(|c| c.new_stream(&core.handle()))
+            .expect("TCP client");
+        let udp = ClientFuture::with_timeout(udp_stream, udp_stream_handle, &core.handle(), timeout, None);
+        let tcp = ClientFuture::with_timeout(tcp_stream, tcp_stream_handle, &core.handle(), timeout, None);
+        Clients { core, udp, tcp }
+    }
+}
+
+#[test]
+fn generated_zones() {
+    let zones = Zones::testing();
+    assert_eq!(zones.find(&TESTING_SERVER, false).map(Zone::apex), Some(&*TESTING_SERVER));
+    assert_eq!(zones.find(&TESTING_SERVER, true).map(Zone::apex), Some(&*NSEC3_TESTING_ZONE));
+    assert!(zones.find(&name("example."), false).is_none());
+
+    let zone = zones.find(&TESTING_SERVER, false).unwrap();
+    let (rcode, answers, authority) = zone.lookup(&name("a.dnssec-tools.org."), RecordType::A, Some(now()));
+    assert_eq!(rcode, ResponseCode::NXDomain);
+    assert!(answers.is_empty());
+    let next: Vec<Name> = authority.iter()
+        .filter_map(|r| match *r.rdata() {
+            RData::DNSSEC(DNSSECRData::NSEC(ref nsec)) => Some(nsec.next_domain_name().clone()),
+            _ => None,
+        })
+        .collect();
+    // The apex NSEC covers both the name and the wildcard
+    assert_eq!(next, vec![name("ns.dnssec-tools.org.")]);
+    // The last NSEC points back to the apex
+    match *zone.nsec(&name("www.dnssec-tools.org.")).rdata() {
+        RData::DNSSEC(DNSSECRData::NSEC(ref nsec)) => assert_eq!(nsec.next_domain_name(), &*TESTING_SERVER),
+        _ => panic!("Not an NSEC"),
+    }
+}
+
+#[cfg(test)]
+use super::TestResult;
+
+/// How long `probe_server` waits for the probe. The clients time out later, so a query without
+/// response never shows up as a client error.
+#[cfg(test)]
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Run a probe against the server, a query without response counts as "No response". The TCP
+/// client doesn't time out on its own when the server keeps the connection open, hence the timer.
+/// Any client error is unexpected and fails the test.
+#[cfg(test)]
+pub fn probe_server<F, P>(server: &Server, f: F) -> TestResult
+    where F: FnOnce(&mut BasicClientHandle, &mut BasicClientHandle) -> P,
+          P: ::futures::Future<Item=TestResult> + 'static,
+          P::Error: ::std::fmt::Display
+{
+    use futures::Future;
+    use futures::sync::oneshot;
+    use tokio_core::reactor::Timeout;
+
+    let mut clients = Clients::connect(server.address());
+    let handle = clients.core.handle();
+    // Like in the runner, the probe runs on its own so that the timer doesn't drop its queries
+    let (sender, receiver) = oneshot::channel();
+    handle.spawn(f(&mut clients.udp, &mut clients.tcp).then(move |response| {
+        let _ = sender.send(response.map_err(|e| e.to_string()));
+        Ok(())
+    }));
+    let timer = Timeout::new(RESPONSE_TIMEOUT, &handle).expect("Timer");
+    let response = clients.core.run(receiver.map(Some).map_err(|_| ())
+                                        .select(timer.map(|_| None).map_err(|_| ())));
+    match response {
+        Ok((Some(Ok(result)), _)) => result,
+        Ok((Some(Err(e)), _)) => panic!("Unexpected client error: {}", e),
+        _ => TestResult::Fail("No response"),
+    }
+}
+
+/// Run a probe against a stand-in resolver serving the testing zones
+#[cfg(test)]
+pub fn probe<F, P>(behaviour: Behaviour, f: F) -> TestResult
+    where F: FnOnce(&mut BasicClientHandle, &mut BasicClientHandle) -> P,
+          P: ::futures::Future<Item=TestResult>
+{
+    probe_server(&Server::resolver(Zones::testing(), behaviour), f)
+}
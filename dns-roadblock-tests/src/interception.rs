@@ -0,0 +1,307 @@
+//! Interception of queries on the path to a resolver.
+//!
+//! Some networks redirect all traffic to port 53 to a resolver of their own, so a client that
+//! sends its queries to 8.8.8.8 talks to something else without noticing. Nothing in an answer
+//! says where it came from, the probes look for the traces an interceptor leaves instead:
+//!
+//! * An answer from a decoy address where no resolver runs.
+//! * The same server behind different resolver addresses. Servers tell their name in the CHAOS
+//!   class (`id.server`, `hostname.bind`), the address they resolve from is the answer to a whoami
+//!   name. Only both together count: sibling addresses of one operator share the name of the site
+//!   and a local stub may pass CHAOS queries on to its upstream, but they resolve on their own.
+//! * EDNS handled differently than the resolver would: a cookie ([RFC 7873](https://tools.ietf.org/html/rfc7873))
+//!   that isn't the one sent, or an unknown EDNS version that isn't rejected
+//!   ([RFC 6891, section 6.1.3](https://tools.ietf.org/html/rfc6891#section-6.1.3)).
+//!
+//! The comparison with other resolvers and the decoy is up to the runner, which probes all of
+//! them at once.
+
+use std::net::IpAddr;
+use std::process;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use trust_dns::op::{Edns, Message, MessageType, OpCode, Query, ResponseCode};
+use trust_dns::rr::{DNSClass, Name, RData, RecordType};
+use trust_dns::rr::rdata::opt::EdnsOption;
+
+use trust_dns_proto::DnsHandle;
+
+use futures::prelude::*;
+
+use super::TestResult;
+
+/// EDNS option code of cookies
+pub const COOKIE: u16 = 10;
+/// Failure of the cookie probe that only an interceptor causes
+pub const FOREIGN_COOKIE: &str = "Cookie of a different client";
+/// Length of the client cookie, the server cookie that follows it has 8 to 32 bytes
+const CLIENT_COOKIE_LEN: usize = 8;
+
+lazy_static! {
+    /// Names a server answers with its own name in the CHAOS class
+    static ref ID_SERVER: Name = Name::from_str("id.server.").expect("Name building should never fail.");
+    static ref HOSTNAME_BIND: Name = Name::from_str("hostname.bind.").expect("Name building should never fail.");
+}
+
+/// What a resolver tells about itself
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Identity {
+    /// Name of the server, `id.server` or `hostname.bind` in the CHAOS class
+    pub server: Option<String>,
+    /// Address the resolver sends its queries to authoritative servers from
+    pub egress: Option<IpAddr>,
+}
+
+fn chaos_query(name: Name) -> Message {
+    let mut query = Query::query(name, RecordType::TXT);
+    query.set_query_class(DNSClass::CH);
+    let mut msg = Message::new();
+    msg.add_query(query)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query);
+    msg
+}
+
+fn query_with_edns(name: Name, edns: Edns) -> Message {
+    let mut msg = Message::new();
+    msg.add_query(Query::query(name, RecordType::A))
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .set_edns(edns);
+    msg
+}
+
+fn txt_answer(msg: &Message) -> Option<String> {
+    if msg.response_code() != ResponseCode::NoError {
+        return None;
+    }
+    msg.answers().iter()
+        .filter_map(|r| match *r.rdata() {
+            RData::TXT(ref txt) => Some(txt.txt_data().concat()),
+            _ => None,
+        })
+        .next()
+}
+
+fn address_answer(msg: &Message) -> Option<IpAddr> {
+    msg.answers().iter()
+        .filter_map(|r| match *r.rdata() {
+            RData::A(ip) => Some(IpAddr::V4(ip)),
+            RData::AAAA(ip) => Some(IpAddr::V6(ip)),
+            _ => None,
+        })
+        .next()
+}
+
+/// Ask the resolver for its name and the address it resolves from. Servers that keep both to
+/// themselves are fine, the parts without an answer stay empty.
+pub fn query_identity<DH>(dns_handle: &mut DH, whoami: &Name) -> impl Future<Item=Identity, Error=DH::Error>
+    where DH: DnsHandle
+{
+    let mut whoami_query = Message::new();
+    whoami_query.add_query(Query::query(whoami.clone(), RecordType::A))
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true);
+    // None of the queries may be dropped before the client gives up on it, so they never fail
+    let id_server = dns_handle.send(chaos_query(ID_SERVER.clone()))
+        .then(|r| Ok::<_, DH::Error>(r.ok().as_ref().and_then(txt_answer)));
+    let hostname_bind = dns_handle.send(chaos_query(HOSTNAME_BIND.clone()))
+        .then(|r| Ok(r.ok().as_ref().and_then(txt_answer)));
+    let egress = dns_handle.send(whoami_query)
+        .then(|r| Ok(r.ok().as_ref().and_then(address_answer)));
+    id_server.join3(hostname_bind, egress)
+        .map(|(id_server, hostname_bind, egress)| Identity { server: id_server.or(hostname_bind), egress })
+}
+
+/// Whether the answers of a resolver come from somewhere else, given its identity, the identities
+/// of the other resolvers probed and whether a decoy address without a resolver answered
+pub fn interception_result(identity: Option<&Identity>, others: &[&Identity], decoy_answered: bool) -> TestResult {
+    if decoy_answered {
+        return TestResult::Fail("An address without a resolver answers");
+    }
+    match identity {
+        Some(i) if i.server.is_some() && i.egress.is_some() && others.contains(&i) => {
+            TestResult::Fail("Same server as another resolver")
+        },
+        _ => TestResult::Success,
+    }
+}
+
+/// Client cookie that differs between runs
+pub fn client_cookie() -> Vec<u8> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    let mut cookie = vec![];
+    for value in &[process::id(), nanos] {
+        cookie.extend_from_slice(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, *value as u8]);
+    }
+    cookie
+}
+
+fn response_cookie(msg: &Message) -> Option<&[u8]> {
+    msg.edns()?.options().options().values()
+        .filter_map(|o| match *o {
+            EdnsOption::Unknown(COOKIE, ref data) => Some(&data[..]),
+            _ => None,
+        })
+        .next()
+}
+
+fn cookie_result(msg: &Message, client_cookie: &[u8]) -> TestResult {
+    match response_cookie(msg) {
+        // Cookies are optional
+        None => TestResult::Success,
+        Some(cookie) if cookie.len() < CLIENT_COOKIE_LEN + 8 || cookie.len() > CLIENT_COOKIE_LEN + 32 => {
+            TestResult::Fail("Malformed cookie")
+        },
+        Some(cookie) if cookie[..CLIENT_COOKIE_LEN] != *client_cookie => TestResult::Fail(FOREIGN_COOKIE),
+        Some(_) => TestResult::Success,
+    }
+}
+
+/// [RFC 7873, section 5.2](https://tools.ietf.org/html/rfc7873#section-5.2)
+///
+/// A server that supports cookies returns the client cookie of the query together with its own.
+/// A proxy that sends queries with a cookie of its own returns that one.
+pub fn support_cookies<DH>(dns_handle: &mut DH, name: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    let cookie = client_cookie();
+    let mut edns = Edns::new();
+    edns.set_option(EdnsOption::Unknown(COOKIE, cookie.clone()));
+    dns_handle
+        .send(query_with_edns(name.clone(), edns))
+        .map(move |msg| cookie_result(&msg, &cookie))
+}
+
+fn edns_version_result(msg: &Message) -> TestResult {
+    // trust-dns decodes BADVERS as BADSIG, they share the code
+    match msg.response_code() {
+        ResponseCode::BADVERS | ResponseCode::BADSIG => TestResult::Success,
+        ResponseCode::NoError => TestResult::Fail("Unknown EDNS version answered"),
+        _ => TestResult::Fail("Unknown EDNS version not rejected with BADVERS"),
+    }
+}
+
+/// [RFC 6891, section 6.1.3](https://tools.ietf.org/html/rfc6891#section-6.1.3)
+///
+/// A query with an EDNS version the resolver doesn't implement must get BADVERS.
+pub fn rejects_unknown_edns_version<DH>(dns_handle: &mut DH, name: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    let mut edns = Edns::new();
+    edns.set_version(1);
+    dns_handle
+        .send(query_with_edns(name.clone(), edns))
+        .map(|msg| edns_version_result(&msg))
+}
+
+#[test]
+fn interception_evidence() {
+    let identity = |server: &str, egress: [u8; 4]| Identity { server: Some(server.to_string()), egress: Some(egress.into()) };
+    let (resolver, other) = (identity("resolver-1", [198, 51, 100, 1]), identity("resolver-2", [198, 51, 100, 2]));
+    assert_eq!(interception_result(Some(&resolver), &[&other], false), TestResult::Success);
+    assert_eq!(interception_result(Some(&resolver), &[&other, &resolver], false),
+               TestResult::Fail("Same server as another resolver"));
+    assert_eq!(interception_result(None, &[&other], true), TestResult::Fail("An address without a resolver answers"));
+    // A name alone isn't enough, siblings and stubs resolve from addresses of their own
+    let sibling = identity("resolver-1", [198, 51, 100, 3]);
+    assert_eq!(interception_result(Some(&resolver), &[&sibling], false), TestResult::Success);
+    let unknown_egress = Identity { egress: None, ..resolver.clone() };
+    assert_eq!(interception_result(Some(&unknown_egress), &[&unknown_egress], false), TestResult::Success);
+    let anonymous = Identity::default();
+    assert_eq!(interception_result(Some(&anonymous), &[&anonymous], false), TestResult::Success);
+
+    let cookie = client_cookie();
+    let with_cookie = |data: Vec<u8>| {
+        let mut edns = Edns::new();
+        edns.set_option(EdnsOption::Unknown(COOKIE, data));
+        let mut msg = Message::new();
+        msg.set_edns(edns);
+        msg
+    };
+    assert_eq!(cookie_result(&Message::new(), &cookie), TestResult::Success);
+    assert_eq!(cookie_result(&with_cookie([&cookie[..], &[1; 8]].concat()), &cookie), TestResult::Success);
+    assert_eq!(cookie_result(&with_cookie(vec![1; 16]), &cookie), TestResult::Fail(FOREIGN_COOKIE));
+    assert_eq!(cookie_result(&with_cookie(cookie.clone()), &cookie), TestResult::Fail("Malformed cookie"));
+}
+
+#[test]
+fn interception_probes() {
+    use fixture::{probe, Behaviour};
+    use super::{TESTING_SERVER, WHOAMI_NAME};
+
+    let correct = Behaviour::default;
+    assert_eq!(probe(correct(), |udp, _| support_cookies(udp, &TESTING_SERVER)), TestResult::Success);
+    assert_eq!(probe(Behaviour { cookies: true, ..correct() }, |udp, _| support_cookies(udp, &TESTING_SERVER)),
+               TestResult::Success);
+    assert_eq!(probe(Behaviour { foreign_cookies: true, ..correct() }, |_, tcp| support_cookies(tcp, &TESTING_SERVER)),
+               TestResult::Fail("Cookie of a different client"));
+    assert_eq!(probe(correct(), |udp, _| rejects_unknown_edns_version(udp, &TESTING_SERVER)), TestResult::Success);
+    assert_eq!(probe(Behaviour { ignore_edns_version: true, ..correct() },
+                     |udp, _| rejects_unknown_edns_version(udp, &TESTING_SERVER)),
+               TestResult::Fail("Unknown EDNS version answered"));
+
+    let identity_of = |behaviour| probe(behaviour, |udp, _| query_identity(udp, &WHOAMI_NAME).map(|identity| {
+        match (identity.server, identity.egress) {
+            (Some(ref server), Some(_)) if server == "ns1.example" => TestResult::Success,
+            (None, None) => TestResult::Fail("Anonymous"),
+            _ => TestResult::Fail("Wrong identity"),
+        }
+    }));
+    assert_eq!(identity_of(Behaviour { identity: Some("ns1.example"), ..correct() }), TestResult::Success);
+    assert_eq!(identity_of(correct()), TestResult::Fail("Anonymous"));
+}
+
+#[test]
+fn interception_across_resolvers() {
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+    use classification::Probe;
+    use fixture::{Behaviour, Server, Zones};
+    use runner::{run_all, Options, Policy, Target};
+
+    let resolver = |identity| Server::resolver(Zones::testing(), Behaviour { identity: Some(identity), ..Default::default() });
+    let (first, second, other) = (resolver("middlebox"), resolver("middlebox"), resolver("resolver.example"));
+    let targets: Vec<Target> = [&first, &second, &other].iter().map(|s| s.address().into()).collect();
+    let mut options = Options {
+        decoy: None,
+        policy: Policy { timeout: Duration::from_millis(500), retries: 0 },
+        ..Default::default()
+    };
+    let intercepted = |options: &Options| -> Vec<bool> {
+        run_all(&targets, options).unwrap().iter().map(|r| r.as_ref().unwrap().classification().intercepted).collect()
+    };
+    assert_eq!(intercepted(&options), vec![true, true, false]);
+
+    let decoy = Server::resolver(Zones::testing(), Behaviour::default());
+    options.decoy = Some(decoy.address().into());
+    assert_eq!(intercepted(&options), vec![true, true, true]);
+
+    options.decoy = None;
+    options.probes = vec![Probe::Udp, Probe::Interception];
+    let reports = run_all(&targets[2..], &options).unwrap();
+    let report = reports[0].as_ref().unwrap();
+    assert_eq!(report.run.get(Probe::Interception), Some(TestResult::Success));
+    assert_eq!(report.identity.as_ref().and_then(|i| i.server.clone()), Some("resolver.example".to_string()));
+
+    // A stub passing CHAOS queries on to its upstream and a sibling of the upstream with the same
+    // site name both resolve from addresses of their own
+    let upstream = resolver("resolver.example");
+    let stub = Server::resolver(Zones::testing(), Behaviour {
+        identity: Some("resolver.example"),
+        egress: Some(Ipv4Addr::new(192, 0, 2, 53)),
+        ..Default::default()
+    });
+    let sibling = Server::resolver(Zones::testing(), Behaviour {
+        identity: Some("resolver.example"),
+        egress: Some(Ipv4Addr::new(198, 51, 100, 200)),
+        ..Default::default()
+    });
+    let targets: Vec<Target> = [&stub, &upstream, &sibling].iter().map(|s| s.address().into()).collect();
+    let reports = run_all(&targets, &options).unwrap();
+    let results: Vec<Option<TestResult>> = reports.iter().map(|r| r.as_ref().unwrap().run.get(Probe::Interception)).collect();
+    assert_eq!(results, vec![Some(TestResult::Success); 3]);
+}
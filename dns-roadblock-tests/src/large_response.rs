@@ -0,0 +1,134 @@
+//! Large responses.
+//!
+//! Signed answers easily exceed 512 bytes and often the MTU, so a network that can carry DNSSEC
+//! must either pass fragmented UDP responses or let the client fall back to TCP after a truncated
+//! one. Both probes ask for an RRset with signatures that is expected to be large, e.g. the DNSKEY
+//! RRset of a TLD.
+
+use trust_dns::op::{Message, ResponseCode};
+use trust_dns::rr::{Name, RecordType};
+
+use trust_dns_proto::DnsHandle;
+
+use futures::prelude::*;
+
+use signed_records::{dnssec_query_with_payload, rrsigs, EDNS_PAYLOAD};
+use super::TestResult;
+
+/// Payload size of plain DNS without EDNS, responses above it must be truncated
+pub const CLASSIC_PAYLOAD: u16 = 512;
+
+/// Size of the message on the wire, as far as it can be told from the parsed one
+pub fn message_size(msg: &Message) -> usize {
+    msg.to_vec().map(|v| v.len()).unwrap_or(0)
+}
+
+/// Check that the response is complete: not truncated, the RRset present and signed
+fn complete_answer(msg: &Message, record_type: RecordType) -> Result<(), &'static str> {
+    if msg.response_code() != ResponseCode::NoError {
+        return Err("Error response code");
+    }
+    if msg.truncated() {
+        return Err("Truncated response");
+    }
+    if !msg.answers().iter().any(|r| r.rr_type() == record_type) {
+        return Err("No records of the queried type");
+    }
+    if !rrsigs(msg.answers()).any(|s| s.type_covered() == record_type) {
+        return Err("No RRSIG covering the answer");
+    }
+    Ok(())
+}
+
+fn large_udp_result(msg: &Message, record_type: RecordType) -> TestResult {
+    if msg.truncated() {
+        return TestResult::Fail("Truncated although the EDNS buffer is large enough");
+    }
+    if msg.edns().is_none() {
+        return TestResult::Fail("No EDNS option");
+    }
+    match complete_answer(msg, record_type) {
+        Ok(()) => TestResult::Success,
+        Err(reason) => TestResult::Fail(reason),
+    }
+}
+
+fn tcp_fallback_result(udp: &Message, tcp: &Message, record_type: RecordType) -> TestResult {
+    if !udp.truncated() {
+        if message_size(udp) > CLASSIC_PAYLOAD as usize {
+            return TestResult::Fail("Response exceeds the announced buffer size");
+        }
+        return TestResult::Fail("Response fits into 512 bytes, truncation not tested");
+    }
+    match complete_answer(tcp, record_type) {
+        Ok(()) => TestResult::Success,
+        Err(_) if tcp.truncated() => TestResult::Fail("Truncated over TCP"),
+        Err(reason) => TestResult::Fail(reason),
+    }
+}
+
+/// A large signed response over UDP with a large EDNS buffer. Responses above 1472 bytes don't fit
+/// into a single packet on Ethernet and many firewalls drop the fragments, in which case the query
+/// times out.
+pub fn support_large_udp<DH>(dns_handle: &mut DH, name: &Name, record_type: RecordType)
+                             -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    dns_handle
+        .send(dnssec_query_with_payload(name.clone(), record_type, EDNS_PAYLOAD))
+        .map(move |msg| large_udp_result(&msg, record_type))
+}
+
+/// The same response with a 512 bytes buffer must come truncated over UDP (TC bit) and complete
+/// over TCP.
+pub fn support_tcp_fallback<U, T>(udp_handle: &mut U, tcp_handle: &mut T, name: &Name, record_type: RecordType)
+                                  -> impl Future<Item=TestResult, Error=U::Error>
+    where U: DnsHandle,
+          T: DnsHandle<Error=U::Error>
+{
+    let udp = udp_handle.send(dnssec_query_with_payload(name.clone(), record_type, CLASSIC_PAYLOAD));
+    let tcp = tcp_handle.send(dnssec_query_with_payload(name.clone(), record_type, EDNS_PAYLOAD));
+    // Wait for both responses even if one of the queries fails, the client doesn't cope with
+    // queries dropped before it gives up on them
+    udp.then(Ok::<_, U::Error>)
+        .join(tcp.then(Ok))
+        .and_then(move |(udp, tcp)| Ok(tcp_fallback_result(&udp?, &tcp?, record_type)))
+}
+
+#[test]
+fn truncation_and_fallback() {
+    let truncated = {
+        let mut msg = Message::new();
+        msg.set_truncated(true);
+        msg
+    };
+    let empty = Message::new();
+    assert_eq!(tcp_fallback_result(&empty, &empty, RecordType::A),
+               TestResult::Fail("Response fits into 512 bytes, truncation not tested"));
+    assert_eq!(tcp_fallback_result(&truncated, &truncated, RecordType::A), TestResult::Fail("Truncated over TCP"));
+    assert_eq!(tcp_fallback_result(&truncated, &empty, RecordType::A), TestResult::Fail("No records of the queried type"));
+    assert_eq!(large_udp_result(&truncated, RecordType::A),
+               TestResult::Fail("Truncated although the EDNS buffer is large enough"));
+}
+
+#[test]
+fn large_response_probes() {
+    use trust_dns::rr::rdata::DNSSECRecordType;
+    use fixture::{probe, Behaviour};
+    use super::LARGE_RESPONSE_ZONE;
+
+    let correct = Behaviour::default;
+    let dnskey = RecordType::DNSSEC(DNSSECRecordType::DNSKEY);
+    assert_eq!(probe(correct(), |udp, _| support_large_udp(udp, &LARGE_RESPONSE_ZONE, dnskey)), TestResult::Success);
+    assert_eq!(probe(Behaviour { drop_fragmented: true, ..correct() }, |udp, _| support_large_udp(udp, &LARGE_RESPONSE_ZONE, dnskey)),
+               TestResult::Fail("No response"));
+    assert_eq!(probe(Behaviour { no_edns: true, ..correct() }, |udp, _| support_large_udp(udp, &LARGE_RESPONSE_ZONE, dnskey)),
+               TestResult::Fail("Truncated although the EDNS buffer is large enough"));
+
+    assert_eq!(probe(correct(), |udp, tcp| support_tcp_fallback(udp, tcp, &LARGE_RESPONSE_ZONE, dnskey)), TestResult::Success);
+    assert_eq!(probe(Behaviour { ignore_payload_size: true, ..correct() },
+                     |udp, tcp| support_tcp_fallback(udp, tcp, &LARGE_RESPONSE_ZONE, dnskey)),
+               TestResult::Fail("Response exceeds the announced buffer size"));
+    assert_eq!(probe(Behaviour { no_tcp: true, ..correct() }, |udp, tcp| support_tcp_fallback(udp, tcp, &LARGE_RESPONSE_ZONE, dnskey)),
+               TestResult::Fail("No response"));
+}
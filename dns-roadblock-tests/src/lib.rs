@@ -0,0 +1,160 @@
+//! Tests of DNS resolvers on the path to the Internet, following
+//! [RFC 8027](https://tools.ietf.org/html/rfc8027).
+//!
+//! Each probe is a function generic over `DnsHandle` returning a future of `TestResult`. The
+//! `runner` module runs them against a resolver and the results of a full run are turned into a
+//! `Classification` of the resolver together with the recommended way of using it.
+
+#[macro_use] extern crate failure;
+extern crate futures;
+extern crate tokio_core;
+extern crate trust_dns;
+#[cfg(feature = "dns-over-tls")]
+extern crate trust_dns_native_tls;
+extern crate trust_dns_proto;
+#[macro_use] extern crate lazy_static;
+
+use std::str::FromStr;
+
+use trust_dns::rr::{Name, RecordType};
+use trust_dns::op::{Edns, Message, Query};
+use trust_dns::rr::rdata::opt::{EdnsOption, EdnsCode};
+
+use trust_dns_proto::DnsHandle;
+
+use futures::prelude::*;
+
+pub mod algorithms;
+pub mod classification;
+pub mod denial;
+#[cfg(test)]
+mod fixture;
+pub mod interception;
+pub mod large_response;
+pub mod nxdomain;
+pub mod runner;
+pub mod signed_records;
+pub mod unknown_type;
+
+lazy_static! {
+    pub static ref TESTING_SERVER: Name = Name::from_str("dnssec-tools.org.")
+                                            .expect("Name building should never fail.");
+    /// Zone signed with NSEC records
+    pub static ref NSEC_TESTING_ZONE: Name = Name::from_str("isc.org.")
+                                            .expect("Name building should never fail.");
+    /// Zone signed with NSEC3 records
+    pub static ref NSEC3_TESTING_ZONE: Name = Name::from_str("org.")
+                                            .expect("Name building should never fail.");
+    /// Zone whose signed DNSKEY RRset doesn't fit into 512 bytes
+    pub static ref LARGE_RESPONSE_ZONE: Name = Name::from_str("org.")
+                                            .expect("Name building should never fail.");
+    /// Zone with zones signed with every algorithm below it, see `algorithms`
+    pub static ref ALGORITHM_TESTING_ZONE: Name = Name::from_str("algorithms.dnssec-tools.org.")
+                                            .expect("Name building should never fail.");
+    /// Name under which nothing exists
+    pub static ref ABSENT_ZONE: Name = Name::from_str("absent.dnssec-tools.org.")
+                                            .expect("Name building should never fail.");
+    /// Name whose A record is the address of the resolver asking for it
+    pub static ref WHOAMI_NAME: Name = Name::from_str("whoami.akamai.net.")
+                                            .expect("Name building should never fail.");
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestResult {
+    Success,
+    Fail(&'static str),
+}
+
+/// [RFC 8027, section 3.1.1 and 3.1.2](https://tools.ietf.org/html/rfc8027#section-3.1.1)
+pub fn support_simple_answers<DH>(dns_handle: &mut DH, name: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    dns_handle
+        .lookup(Query::query(name.clone(), RecordType::A))
+        .map(|_| TestResult::Success)
+}
+
+/// [RFC 8027, section 3.1.3](https://tools.ietf.org/html/rfc8027#section-3.1.3)
+///
+/// sth...
+pub fn support_edns0<DH>(dns_handle: &mut DH, name: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    // Create a query
+    let query = Query::query(name.clone(), RecordType::A);
+    // Create an EDNS struct
+    let mut edns = Edns::new();
+    let v = vec![];
+    edns.set_option(EdnsOption::from((EdnsCode::Zero, &v[..])));
+    // Finally, assemble a message
+    let mut msg = Message::new();
+    msg.add_query(query);
+    msg.set_edns(edns);
+
+    dns_handle
+        .send(msg)
+        .map(|msg| {
+            if let Some(edns) = msg.edns() {
+                if edns.version() == 0 {
+                    TestResult::Success
+                } else {
+                    TestResult::Fail("Wrong EDNS option")
+                }
+            } else {
+                TestResult::Fail("No EDNS option")
+            }
+        })
+}
+
+
+/// [RFC 8027, section 3.1.4](https://tools.ietf.org/html/rfc8027#section-3.1.4)
+///
+/// This function implements [RFC 8027, section 3.1.4](https://tools.ietf.org/html/rfc8027#section-3.1.4)
+/// which tests resolver for DO bit support. (DO stands for DNSSEC Ok and is defined in
+/// [RFC 6891, section 6.1.4](https://tools.ietf.org/html/rfc6891#section-6.1.4).
+pub fn support_do_bit<DH>(dns_handle: &mut DH, name: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    // Create a query
+    let query = Query::query(name.clone(), RecordType::A);
+    // Create an EDNS struct
+    let mut edns = Edns::new();
+    edns.set_dnssec_ok(true);
+    // Finally, assemble a message
+    let mut msg = Message::new();
+    msg.add_query(query);
+    msg.set_edns(edns);
+    dns_handle
+        .send(msg)
+        .map(|msg| {
+            if let Some(edns) = msg.edns() {
+                if edns.dnssec_ok() {
+                    TestResult::Success
+                } else {
+                    TestResult::Fail("DO not set")
+                }
+            } else {
+                TestResult::Fail("No EDNS option")
+            }
+        })
+}
+
+#[test]
+fn basic_probes() {
+    use fixture::{probe, Behaviour};
+
+    let correct = Behaviour::default;
+    assert_eq!(probe(correct(), |udp, _| support_simple_answers(udp, &TESTING_SERVER)), TestResult::Success);
+    assert_eq!(probe(correct(), |_, tcp| support_simple_answers(tcp, &TESTING_SERVER)), TestResult::Success);
+    assert_eq!(probe(Behaviour { no_udp: true, ..correct() }, |udp, _| support_simple_answers(udp, &TESTING_SERVER)),
+               TestResult::Fail("No response"));
+    assert_eq!(probe(Behaviour { no_tcp: true, ..correct() }, |_, tcp| support_simple_answers(tcp, &TESTING_SERVER)),
+               TestResult::Fail("No response"));
+
+    assert_eq!(probe(correct(), |udp, _| support_edns0(udp, &TESTING_SERVER)), TestResult::Success);
+    assert_eq!(probe(Behaviour { no_edns: true, ..correct() }, |udp, _| support_edns0(udp, &TESTING_SERVER)),
+               TestResult::Fail("No EDNS option"));
+    assert_eq!(probe(correct(), |udp, _| support_do_bit(udp, &TESTING_SERVER)), TestResult::Success);
+    assert_eq!(probe(Behaviour { strip_dnssec: true, ..correct() }, |udp, _| support_do_bit(udp, &TESTING_SERVER)),
+               TestResult::Fail("DO not set"));
+}
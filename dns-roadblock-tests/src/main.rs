@@ -1,151 +1,372 @@
-extern crate futures;
-extern crate tokio_core;
+extern crate dns_roadblock_tests;
+extern crate docopt;
+#[macro_use] extern crate serde_derive;
+#[macro_use] extern crate serde_json;
 extern crate trust_dns;
-extern crate trust_dns_proto;
-extern crate failure;
 //#[macro_use] extern crate failure_derive;
-#[macro_use] extern crate lazy_static;
 
-use std::env;
-use std::str::FromStr;
+use std::process;
+use std::time::Duration;
 
-use trust_dns::client::{ClientConnection, ClientFuture};
-use trust_dns::udp::UdpClientConnection;
-use trust_dns::tcp::TcpClientConnection;
-use trust_dns::rr::{Name, RecordType};
-use trust_dns::op::{Edns, Message, Query};
-use trust_dns::rr::rdata::opt::{EdnsOption, EdnsCode};
+use docopt::Docopt;
+use serde_json::Value;
+use trust_dns::rr::Name;
 
-use trust_dns_proto::DnsHandle;
+use dns_roadblock_tests::TestResult;
+use dns_roadblock_tests::algorithms::AlgorithmSupport;
+use dns_roadblock_tests::classification::{Category, Classification, Probe};
+use dns_roadblock_tests::runner::{run_all, Options, Policy, Report, Target, Transport, TLS_SUPPORTED};
 
-use failure::Error;
-use futures::prelude::*;
-use tokio_core::reactor::{Core};
+const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
+const USAGE: &str = "
+dns-roadblock-tests - Check DNS resolvers for DNSSEC roadblocks (RFC 8027)
 
-lazy_static! {
-    static ref TESTING_SERVER: Name = Name::from_str("dnssec-tools.org.")
-                                            .expect("Name building should never fail.");
+Usage:
+  dns-roadblock-tests [options] [<resolver>...]
+  dns-roadblock-tests (-h | --help)
+  dns-roadblock-tests --version
+
+Options:
+  -h --help             Show this screen.
+  --version             Show version.
+  --domain <name>       Name in a signed zone for the DNSSEC record probes [default: dnssec-tools.org.]
+  --nsec-zone <name>    Zone signed with NSEC records [default: isc.org.]
+  --nsec3-zone <name>   Zone signed with NSEC3 records [default: org.]
+  --large-zone <name>   Zone whose signed DNSKEY RRset doesn't fit into 512 bytes [default: org.]
+  --algorithm-zone <name>
+                        Zone with zones signed with each algorithm below it, alg-<number> and
+                        alg-<number>-bogus [default: algorithms.dnssec-tools.org.]
+  --absent-zone <name>  Zone without any names, for NXDOMAIN rewriting [default: absent.dnssec-tools.org.]
+  --whoami <name>       Name whose answer is the address of the resolver [default: whoami.akamai.net.]
+  --decoy <address>     Address without a resolver, an answer from it means interception, none to
+                        skip it [default: 192.0.2.1]
+  --transport <list>    Comma separated transports: udp, tcp, tls [default: udp,tcp]
+  --tls-name <name>     Name in the certificate of the resolvers, needed for tls.
+  --timeout <ms>        How long each probe waits for its response in milliseconds [default: 5000]
+  --retries <n>         How many times a probe without a response is repeated [default: 1]
+  --policy <list>       Comma separated timeouts and retries of single probes, e.g.
+                        large-udp=10000/0 or tcp=2000 (the default retries).
+  --tests <list>        Comma separated probes to run [default: all]
+  --json                Print a JSON report instead of a table.
+
+Resolvers are IP addresses, optionally with a port. Without any, 127.0.0.1, 8.8.8.8 and 1.1.1.1
+are probed. All resolvers are probed at once, a probe that gets no response fails as timed out.
+
+Probes: udp, tcp, tls, edns0, do-bit, rrsig, dnskey, ds, nsec-nxdomain, nsec-nodata, nsec3-nxdomain,
+nsec3-nodata, unknown-type, large-udp, tcp-fallback, validation, algorithms,
+nxdomain-rewriting, interception, cookie, edns-version
+
+Exit status, from the least to the most severe. With several resolvers it is the most severe one.
+  0  The resolver validates or passes DNSSEC records through.
+  1  Invalid arguments or nothing could be probed.
+  2  Partial DNSSEC support, or the answers come from an interceptor.
+  3  DNS works, DNSSEC doesn't.
+  4  No answers at all.
+  5  The probes couldn't run against the resolver.
+";
+
+#[derive(Debug, Deserialize)]
+struct Args {
+    arg_resolver: Vec<String>,
+    flag_domain: String,
+    flag_nsec_zone: String,
+    flag_nsec3_zone: String,
+    flag_large_zone: String,
+    flag_algorithm_zone: String,
+    flag_absent_zone: String,
+    flag_whoami: String,
+    flag_decoy: String,
+    flag_transport: String,
+    flag_tls_name: Option<String>,
+    flag_timeout: u64,
+    flag_retries: u32,
+    flag_policy: Option<String>,
+    flag_tests: String,
+    flag_json: bool,
 }
 
-#[derive(Debug)]
-pub enum TestResult {
-    Success,
-    Fail(&'static str),
+fn parse_name(name: &str) -> Result<Name, String> {
+    name.parse().map_err(|_| format!("Invalid domain name {}", name))
 }
 
-/// [RFC 8027, section 3.1.1 and 3.1.2](https://tools.ietf.org/html/rfc8027#section-3.1.1)
-pub fn support_simple_answers<DH>(dns_handle: &mut DH) -> impl Future<Item=TestResult, Error=DH::Error>
-    where DH: DnsHandle
+fn parse_list<T, F>(list: &str, parse: F) -> Result<Vec<T>, String>
+    where F: Fn(&str) -> Result<T, String>
 {
-    dns_handle
-        .lookup(Query::query(TESTING_SERVER.clone(), RecordType::A))
-        .map(|_| TestResult::Success)
+    list.split(',').map(str::trim).filter(|s| !s.is_empty()).map(parse).collect()
 }
 
-/// [RFC 8027, section 3.1.3](https://tools.ietf.org/html/rfc8027#section-3.1.3)
-///
-/// sth...
-pub fn support_edns0<DH>(dns_handle: &mut DH) -> impl Future<Item=TestResult, Error=DH::Error>
-    where DH: DnsHandle
-{
-    // Create a query
-    let query = Query::query(TESTING_SERVER.clone(), RecordType::A);
-    // Create an EDNS struct
-    let mut edns = Edns::new();
-    let v = vec![];
-    edns.set_option(EdnsOption::from((EdnsCode::Zero, &v[..])));
-    // Finally, assemble a message
-    let mut msg = Message::new();
-    msg.add_query(query);
-    msg.set_edns(edns);
-
-    dns_handle
-        .send(msg)
-        .map(|msg| {
-            if let Some(edns) = msg.edns() {
-                if edns.version() == 0 {
-                    TestResult::Success
-                } else {
-                    TestResult::Fail("Wrong EDNS option")
-                }
-            } else {
-                TestResult::Fail("No EDNS option")
-            }
-        })
+/// `probe=timeout[/retries]`, the retries default to the ones of the default policy
+fn parse_policy(policy: &str, default: Policy) -> Result<(Probe, Policy), String> {
+    let invalid = || format!("Invalid probe policy {}", policy);
+    let (probe, rest) = policy.split_at(policy.find('=').ok_or_else(invalid)?);
+    if probe.is_empty() {
+        return Err(invalid());
+    }
+    let probe = Probe::from_name(probe).ok_or_else(|| format!("Unknown probe {}", probe))?;
+    let mut parts = rest[1..].splitn(2, '/');
+    let timeout = parts.next().and_then(|t| t.parse().ok()).ok_or_else(invalid)?;
+    let retries = match parts.next() {
+        Some(r) => r.parse().map_err(|_| invalid())?,
+        None => default.retries,
+    };
+    Ok((probe, Policy { timeout: Duration::from_millis(timeout), retries }))
+}
+
+fn parse_args(args: &Args) -> Result<(Vec<Target>, Options), String> {
+    let targets = if args.arg_resolver.is_empty() {
+        vec!["127.0.0.1".to_string(), "8.8.8.8".to_string(), "1.1.1.1".to_string()]
+    } else {
+        args.arg_resolver.clone()
+    };
+    let targets = targets.iter().map(|t| t.parse()).collect::<Result<Vec<Target>, String>>()?;
+    let transports = parse_list(&args.flag_transport, |t| t.parse())?;
+    if transports.is_empty() {
+        return Err("No transport selected".to_string());
+    }
+    if transports.contains(&Transport::Tls) {
+        if !TLS_SUPPORTED {
+            return Err("DNS over TLS is not supported, build with the dns-over-tls feature".to_string());
+        }
+        if args.flag_tls_name.is_none() {
+            return Err("DNS over TLS needs --tls-name".to_string());
+        }
+    }
+    let probes = if args.flag_tests == "all" {
+        Probe::all().to_vec()
+    } else {
+        parse_list(&args.flag_tests, |p| Probe::from_name(p).ok_or_else(|| format!("Unknown probe {}", p)))?
+    };
+    let policy = Policy { timeout: Duration::from_millis(args.flag_timeout), retries: args.flag_retries };
+    let probe_policies = match args.flag_policy {
+        Some(ref list) => parse_list(list, |p| parse_policy(p, policy))?.into_iter().collect(),
+        None => Default::default(),
+    };
+    let decoy = match args.flag_decoy.as_str() {
+        "none" => None,
+        decoy => Some(decoy.parse()?),
+    };
+    let options = Options {
+        domain: parse_name(&args.flag_domain)?,
+        nsec_zone: parse_name(&args.flag_nsec_zone)?,
+        nsec3_zone: parse_name(&args.flag_nsec3_zone)?,
+        large_response_zone: parse_name(&args.flag_large_zone)?,
+        algorithm_zone: parse_name(&args.flag_algorithm_zone)?,
+        absent_zone: parse_name(&args.flag_absent_zone)?,
+        transports,
+        tls_name: args.flag_tls_name.clone(),
+        whoami_name: parse_name(&args.flag_whoami)?,
+        decoy,
+        policy,
+        probe_policies,
+        probes,
+    };
+    Ok((targets, options))
 }
 
+/// Exit status when nothing was probed, the others are ordered by severity
+const EXIT_NOT_RUN: i32 = 1;
+const EXIT_RESOLVER_ERROR: i32 = 5;
 
-/// [RFC 8027, section 3.1.4](https://tools.ietf.org/html/rfc8027#section-3.1.4)
-///
-/// This function implements [RFC 8027, section 3.1.4](https://tools.ietf.org/html/rfc8027#section-3.1.4)
-/// which tests resolver for DO bit support. (DO stands for DNSSEC Ok and is defined in
-/// [RFC 6891, section 6.1.4](https://tools.ietf.org/html/rfc6891#section-6.1.4).
-pub fn support_do_bit<DH>(dns_handle: &mut DH) -> impl Future<Item=TestResult, Error=DH::Error>
-    where DH: DnsHandle
-{
-    // Create a query
-    let query = Query::query(TESTING_SERVER.clone(), RecordType::A);
-    // Create an EDNS struct
-    let mut edns = Edns::new();
-    edns.set_dnssec_ok(true);
-    // Finally, assemble a message
-    let mut msg = Message::new();
-    msg.add_query(query);
-    msg.set_edns(edns);
-    dns_handle
-        .send(msg)
-        .map(|msg| {
-            if let Some(edns) = msg.edns() {
-                if edns.dnssec_ok() {
-                    TestResult::Success
-                } else {
-                    TestResult::Fail("DO not set")
-                }
-            } else {
-                TestResult::Fail("No EDNS option")
-            }
-        })
+fn exit_code(classification: &Classification) -> i32 {
+    match classification.category {
+        // Whatever answers may be fine, but it is not the resolver that was asked for
+        Category::Validating | Category::DnssecAware if classification.intercepted => 2,
+        Category::Validating | Category::DnssecAware => 0,
+        Category::Partial => 2,
+        Category::DnssecBroken => 3,
+        Category::NoDns => 4,
+    }
 }
 
-fn run_tests(address: std::net::SocketAddr) -> Result<(), Error> {
-    // create connections
-    let udp_conn = UdpClientConnection::new(address).unwrap();
-    let tcp_conn = TcpClientConnection::new(address).unwrap();
+fn print_table(report: &Report) {
+    println!("Resolver {}", report.target);
+    for observation in &report.observations {
+        let result = match observation.result {
+            TestResult::Success => "ok".to_string(),
+            TestResult::Fail(reason) => format!("FAIL: {}", reason),
+        };
+        println!("  {:<15} {:<4} {}", observation.probe, observation.transport, result);
+    }
+    if !report.algorithms.is_empty() {
+        println!("  Algorithms:");
+        for (algorithm, support) in &report.algorithms {
+            let support = match support {
+                Some(AlgorithmSupport::Breaks(reason)) => format!("breaks: {}", reason),
+                Some(support) => support.name().to_string(),
+                None => "unknown".to_string(),
+            };
+            println!("    {:<15} {}", algorithm.to_str(), support);
+        }
+    }
+    if let Some(ref identity) = report.identity {
+        let unknown = || "unknown".to_string();
+        println!("  Server: {}, resolving from {}", identity.server.clone().unwrap_or_else(unknown),
+                 identity.egress.map(|e| e.to_string()).unwrap_or_else(unknown));
+    }
+    println!("  Classification: {}", report.classification());
+}
 
-    // instantiate tokio.rs reactor
-    let mut reactor = Core::new().unwrap();
-    let handle = &reactor.handle();
+fn report_json(report: &Report) -> Value {
+    let classification = report.classification();
+    let results: Vec<Value> = report.observations.iter()
+        .map(|o| match o.result {
+            TestResult::Success => json!({
+                "probe": o.probe.name(),
+                "transport": o.transport.name(),
+                "result": "success",
+            }),
+            TestResult::Fail(reason) => json!({
+                "probe": o.probe.name(),
+                "transport": o.transport.name(),
+                "result": "fail",
+                "reason": reason,
+            }),
+        })
+        .collect();
+    let identity = report.identity.as_ref().map(|i| json!({
+        "server": i.server,
+        "egress": i.egress.map(|e| e.to_string()),
+    }));
+    let algorithms: Vec<Value> = report.algorithms.iter()
+        .map(|(algorithm, support)| json!({
+            "algorithm": algorithm.to_str(),
+            "number": u8::from(*algorithm),
+            "support": support.map(|s| s.name()),
+            "reason": match support {
+                Some(AlgorithmSupport::Breaks(reason)) => Some(reason),
+                _ => None,
+            },
+        }))
+        .collect();
+    json!({
+        "resolver": report.target.to_string(),
+        "category": classification.category.name(),
+        "mitigation": classification.mitigation.name(),
+        "intercepted": classification.intercepted,
+        "identity": identity,
+        "algorithms": algorithms,
+        "results": results,
+    })
+}
 
-    // UDP stream, where stream is a series of Futures??
-    let (udp_stream, udp_stream_handle) = udp_conn.new_stream(handle).unwrap();
-    let (tcp_stream, tcp_stream_handle) = tcp_conn.new_stream(handle).unwrap();
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| {
+            d.help(true)
+                .version(VERSION.map(|s| s.to_string()))
+                .deserialize()
+        })
+        .unwrap_or_else(|e| e.exit());
 
-    // run basic UDP test
-    let mut udp_client_handle = ClientFuture::new(udp_stream, udp_stream_handle, handle, None);
-    println!("[{}] Basic UDP: {:?}", address, reactor.run(support_simple_answers(&mut udp_client_handle)));
+    let (targets, options) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_NOT_RUN);
+        },
+    };
 
-    // run basic TCP test
-    let mut tcp_client_handle = ClientFuture::new(tcp_stream, tcp_stream_handle, handle, None);
-    println!("[{}] Basic TCP: {:?}", address, reactor.run(support_simple_answers(&mut tcp_client_handle)));
+    let results = match run_all(&targets, &options) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_NOT_RUN);
+        },
+    };
+    let mut code = 0;
+    let mut reports = vec![];
+    for (target, result) in targets.iter().zip(results) {
+        match result {
+            Ok(report) => {
+                code = code.max(exit_code(&report.classification()));
+                if args.flag_json {
+                    reports.push(report_json(&report));
+                } else {
+                    print_table(&report);
+                }
+            },
+            Err(e) => {
+                code = code.max(EXIT_RESOLVER_ERROR);
+                if args.flag_json {
+                    reports.push(json!({ "resolver": target.to_string(), "error": e.to_string() }));
+                } else {
+                    println!("Resolver {}\n  Error: {}", target, e);
+                }
+            },
+        }
+    }
+    if args.flag_json {
+        println!("{}", Value::Array(reports));
+    }
+    process::exit(code);
+}
 
-    // run edns0 test
-    println!("[{}] Edns0 UDP: {:?}", address, reactor.run(support_edns0(&mut udp_client_handle)));
-    println!("[{}] DO UDP: {:?}", address, reactor.run(support_do_bit(&mut udp_client_handle)));
-    Ok(())
+#[cfg(test)]
+fn args(argv: &[&str]) -> Args {
+    let argv = ["dns-roadblock-tests"].iter().chain(argv).map(|a| a.to_string());
+    Docopt::new(USAGE).and_then(|d| d.argv(argv).deserialize()).unwrap()
 }
 
-fn main() {
-    let address = "127.0.0.1:53".parse().unwrap();
-    //let address = "8.8.8.8:53".parse().unwrap();
+#[test]
+fn arguments() {
+    let (targets, options) = parse_args(&args(&[])).unwrap();
+    assert_eq!(targets.len(), 3);
+    assert_eq!(options.probes, Probe::all().to_vec());
+    assert_eq!(options.policy, Policy { timeout: Duration::from_millis(5000), retries: 1 });
+    assert!(options.decoy.is_some());
+
+    let (targets, options) = parse_args(&args(&["--transport", "tcp", "--tests", "rrsig, ds", "--decoy", "none",
+                                                 "--policy", "ds=100/3,rrsig=200", "--retries", "2",
+                                                 "192.0.2.1:5353"])).unwrap();
+    assert_eq!(targets, vec!["192.0.2.1:5353".parse().unwrap()]);
+    assert_eq!(options.probes, vec![Probe::Rrsig, Probe::Ds]);
+    assert_eq!(options.decoy, None);
+    assert_eq!(options.policy(Probe::Ds), Policy { timeout: Duration::from_millis(100), retries: 3 });
+    assert_eq!(options.policy(Probe::Rrsig), Policy { timeout: Duration::from_millis(200), retries: 2 });
+    assert_eq!(options.policy(Probe::Udp), Policy { timeout: Duration::from_millis(5000), retries: 2 });
 
-    if let Some(_) = env::args().nth(1) {
-        println!("With args...");
+    let tls = parse_args(&args(&["--transport", "udp,dot", "--tls-name", "dns.example", "192.0.2.1"]));
+    if TLS_SUPPORTED {
+        let (targets, options) = tls.unwrap();
+        assert_eq!(targets[0].address(Transport::Tls), "192.0.2.1:853".parse().unwrap());
+        assert_eq!(options.transports, vec![Transport::Udp, Transport::Tls]);
+        assert_eq!(options.tls_name, Some("dns.example".to_string()));
+        assert_eq!(parse_args(&args(&["--transport", "tls"])).err(), Some("DNS over TLS needs --tls-name".to_string()));
     } else {
-        // no arg
-        let _ = run_tests(address);
-        let address = "8.8.8.8:53".parse().unwrap();
-        let _ = run_tests(address);
-        let address = "1.1.1.1:53".parse().unwrap();
-        let _ = run_tests(address);
+        assert_eq!(tls.err(), Some("DNS over TLS is not supported, build with the dns-over-tls feature".to_string()));
     }
+    assert_eq!(parse_args(&args(&["--transport", "quic"])).err(), Some("Unknown transport quic".to_string()));
+    assert_eq!(parse_args(&args(&["--transport", ","])).err(), Some("No transport selected".to_string()));
+    assert_eq!(parse_args(&args(&["--tests", "udp,dane"])).err(), Some("Unknown probe dane".to_string()));
+    assert_eq!(parse_args(&args(&["--decoy", "nowhere"])).err(), Some("Invalid resolver address nowhere".to_string()));
+    assert!(parse_args(&args(&["resolver.example"])).is_err());
+}
+
+#[test]
+fn probe_policies() {
+    let default = Policy { timeout: Duration::from_secs(5), retries: 1 };
+    assert_eq!(parse_policy("udp=100", default), Ok((Probe::Udp, Policy { timeout: Duration::from_millis(100), retries: 1 })));
+    assert_eq!(parse_policy("udp=100/0", default), Ok((Probe::Udp, Policy { timeout: Duration::from_millis(100), retries: 0 })));
+    for invalid in &["udp", "udp=", "=100", "udp=100/", "udp=100/1/2", "udp=-1"] {
+        assert_eq!(parse_policy(invalid, default), Err(format!("Invalid probe policy {}", invalid)));
+    }
+    assert_eq!(parse_policy("dane=100", default), Err("Unknown probe dane".to_string()));
+}
+
+#[test]
+fn exit_codes() {
+    let classification = |category, intercepted| Classification {
+        category,
+        mitigation: dns_roadblock_tests::classification::Mitigation::Recurse,
+        failures: vec![],
+        intercepted,
+    };
+    assert_eq!(exit_code(&classification(Category::Validating, false)), 0);
+    assert_eq!(exit_code(&classification(Category::DnssecAware, false)), 0);
+    assert_eq!(exit_code(&classification(Category::DnssecAware, true)), 2);
+    assert_eq!(exit_code(&classification(Category::Partial, false)), 2);
+    assert_eq!(exit_code(&classification(Category::DnssecBroken, true)), 3);
+    assert_eq!(exit_code(&classification(Category::NoDns, false)), 4);
+    // Errors of single resolvers aren't hidden by any classification
+    assert!(exit_code(&classification(Category::NoDns, true)) < EXIT_RESOLVER_ERROR);
 }
@@ -0,0 +1,85 @@
+//! Rewriting of NXDOMAIN answers.
+//!
+//! Captive portals and ISP resolvers turn NXDOMAIN into an address of their own, usually a search
+//! or advertising page. The synthesized answer can't be signed, so a validator behind such a
+//! resolver sees bogus answers instead of proofs of non-existence, and search lists stop working
+//! because the first name tried always "exists". The probe asks for random names under a zone that
+//! is known to be absent, where every single answer has to be NXDOMAIN.
+
+use trust_dns::op::{Message, ResponseCode};
+use trust_dns::rr::{Name, RecordType};
+
+use trust_dns_proto::DnsHandle;
+
+use futures::future;
+use futures::prelude::*;
+
+use denial::random_label;
+use signed_records::dnssec_query;
+use super::TestResult;
+
+/// How many names to ask for, some resolvers only rewrite now and then
+const NAMES: usize = 3;
+
+fn nxdomain_result(msg: &Message) -> TestResult {
+    match msg.response_code() {
+        ResponseCode::NXDomain if msg.answers().is_empty() => TestResult::Success,
+        ResponseCode::NXDomain => TestResult::Fail("NXDOMAIN with an answer"),
+        ResponseCode::NoError if msg.answers().is_empty() => TestResult::Fail("NXDOMAIN turned into NODATA"),
+        ResponseCode::NoError => TestResult::Fail("NXDOMAIN rewritten to an answer"),
+        _ => TestResult::Fail("Error response code"),
+    }
+}
+
+/// Random names under the absent zone must all get NXDOMAIN without any answer
+pub fn keeps_nxdomain<DH>(dns_handle: &mut DH, absent_zone: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    let queries: Vec<_> = (0..NAMES)
+        .map(|i| {
+            let name = Name::from_labels(vec![format!("{}-{}", random_label(), i)]).append_domain(absent_zone);
+            // None of the queries may be dropped before the client gives up on it
+            dns_handle.send(dnssec_query(name, RecordType::A)).then(Ok::<_, DH::Error>)
+        })
+        .collect();
+    future::join_all(queries).and_then(|responses| {
+        for response in responses {
+            if let TestResult::Fail(reason) = nxdomain_result(&response?) {
+                return Ok(TestResult::Fail(reason));
+            }
+        }
+        Ok(TestResult::Success)
+    })
+}
+
+#[test]
+fn rewritten_nxdomain() {
+    use std::net::Ipv4Addr;
+    use std::str::FromStr;
+    use trust_dns::rr::{RData, Record};
+
+    let mut msg = Message::new();
+    msg.set_response_code(ResponseCode::NXDomain);
+    assert_eq!(nxdomain_result(&msg), TestResult::Success);
+    msg.set_response_code(ResponseCode::NoError);
+    assert_eq!(nxdomain_result(&msg), TestResult::Fail("NXDOMAIN turned into NODATA"));
+    msg.add_answer(Record::from_rdata(Name::from_str("a.absent.example.").unwrap(), 60, RecordType::A,
+                                      RData::A(Ipv4Addr::new(192, 0, 2, 99))));
+    assert_eq!(nxdomain_result(&msg), TestResult::Fail("NXDOMAIN rewritten to an answer"));
+    msg.set_response_code(ResponseCode::ServFail);
+    assert_eq!(nxdomain_result(&msg), TestResult::Fail("Error response code"));
+}
+
+#[test]
+fn nxdomain_rewriting_probe() {
+    use fixture::{probe, Behaviour};
+    use signed_records::support_rrsig;
+    use super::{ABSENT_ZONE, TESTING_SERVER};
+
+    assert_eq!(probe(Behaviour::default(), |udp, _| keeps_nxdomain(udp, &ABSENT_ZONE)), TestResult::Success);
+    assert_eq!(probe(Behaviour { rewrite_nxdomain: true, ..Default::default() }, |udp, _| keeps_nxdomain(udp, &ABSENT_ZONE)),
+               TestResult::Fail("NXDOMAIN rewritten to an answer"));
+    // Names that exist are left alone
+    assert_eq!(probe(Behaviour { rewrite_nxdomain: true, ..Default::default() }, |udp, _| support_rrsig(udp, &TESTING_SERVER)),
+               TestResult::Success);
+}
@@ -0,0 +1,583 @@
+//! Running the probes against a resolver.
+//!
+//! `run_all` connects to the resolvers over the selected transports, runs the selected probes
+//! against all of them concurrently on one reactor and collects the results of each resolver in
+//! a `Report`. The DNSSEC probes go over the first selected transport, the transport probes and
+//! the unknown type probe over each of them. Every probe has a `Policy`: it fails with "Timed
+//! out" when no response comes within its timeout, after the retries the policy allows.
+//!
+//! The interception probe is decided once all resolvers are done, from the identities they
+//! reported and whether the decoy answered.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::rc::Rc;
+use std::time::Duration;
+
+use trust_dns::client::{BasicClientHandle, ClientConnection, ClientFuture};
+use trust_dns::error::ClientError;
+use trust_dns::rr::{Name, RecordType};
+use trust_dns::rr::dnssec::Algorithm;
+use trust_dns::rr::rdata::DNSSECRecordType;
+use trust_dns::tcp::TcpClientConnection;
+use trust_dns::udp::UdpClientConnection;
+
+use failure::Error;
+use futures::future;
+use futures::sync::oneshot;
+use tokio_core::reactor::{Core, Handle, Timeout};
+
+use algorithms::{algorithms_result, support_algorithms, AlgorithmSupport};
+use classification::{classify, Classification, Probe, ProbeRun};
+use denial::{support_nsec3_nodata, support_nsec3_nxdomain, support_nsec_nodata, support_nsec_nxdomain};
+use interception::{interception_result, query_identity, rejects_unknown_edns_version, support_cookies, Identity};
+use large_response::{support_large_udp, support_tcp_fallback};
+use nxdomain::keeps_nxdomain;
+use signed_records::{dnssec_query, support_dnskey, support_ds, support_rrsig, validates_answers};
+use unknown_type::support_unknown_type;
+use super::*;
+
+/// Whether DNS over TLS was compiled in
+pub const TLS_SUPPORTED: bool = cfg!(feature = "dns-over-tls");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Udp,
+    Tcp,
+    /// DNS over TLS ([RFC 7858](https://tools.ietf.org/html/rfc7858))
+    Tls,
+}
+
+impl Transport {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transport::Udp => "udp",
+            Transport::Tcp => "tcp",
+            Transport::Tls => "tls",
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Transport::Udp | Transport::Tcp => 53,
+            Transport::Tls => 853,
+        }
+    }
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "udp" => Ok(Transport::Udp),
+            "tcp" => Ok(Transport::Tcp),
+            "tls" | "dot" => Ok(Transport::Tls),
+            _ => Err(format!("Unknown transport {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// Resolver to probe. Without a port every transport uses its default one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pub ip: IpAddr,
+    pub port: Option<u16>,
+}
+
+impl Target {
+    pub fn address(&self, transport: Transport) -> SocketAddr {
+        SocketAddr::new(self.ip, self.port.unwrap_or_else(|| transport.default_port()))
+    }
+}
+
+impl From<SocketAddr> for Target {
+    fn from(address: SocketAddr) -> Self {
+        Target { ip: address.ip(), port: Some(address.port()) }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    /// Either an address alone or with a port, IPv6 addresses with a port in brackets
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(ip) = s.parse() {
+            return Ok(Target { ip, port: None });
+        }
+        s.parse::<SocketAddr>()
+            .map(Target::from)
+            .map_err(|_| format!("Invalid resolver address {}", s))
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.ip, self.port) {
+            (ip, None) => write!(f, "{}", ip),
+            (IpAddr::V4(ip), Some(port)) => write!(f, "{}:{}", ip, port),
+            (IpAddr::V6(ip), Some(port)) => write!(f, "[{}]:{}", ip, port),
+        }
+    }
+}
+
+/// How long a probe waits for its response and how many times it's repeated when none comes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    pub timeout: Duration,
+    pub retries: u32,
+}
+
+/// What to probe and how
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Signed zone for the RRSIG, DNSKEY, DS, unknown type and validation probes
+    pub domain: Name,
+    pub nsec_zone: Name,
+    pub nsec3_zone: Name,
+    pub large_response_zone: Name,
+    /// Zone with the zones of the algorithm probe below it
+    pub algorithm_zone: Name,
+    /// Zone without any names, for the NXDOMAIN rewriting probe
+    pub absent_zone: Name,
+    pub transports: Vec<Transport>,
+    /// Name in the certificate of the resolver, for DNS over TLS
+    pub tls_name: Option<String>,
+    /// Name whose answer is the address the resolver resolves from
+    pub whoami_name: Name,
+    /// Address where no resolver runs, an answer from it means that queries are intercepted
+    pub decoy: Option<Target>,
+    /// Policy of the probes without one of their own
+    pub policy: Policy,
+    pub probe_policies: BTreeMap<Probe, Policy>,
+    pub probes: Vec<Probe>,
+}
+
+impl Options {
+    pub fn policy(&self, probe: Probe) -> Policy {
+        self.probe_policies.get(&probe).cloned().unwrap_or(self.policy)
+    }
+
+    /// The longest a single query may take
+    fn longest_timeout(&self) -> Duration {
+        self.probe_policies.values().map(|p| p.timeout).fold(self.policy.timeout, Duration::max)
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            domain: TESTING_SERVER.clone(),
+            nsec_zone: NSEC_TESTING_ZONE.clone(),
+            nsec3_zone: NSEC3_TESTING_ZONE.clone(),
+            large_response_zone: LARGE_RESPONSE_ZONE.clone(),
+            algorithm_zone: ALGORITHM_TESTING_ZONE.clone(),
+            absent_zone: ABSENT_ZONE.clone(),
+            transports: vec![Transport::Udp, Transport::Tcp],
+            tls_name: None,
+            whoami_name: WHOAMI_NAME.clone(),
+            // TEST-NET-1, not routed anywhere
+            decoy: Some(Target { ip: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), port: None }),
+            policy: Policy { timeout: Duration::from_secs(5), retries: 1 },
+            probe_policies: BTreeMap::new(),
+            probes: Probe::all().to_vec(),
+        }
+    }
+}
+
+/// Result of a single probe over one transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Observation {
+    pub probe: Probe,
+    pub transport: Transport,
+    pub result: TestResult,
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub target: Target,
+    /// Every result in the order the probes ran
+    pub observations: Vec<Observation>,
+    pub run: ProbeRun,
+    /// What the resolver tells about itself, when the interception probe ran
+    pub identity: Option<Identity>,
+    /// Support of each algorithm, when the algorithm probe ran. Algorithms without support didn't
+    /// get answers from their zones.
+    pub algorithms: Vec<(Algorithm, Option<AlgorithmSupport>)>,
+}
+
+impl Report {
+    pub fn new(target: Target) -> Self {
+        Report {
+            target,
+            observations: vec![],
+            run: ProbeRun::new(),
+            identity: None,
+            algorithms: vec![],
+        }
+    }
+
+    pub fn record(&mut self, probe: Probe, transport: Transport, result: TestResult) {
+        self.observations.push(Observation { probe, transport, result });
+        self.run.record(probe, result);
+    }
+
+    pub fn classification(&self) -> Classification {
+        classify(&self.run)
+    }
+}
+
+/// Client errors aren't `Sync`, so they can't be turned into `failure::Error` directly
+fn client_error(e: ClientError) -> Error {
+    format_err!("{}", e)
+}
+
+/// Timeout of the clients themselves. The probes have their own, shorter ones, this one only
+/// keeps the clients from waiting for lost responses forever.
+fn client_timeout(options: &Options) -> Duration {
+    options.longest_timeout() + Duration::from_secs(1)
+}
+
+fn connect(target: &Target, transport: Transport, options: &Options, handle: &Handle) -> Result<BasicClientHandle, Error> {
+    let address = target.address(transport);
+    match transport {
+        Transport::Udp => {
+            let (stream, stream_handle) = UdpClientConnection::new(address)
+                .and_then(|c| c.new_stream(handle))
+                .map_err(client_error)?;
+            Ok(ClientFuture::with_timeout(stream, stream_handle, handle, client_timeout(options), None))
+        },
+        Transport::Tcp => {
+            let (stream, stream_handle) = TcpClientConnection::with_timeout(address, client_timeout(options))
+                .and_then(|c| c.new_stream(handle))
+                .map_err(client_error)?;
+            Ok(ClientFuture::with_timeout(stream, stream_handle, handle, client_timeout(options), None))
+        },
+        Transport::Tls => connect_tls(address, options, handle),
+    }
+}
+
+#[cfg(feature = "dns-over-tls")]
+fn connect_tls(address: SocketAddr, options: &Options, handle: &Handle) -> Result<BasicClientHandle, Error> {
+    use trust_dns_native_tls::TlsClientConnection;
+
+    let tls_name = options.tls_name.clone().ok_or_else(|| format_err!("DNS over TLS needs the name of the resolver"))?;
+    let (stream, stream_handle) = TlsClientConnection::builder()
+        .build(address, tls_name)
+        .and_then(|c| c.new_stream(handle))
+        .map_err(client_error)?;
+    Ok(ClientFuture::with_timeout(stream, stream_handle, handle, client_timeout(options), None))
+}
+
+#[cfg(not(feature = "dns-over-tls"))]
+fn connect_tls(_address: SocketAddr, _options: &Options, _handle: &Handle) -> Result<BasicClientHandle, Error> {
+    Err(format_err!("dns-roadblock-tests was built without the dns-over-tls feature"))
+}
+
+type ProbeFuture<T> = Box<dyn Future<Item=T, Error=ClientError>>;
+
+/// Starts a probe over the client of the task, the second client is only used by the TCP
+/// fallback probe
+type StartProbe<T> = fn(&mut BasicClientHandle, &mut BasicClientHandle, &Options) -> ProbeFuture<T>;
+
+struct Task<T> {
+    probe: Probe,
+    transport: Transport,
+    client: BasicClientHandle,
+    second: BasicClientHandle,
+    start: StartProbe<T>,
+}
+
+impl<T: 'static> Task<T> {
+    fn new(probe: Probe, transport: Transport, client: &BasicClientHandle, start: StartProbe<T>) -> Self {
+        Task { probe, transport, client: client.clone(), second: client.clone(), start }
+    }
+
+    /// Run the task under the policy of its probe
+    fn run(self, options: &Rc<Options>, handle: &Handle) -> Box<dyn Future<Item=Outcome<T>, Error=()>> {
+        let retries = options.policy(self.probe).retries;
+        attempt(Rc::new(self), options.clone(), handle.clone(), retries)
+    }
+}
+
+enum Outcome<T> {
+    Done(T),
+    NoResponse,
+    TimedOut,
+}
+
+impl<T> Outcome<T> {
+    fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Outcome<U> {
+        match self {
+            Outcome::Done(value) => Outcome::Done(f(value)),
+            Outcome::NoResponse => Outcome::NoResponse,
+            Outcome::TimedOut => Outcome::TimedOut,
+        }
+    }
+}
+
+impl Outcome<TestResult> {
+    fn result(self) -> TestResult {
+        match self {
+            Outcome::Done(result) => result,
+            Outcome::NoResponse => TestResult::Fail("No response"),
+            Outcome::TimedOut => TestResult::Fail("Timed out"),
+        }
+    }
+}
+
+/// Run the task, starting it again as long as there's no response within the timeout and the
+/// policy allows more retries
+fn attempt<T: 'static>(task: Rc<Task<T>>, options: Rc<Options>, handle: Handle, retries: u32)
+                       -> Box<dyn Future<Item=Outcome<T>, Error=()>> {
+    let (mut client, mut second) = (task.client.clone(), task.second.clone());
+    // The client panics when a query is dropped before it gives up on it, so the probe runs on
+    // its own and its response is ignored once the timeout is over
+    let (sender, receiver) = oneshot::channel();
+    handle.spawn((task.start)(&mut client, &mut second, &options).then(move |response| {
+        let _ = sender.send(response.map(Outcome::Done).unwrap_or(Outcome::NoResponse));
+        Ok(())
+    }));
+    let probe = receiver.map_err(|_| ());
+    let timer: Box<dyn Future<Item=Outcome<T>, Error=()>> = match Timeout::new(options.policy(task.probe).timeout, &handle) {
+        Ok(timer) => Box::new(timer.then(|_| Ok(Outcome::TimedOut))),
+        // the timeout of the client still applies
+        Err(_) => Box::new(future::empty()),
+    };
+    Box::new(probe.select(timer)
+        .map(|(outcome, _)| outcome)
+        .map_err(|_| ())
+        .and_then(move |outcome| -> Box<dyn Future<Item=Outcome<T>, Error=()>> {
+            match outcome {
+                Outcome::Done(_) => Box::new(future::ok(outcome)),
+                _ if retries > 0 => attempt(task, options, handle, retries - 1),
+                _ => Box::new(future::ok(outcome)),
+            }
+        }))
+}
+
+/// Clients of every transport of the options
+fn connect_all(target: &Target, options: &Options, handle: &Handle) -> Result<Vec<(Transport, BasicClientHandle)>, Error> {
+    let mut clients = vec![];
+    for transport in &options.transports {
+        clients.push((*transport, connect(target, *transport, options, handle)?));
+    }
+    if clients.is_empty() {
+        return Err(format_err!("No transport to run the probes over"));
+    }
+    Ok(clients)
+}
+
+/// The selected probes in the order they're reported
+fn tasks(clients: &[(Transport, BasicClientHandle)], options: &Options) -> Vec<Task<TestResult>> {
+    let client = |transport| clients.iter().find(|(t, _)| *t == transport).map(|(_, c)| c.clone());
+    let (first_transport, ref first) = clients[0];
+    let mut tasks = vec![];
+    let mut add = |probe, transport, client: &BasicClientHandle, second: Option<&BasicClientHandle>, start| {
+        if options.probes.contains(&probe) {
+            let mut task = Task::new(probe, transport, client, start);
+            task.second = second.unwrap_or(client).clone();
+            tasks.push(task);
+        }
+    };
+
+    // basic queries over every transport
+    for &(transport, ref client) in clients {
+        let probe = match transport {
+            Transport::Udp => Probe::Udp,
+            Transport::Tcp => Probe::Tcp,
+            Transport::Tls => Probe::Tls,
+        };
+        add(probe, transport, client, None, |c, _, o| Box::new(support_simple_answers(c, &o.domain)));
+    }
+
+    // EDNS and DNSSEC records
+    add(Probe::Edns0, first_transport, first, None, |c, _, o| Box::new(support_edns0(c, &o.domain)));
+    add(Probe::DoBit, first_transport, first, None, |c, _, o| Box::new(support_do_bit(c, &o.domain)));
+    add(Probe::Rrsig, first_transport, first, None, |c, _, o| Box::new(support_rrsig(c, &o.domain)));
+    add(Probe::Dnskey, first_transport, first, None, |c, _, o| Box::new(support_dnskey(c, &o.domain)));
+    add(Probe::Ds, first_transport, first, None, |c, _, o| Box::new(support_ds(c, &o.domain)));
+    add(Probe::Validation, first_transport, first, None, |c, _, o| Box::new(validates_answers(c, &o.domain)));
+
+    // authenticated denial
+    add(Probe::NsecNxdomain, first_transport, first, None, |c, _, o| Box::new(support_nsec_nxdomain(c, &o.nsec_zone)));
+    add(Probe::NsecNodata, first_transport, first, None, |c, _, o| Box::new(support_nsec_nodata(c, &o.nsec_zone)));
+    add(Probe::Nsec3Nxdomain, first_transport, first, None, |c, _, o| Box::new(support_nsec3_nxdomain(c, &o.nsec3_zone)));
+    add(Probe::Nsec3Nodata, first_transport, first, None, |c, _, o| Box::new(support_nsec3_nodata(c, &o.nsec3_zone)));
+
+    // unknown types and large responses
+    for &(transport, ref client) in clients {
+        add(Probe::UnknownType, transport, client, None, |c, _, o| Box::new(support_unknown_type(c, &o.domain)));
+    }
+    if let Some(udp) = client(Transport::Udp) {
+        add(Probe::LargeUdp, Transport::Udp, &udp, None,
+            |c, _, o| Box::new(support_large_udp(c, &o.large_response_zone, RecordType::DNSSEC(DNSSECRecordType::DNSKEY))));
+        if let Some(tcp) = client(Transport::Tcp) {
+            add(Probe::TcpFallback, Transport::Tcp, &udp, Some(&tcp),
+                |udp, tcp, o| Box::new(support_tcp_fallback(udp, tcp, &o.large_response_zone,
+                                                            RecordType::DNSSEC(DNSSECRecordType::DNSKEY))));
+        }
+    }
+
+    // tampering with answers
+    add(Probe::NxdomainRewriting, first_transport, first, None, |c, _, o| Box::new(keeps_nxdomain(c, &o.absent_zone)));
+
+    // traces of interception, the interception probe itself needs the other resolvers
+    add(Probe::Cookie, first_transport, first, None, |c, _, o| Box::new(support_cookies(c, &o.domain)));
+    add(Probe::EdnsVersion, first_transport, first, None, |c, _, o| Box::new(rejects_unknown_edns_version(c, &o.domain)));
+    tasks
+}
+
+/// Whether the decoy answers over any of the transports of the options. Interceptors don't care
+/// where a query goes, over TLS the resolver authenticates itself and can't be replaced anyway.
+fn probe_decoy(decoy: &Target, options: &Rc<Options>, handle: &Handle) -> Box<dyn Future<Item=bool, Error=()>> {
+    let answers = options.transports.iter()
+        .filter(|t| **t != Transport::Tls)
+        .filter_map(|t| connect(decoy, *t, options, handle).ok().map(|client| (*t, client)))
+        .map(|(transport, client)| {
+            let task = Task::new(Probe::Interception, transport, &client, |c, _, o| {
+                Box::new(c.send(dnssec_query(o.domain.clone(), RecordType::A)).map(|_| TestResult::Success))
+            });
+            task.run(options, handle).map(|outcome| matches!(outcome, Outcome::Done(_)))
+        })
+        .collect::<Vec<_>>();
+    Box::new(future::join_all(answers).map(|answers| answers.into_iter().any(|a| a)))
+}
+
+/// Run the probes of the options against all resolvers at once. Every probe runs concurrently
+/// under its own policy, so a resolver that doesn't answer only delays the run by the timeouts
+/// and retries of its probes. The reports are in the order of the targets, the error of a target
+/// is the failure to set up its clients.
+pub fn run_all(targets: &[Target], options: &Options) -> Result<Vec<Result<Report, Error>>, Error> {
+    let mut core = Core::new()?;
+    let handle = core.handle();
+    let shared = Rc::new(options.clone());
+    let interception = options.probes.contains(&Probe::Interception);
+    let mut runs: Vec<Box<dyn Future<Item=Result<Report, Error>, Error=()>>> = vec![];
+    for target in targets {
+        let clients = match connect_all(target, options, &handle) {
+            Ok(clients) => clients,
+            Err(e) => {
+                runs.push(Box::new(future::ok(Err(e))));
+                continue;
+            },
+        };
+        let observations = tasks(&clients, options).into_iter()
+            .map(|task| {
+                let (probe, transport) = (task.probe, task.transport);
+                task.run(&shared, &handle).map(move |outcome| Observation { probe, transport, result: outcome.result() })
+            })
+            .collect::<Vec<_>>();
+        let identity: Box<dyn Future<Item=Option<Identity>, Error=()>> = if interception {
+            let task = Task::new(Probe::Interception, clients[0].0, &clients[0].1,
+                                 |c, _, o| Box::new(query_identity(c, &o.whoami_name)));
+            Box::new(task.run(&shared, &handle).map(|outcome| match outcome {
+                Outcome::Done(identity) => Some(identity),
+                _ => None,
+            }))
+        } else {
+            Box::new(future::ok(None))
+        };
+        let algorithms: Box<dyn Future<Item=Option<(Observation, _)>, Error=()>> = if options.probes.contains(&Probe::Algorithms) {
+            let (transport, ref client) = clients[0];
+            let task = Task::new(Probe::Algorithms, transport, client, |c, _, o| Box::new(support_algorithms(c, &o.algorithm_zone)));
+            Box::new(task.run(&shared, &handle).map(move |outcome| {
+                let (result, support) = match outcome {
+                    Outcome::Done(support) => (algorithms_result(&support), support),
+                    failed => (failed.map(|_| TestResult::Success).result(), vec![]),
+                };
+                Some((Observation { probe: Probe::Algorithms, transport, result }, support))
+            }))
+        } else {
+            Box::new(future::ok(None))
+        };
+        let target = *target;
+        let run = future::join_all(observations).join3(identity, algorithms);
+        runs.push(Box::new(run.map(move |(observations, identity, algorithms)| {
+            let mut report = Report::new(target);
+            for o in observations {
+                report.record(o.probe, o.transport, o.result);
+            }
+            if let Some((o, support)) = algorithms {
+                report.record(o.probe, o.transport, o.result);
+                report.algorithms = support;
+            }
+            report.identity = identity;
+            Ok(report)
+        })));
+    }
+    let decoy = match options.decoy {
+        Some(ref decoy) if interception => probe_decoy(decoy, &shared, &handle),
+        _ => Box::new(future::ok(false)),
+    };
+    let (mut reports, decoy_answered) = core.run(future::join_all(runs).join(decoy))
+        .map_err(|_| format_err!("Probe run failed"))?;
+
+    if interception {
+        let identities: Vec<(Target, Option<Identity>)> = reports.iter()
+            .filter_map(|r| r.as_ref().ok())
+            .map(|r| (r.target, r.identity.clone()))
+            .collect();
+        for report in reports.iter_mut().filter_map(|r| r.as_mut().ok()) {
+            let others: Vec<&Identity> = identities.iter()
+                .filter(|(t, _)| *t != report.target)
+                .filter_map(|(_, i)| i.as_ref())
+                .collect();
+            let result = interception_result(report.identity.as_ref(), &others, decoy_answered);
+            report.record(Probe::Interception, options.transports[0], result);
+        }
+    }
+    Ok(reports)
+}
+
+/// Run the probes of the options against the resolver
+pub fn run_probes(target: &Target, options: &Options) -> Result<Report, Error> {
+    run_all(&[*target], options)?.pop().unwrap_or_else(|| Err(format_err!("No report")))
+}
+
+#[test]
+fn targets() {
+    let target = |s: &str| s.parse::<Target>();
+    assert_eq!(target("192.0.2.1").map(|t| t.address(Transport::Tls)), Ok("192.0.2.1:853".parse().unwrap()));
+    assert_eq!(target("192.0.2.1:5353").map(|t| t.address(Transport::Tls)), Ok("192.0.2.1:5353".parse().unwrap()));
+    assert_eq!(target("2001:db8::1").map(|t| t.address(Transport::Udp)), Ok("[2001:db8::1]:53".parse().unwrap()));
+    assert_eq!(target("[2001:db8::1]:53").map(|t| t.to_string()), Ok("[2001:db8::1]:53".to_string()));
+    assert!(target("resolver.example").is_err());
+    assert_eq!("dot".parse(), Ok(Transport::Tls));
+}
+
+#[test]
+fn silent_resolvers_time_out() {
+    use std::time::Instant;
+    use classification::Category;
+    use fixture::{Behaviour, Server, Zones};
+
+    let silent = Server::resolver(Zones::testing(), Behaviour { no_udp: true, no_tcp: true, ..Default::default() });
+    let validating = Server::resolver(Zones::testing(), Behaviour::validating());
+    let timeout = Duration::from_millis(300);
+    let options = Options { decoy: None, policy: Policy { timeout, retries: 1 }, ..Default::default() };
+    let started = Instant::now();
+    let reports = run_all(&[silent.address().into(), validating.address().into()], &options).unwrap();
+    let elapsed = started.elapsed();
+    // Every probe waits for two attempts, but all of them at once
+    assert!(elapsed >= timeout * 2 && elapsed < timeout * 6, "{:?}", elapsed);
+
+    let silent = reports[0].as_ref().unwrap();
+    // no tls probe, but the unknown type probe over both transports
+    assert_eq!(silent.observations.len(), options.probes.len());
+    assert_eq!(silent.identity, None);
+    // nothing points to interception either
+    assert!(silent.observations.iter()
+        .filter(|o| o.probe != Probe::Interception)
+        .all(|o| o.result == TestResult::Fail("Timed out")));
+    assert_eq!(silent.classification().category, Category::NoDns);
+    assert_eq!(reports[1].as_ref().unwrap().classification().category, Category::Validating);
+}
@@ -0,0 +1,303 @@
+//! Retrieval of DNSSEC records, [RFC 8027, sections 3.1.5 to 3.1.7](https://tools.ietf.org/html/rfc8027#section-3.1.5).
+//!
+//! A resolver that sets the DO bit may still drop signatures or refuse to answer queries for
+//! DNSKEY and DS records. Each probe therefore checks the records it got back: the queried RRset
+//! has to be present together with an RRSIG covering it, made by the right zone and valid right
+//! now.
+//!
+//! The name the probes get doesn't have to be the apex of its zone. The DNSKEY and DS probes look
+//! the zone up first, in the SOA record that comes back for the name.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use trust_dns::op::{Edns, Message, MessageType, OpCode, Query, ResponseCode};
+use trust_dns::rr::{Name, RData, Record, RecordType};
+use trust_dns::rr::rdata::{DNSSECRData, DNSSECRecordType, DNSKEY, SIG};
+use trust_dns::rr::rdata::dnskey;
+use trust_dns::serialize::binary::BinEncoder;
+
+use trust_dns_proto::DnsHandle;
+
+use futures::future::{self, Either};
+use futures::prelude::*;
+
+use super::TestResult;
+
+/// UDP payload announced in EDNS. The UDP stream of trust-dns receives at most 2048 bytes, so
+/// there's no point in asking for more.
+pub const EDNS_PAYLOAD: u16 = 2048;
+
+/// Recursive query with the DO bit set, so that the resolver includes signatures
+pub fn dnssec_query(name: Name, record_type: RecordType) -> Message {
+    dnssec_query_with_payload(name, record_type, EDNS_PAYLOAD)
+}
+
+/// Same as `dnssec_query`, announcing the given UDP payload size
+pub fn dnssec_query_with_payload(name: Name, record_type: RecordType, payload: u16) -> Message {
+    let mut edns = Edns::new();
+    edns.set_dnssec_ok(true);
+    edns.set_max_payload(payload);
+    let mut msg = Message::new();
+    msg.add_query(Query::query(name, record_type))
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .set_edns(edns);
+    msg
+}
+
+/// Signatures among the records
+pub fn rrsigs(records: &[Record]) -> impl Iterator<Item = &SIG> {
+    records.iter()
+        .filter(|r| r.rr_type() == RecordType::DNSSEC(DNSSECRecordType::RRSIG))
+        .filter_map(|r| match *r.rdata() {
+            RData::DNSSEC(DNSSECRData::SIG(ref sig)) => Some(sig),
+            _ => None,
+        })
+}
+
+/// Seconds since the epoch, truncated the same way as the RRSIG validity period
+fn now() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as u32).unwrap_or(0)
+}
+
+/// Whether `now` falls into the validity period using serial number arithmetic
+/// ([RFC 4034, section 3.1.5](https://tools.ietf.org/html/rfc4034#section-3.1.5))
+fn is_valid_at(sig: &SIG, now: u32) -> bool {
+    now.wrapping_sub(sig.sig_inception()) as i32 >= 0 && sig.sig_expiration().wrapping_sub(now) as i32 >= 0
+}
+
+/// Check that the records contain an RRset of the given type and a currently valid RRSIG made by
+/// the signer covering it
+pub fn check_signed_rrset(records: &[Record], covered: RecordType, signer: &Name) -> Result<(), &'static str> {
+    check_signatures(records, covered, |s| s == signer)
+}
+
+/// Same as `check_signed_rrset`, for any signer the predicate accepts
+fn check_signatures<F>(records: &[Record], covered: RecordType, is_signer: F) -> Result<(), &'static str>
+    where F: Fn(&Name) -> bool
+{
+    if !records.iter().any(|r| r.rr_type() == covered) {
+        return Err("No records of the queried type");
+    }
+    let sigs: Vec<&SIG> = rrsigs(records).filter(|s| s.type_covered() == covered).collect();
+    if sigs.is_empty() {
+        return Err("No RRSIG covering the answer");
+    }
+    let sigs: Vec<&SIG> = sigs.into_iter().filter(|s| is_signer(s.signer_name())).collect();
+    if sigs.is_empty() {
+        return Err("RRSIG made by a different zone");
+    }
+    let now = now();
+    if !sigs.iter().any(|s| is_valid_at(s, now)) {
+        return Err("RRSIG outside of its validity period");
+    }
+    Ok(())
+}
+
+/// Key tag of a DNSKEY ([RFC 4034, appendix B](https://tools.ietf.org/html/rfc4034#appendix-B))
+pub fn key_tag(key: &DNSKEY) -> Option<u16> {
+    let mut rdata = vec![];
+    dnskey::emit(&mut BinEncoder::new(&mut rdata), key).ok()?;
+    let mut acc: u32 = 0;
+    for (i, b) in rdata.iter().enumerate() {
+        acc += if i % 2 == 0 { u32::from(*b) << 8 } else { u32::from(*b) };
+    }
+    acc += (acc >> 16) & 0xffff;
+    Some((acc & 0xffff) as u16)
+}
+
+fn response_result<F>(msg: &Message, covered: RecordType, is_signer: F) -> TestResult
+    where F: Fn(&Name) -> bool
+{
+    if msg.response_code() != ResponseCode::NoError {
+        return TestResult::Fail("Error response code");
+    }
+    match check_signatures(msg.answers(), covered, is_signer) {
+        Ok(()) => TestResult::Success,
+        Err(reason) => TestResult::Fail(reason),
+    }
+}
+
+/// Apex of the zone of the name, the owner of the SOA record in the answer to a SOA query for
+/// the name or, below the apex, in the authority section
+fn zone_apex(msg: &Message, name: &Name) -> Option<Name> {
+    msg.answers().iter().chain(msg.name_servers())
+        .filter(|r| r.rr_type() == RecordType::SOA && r.name().zone_of(name))
+        .map(|r| r.name().clone())
+        .max_by_key(|apex| apex.num_labels())
+}
+
+/// Find the zone of the name and run the query for the probe at its apex
+fn query_at_apex<DH, F>(dns_handle: &mut DH, name: &Name, record_type: RecordType, result: F)
+                        -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle,
+          F: FnOnce(&Message, &Name) -> TestResult
+{
+    let mut apex_handle = dns_handle.clone();
+    let name = name.clone();
+    dns_handle
+        .send(dnssec_query(name.clone(), RecordType::SOA))
+        .and_then(move |msg| match zone_apex(&msg, &name) {
+            Some(apex) => Either::A(apex_handle.send(dnssec_query(apex.clone(), record_type))
+                .map(move |msg| result(&msg, &apex))),
+            None => Either::B(future::ok(TestResult::Fail("Zone of the name not found"))),
+        })
+}
+
+/// [RFC 8027, section 3.1.5](https://tools.ietf.org/html/rfc8027#section-3.1.5)
+///
+/// A query for a signed A record with the DO bit set must return the RRSIG as well, made by the
+/// zone of the name, which is the name itself or one of its ancestors.
+pub fn support_rrsig<DH>(dns_handle: &mut DH, name: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    let name = name.clone();
+    dns_handle
+        .send(dnssec_query(name.clone(), RecordType::A))
+        .map(move |msg| response_result(&msg, RecordType::A, |signer| signer.zone_of(&name)))
+}
+
+/// [RFC 8027, section 3.1.6](https://tools.ietf.org/html/rfc8027#section-3.1.6)
+///
+/// The DNSKEY RRset of the zone must come back signed by one of the keys in it.
+pub fn support_dnskey<DH>(dns_handle: &mut DH, name: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    let covered = RecordType::DNSSEC(DNSSECRecordType::DNSKEY);
+    query_at_apex(dns_handle, name, covered, move |msg, apex| {
+        if let TestResult::Fail(reason) = response_result(msg, covered, |signer| signer == apex) {
+            return TestResult::Fail(reason);
+        }
+        let tags: Vec<u16> = msg.answers().iter()
+            .filter_map(|r| match *r.rdata() {
+                RData::DNSSEC(DNSSECRData::DNSKEY(ref key)) if key.zone_key() => key_tag(key),
+                _ => None,
+            })
+            .collect();
+        if tags.is_empty() {
+            TestResult::Fail("No zone key in the DNSKEY RRset")
+        } else if !rrsigs(msg.answers()).any(|s| s.type_covered() == covered && tags.contains(&s.key_tag())) {
+            TestResult::Fail("DNSKEY RRset not signed by any of its keys")
+        } else {
+            TestResult::Success
+        }
+    })
+}
+
+/// [RFC 8027, section 3.1.7](https://tools.ietf.org/html/rfc8027#section-3.1.7)
+///
+/// DS records live in the parent zone, so the resolver has to send the query for the apex of the
+/// zone there and return the DS RRset signed by the parent.
+pub fn support_ds<DH>(dns_handle: &mut DH, name: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    let covered = RecordType::DNSSEC(DNSSECRecordType::DS);
+    query_at_apex(dns_handle, name, covered, move |msg, apex| {
+        response_result(msg, covered, |signer| signer != apex && signer.zone_of(apex))
+    })
+}
+
+/// Whether the resolver validates: an answer from a signed zone must have the AD bit set
+/// ([RFC 4035, section 3.2.3](https://tools.ietf.org/html/rfc4035#section-3.2.3)). A resolver
+/// that merely passes signatures through never sets it.
+pub fn validates_answers<DH>(dns_handle: &mut DH, name: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    dns_handle
+        .send(dnssec_query(name.clone(), RecordType::A))
+        .map(|msg| {
+            if msg.response_code() != ResponseCode::NoError {
+                TestResult::Fail("Error response code")
+            } else if !msg.authentic_data() {
+                TestResult::Fail("AD bit not set")
+            } else {
+                TestResult::Success
+            }
+        })
+}
+
+#[test]
+fn key_tag_of_root_ksk() {
+    use trust_dns::rr::dnssec::Algorithm;
+
+    // KSK-2017 of the root zone, key tag 20326
+    let public_key = [
+        "AwEAAaz/tAm8yTn4Mfeh5eyI96WSVexTBAvkMgJzkKTOiW1vkIbzxeF3",
+        "+/4RgWOq7HrxRixHlFlExOLAJr5emLvN7SWXgnLh4+B5xQlNVz8Og8kv",
+        "ArMtNROxVQuCaSnIDdD5LKyWbRd2n9WGe2R8PzgCmr3EgVLrjyBxWezF",
+        "0jLHwVN8efS3rCj/EWgvIWgb9tarpVUDK/b58Da+sqqls3eNbuv7pr+e",
+        "oZG+SrDK6nWeL3c6H5Apxz7LjVc1uTIdsIXxuOLYA4/ilBmSVIzuDWfd",
+        "RUfhHdY6+cn8HFRm+2hM8AnXGXws9555KrUB5qihylGa8subX2Nn6UwN",
+        "R1AkUTV74bU=",
+    ].concat();
+    let key = DNSKEY::new(true, true, false, Algorithm::RSASHA256, decode_base64(&public_key));
+    assert_eq!(key_tag(&key), Some(20326));
+}
+
+#[cfg(test)]
+fn decode_base64(input: &str) -> Vec<u8> {
+    let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = vec![];
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for c in input.bytes().filter(|c| *c != b'=') {
+        acc = (acc << 6) | alphabet.iter().position(|a| *a == c).unwrap() as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    out
+}
+
+#[test]
+fn signature_validity_period() {
+    use std::str::FromStr;
+    use trust_dns::rr::dnssec::Algorithm;
+
+    let sig = |inception, expiration| SIG::new(RecordType::A, Algorithm::RSASHA256, 2, 3600, expiration, inception, 1234,
+                                               Name::from_str("example.").unwrap(), vec![]);
+    assert!(is_valid_at(&sig(1000, 2000), 1500));
+    assert!(!is_valid_at(&sig(1000, 2000), 2500));
+    assert!(!is_valid_at(&sig(1000, 2000), 500));
+    // The period may wrap around the end of the 32 bit space
+    assert!(is_valid_at(&sig(u32::MAX - 10, 10), 5));
+}
+
+#[test]
+fn signed_record_probes() {
+    use fixture::{probe, probe_server, Behaviour, Server, Zones};
+    use super::TESTING_SERVER;
+
+    let correct = Behaviour::default;
+    assert_eq!(probe(correct(), |udp, _| support_rrsig(udp, &TESTING_SERVER)), TestResult::Success);
+    assert_eq!(probe(Behaviour { strip_dnssec: true, ..correct() }, |udp, _| support_rrsig(udp, &TESTING_SERVER)),
+               TestResult::Fail("No RRSIG covering the answer"));
+    assert_eq!(probe(Behaviour { stale_signatures: true, ..correct() }, |udp, _| support_rrsig(udp, &TESTING_SERVER)),
+               TestResult::Fail("RRSIG outside of its validity period"));
+
+    assert_eq!(probe(correct(), |udp, _| support_dnskey(udp, &TESTING_SERVER)), TestResult::Success);
+    assert_eq!(probe(Behaviour { strip_dnssec: true, ..correct() }, |udp, _| support_dnskey(udp, &TESTING_SERVER)),
+               TestResult::Fail("No records of the queried type"));
+
+    assert_eq!(probe(correct(), |udp, _| support_ds(udp, &TESTING_SERVER)), TestResult::Success);
+    assert_eq!(probe(Behaviour { ds_from_child: true, ..correct() }, |udp, _| support_ds(udp, &TESTING_SERVER)),
+               TestResult::Fail("No records of the queried type"));
+
+    // Names below the apex work the same way, the DNSKEY and DS RRsets are those of the zone
+    let www = Name::from_labels(vec!["www"]).append_domain(&TESTING_SERVER);
+    assert_eq!(probe(correct(), |udp, _| support_rrsig(udp, &www)), TestResult::Success);
+    assert_eq!(probe(correct(), |udp, _| support_dnskey(udp, &www)), TestResult::Success);
+    assert_eq!(probe(correct(), |udp, _| support_ds(udp, &www)), TestResult::Success);
+    assert_eq!(probe(Behaviour { ds_from_child: true, ..correct() }, |udp, _| support_ds(udp, &www)),
+               TestResult::Fail("No records of the queried type"));
+
+    assert_eq!(probe(Behaviour::validating(), |udp, _| validates_answers(udp, &TESTING_SERVER)), TestResult::Success);
+    assert_eq!(probe(correct(), |udp, _| validates_answers(udp, &TESTING_SERVER)), TestResult::Fail("AD bit not set"));
+    // Authoritative servers never set it, but they serve the signed records
+    let authoritative = Server::authoritative(Zones::testing());
+    assert_eq!(probe_server(&authoritative, |udp, _| validates_answers(udp, &TESTING_SERVER)), TestResult::Fail("AD bit not set"));
+    assert_eq!(probe_server(&authoritative, |udp, _| support_dnskey(udp, &TESTING_SERVER)), TestResult::Success);
+}
@@ -0,0 +1,78 @@
+//! Unknown record types, [RFC 8027, section 3.1.10](https://tools.ietf.org/html/rfc8027#section-3.1.10).
+//!
+//! [RFC 3597](https://tools.ietf.org/html/rfc3597) requires resolvers to handle record types they
+//! don't know as opaque data. Resolvers and middleboxes that parse every record refuse such
+//! queries, which means they will refuse the next DNSSEC record type as well.
+
+use trust_dns::op::{Message, ResponseCode};
+use trust_dns::rr::{Name, RecordType};
+
+use trust_dns_proto::DnsHandle;
+
+use futures::prelude::*;
+
+use signed_records::dnssec_query;
+use super::TestResult;
+
+/// Type from the private use range, no resolver can have special handling for it
+/// ([RFC 6895, section 3.1](https://tools.ietf.org/html/rfc6895#section-3.1))
+pub const UNASSIGNED_TYPE: RecordType = RecordType::Unknown(65_280);
+
+fn unknown_type_result(msg: &Message) -> TestResult {
+    match msg.response_code() {
+        // Either NODATA or the opaque record itself, both are fine
+        ResponseCode::NoError => {
+            if msg.answers().iter().all(|r| r.rr_type() == UNASSIGNED_TYPE || r.rr_type().is_cname()) {
+                TestResult::Success
+            } else {
+                TestResult::Fail("Answer of a different type")
+            }
+        },
+        // The name exists, but a resolver may treat an unknown type as a reason to deny it all
+        ResponseCode::NXDomain => TestResult::Fail("NXDOMAIN for an unknown type"),
+        ResponseCode::FormErr => TestResult::Fail("Unknown type rejected as malformed"),
+        ResponseCode::NotImp => TestResult::Fail("Unknown type not implemented"),
+        ResponseCode::ServFail => TestResult::Fail("Server failure for an unknown type"),
+        ResponseCode::Refused => TestResult::Fail("Unknown type refused"),
+        _ => TestResult::Fail("Error response code"),
+    }
+}
+
+/// [RFC 8027, section 3.1.10](https://tools.ietf.org/html/rfc8027#section-3.1.10)
+///
+/// A query for an unassigned type at an existing name must get a regular answer.
+pub fn support_unknown_type<DH>(dns_handle: &mut DH, name: &Name) -> impl Future<Item=TestResult, Error=DH::Error>
+    where DH: DnsHandle
+{
+    dns_handle
+        .send(dnssec_query(name.clone(), UNASSIGNED_TYPE))
+        .map(|msg| unknown_type_result(&msg))
+}
+
+#[test]
+fn unknown_type_responses() {
+    use std::str::FromStr;
+    use trust_dns::rr::{RData, Record};
+
+    let mut msg = Message::new();
+    assert_eq!(unknown_type_result(&msg), TestResult::Success);
+    msg.set_response_code(ResponseCode::FormErr);
+    assert_eq!(unknown_type_result(&msg), TestResult::Fail("Unknown type rejected as malformed"));
+
+    let mut msg = Message::new();
+    let name = Name::from_str("example.").unwrap();
+    msg.add_answer(Record::from_rdata(name, 300, RecordType::A, RData::A("192.0.2.1".parse().unwrap())));
+    assert_eq!(unknown_type_result(&msg), TestResult::Fail("Answer of a different type"));
+}
+
+#[test]
+fn unknown_type_probe() {
+    use fixture::{probe, Behaviour};
+    use super::TESTING_SERVER;
+
+    let correct = Behaviour::default;
+    assert_eq!(probe(correct(), |udp, _| support_unknown_type(udp, &TESTING_SERVER)), TestResult::Success);
+    assert_eq!(probe(correct(), |_, tcp| support_unknown_type(tcp, &TESTING_SERVER)), TestResult::Success);
+    assert_eq!(probe(Behaviour { refuse_unknown_types: true, ..correct() }, |udp, _| support_unknown_type(udp, &TESTING_SERVER)),
+               TestResult::Fail("Unknown type rejected as malformed"));
+}
@@ -1,51 +1,134 @@
-use std::net::Ipv4Addr;
-
-/// Type of a connection as reported by Network Manager
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
-pub enum ConnectionType {
-    Ethernet = 0,
-    VPN = 1,
-    WiFi = 2,
-    Other = 3,
-}
+use std::net::IpAddr;
 
-impl ConnectionType {
-    pub fn from_str(s: &str) -> Self {
-        if s.contains("ethernet") {
-            ConnectionType::Ethernet
-        } else if s.contains("wireless") {
-            ConnectionType::WiFi
-        } else if s.contains("vpn") {
-            ConnectionType::VPN
-        } else {
-            ConnectionType::Other
-        }
-    }
-}
-
-pub type Domain = String;
+use nm_connection::{parse_prefix, Identity};
+pub use nm_connection::{ConnectionType, Domain, Domains};
 
 /// Structure containing all information, that are relevant for DNS configuration about each
 /// connection.
 #[derive(Debug)]
 pub struct Connection {
     pub id: String,
+    pub uuid: String,
+    /// IP interfaces of the devices the connection is active on
+    pub interfaces: Vec<String>,
     pub con_type: ConnectionType,
+    /// Type exactly as Network Manager reports it (`connection.type`), e.g. `802-3-ethernet`
+    pub nm_type: String,
     pub default: bool,
-    pub addresses: Vec<(Ipv4Addr, u8)>,
-    pub nameservers: Vec<Ipv4Addr>,
-    pub domains: Vec<Domain>,
+    /// `ipv4.never-default`/`ipv6.never-default` of the profile, a VPN that never gets the
+    /// default route is a split tunnel even if it asks for all queries (`~.`)
+    pub never_default: bool,
+    /// IPv4 and IPv6 addresses of the connection
+    pub addresses: Vec<(IpAddr, u8)>,
+    /// IPv4 nameservers followed by the IPv6 ones
+    pub nameservers: Vec<IpAddr>,
+    /// Domains of both IP families
+    pub domains: Domains,
+    /// Destinations of IPv4 and IPv6 routes of the connection
+    pub routes: Vec<(IpAddr, u8)>,
 }
 
 impl Default for Connection {
     fn default() -> Self {
         Connection {
             id: "".to_string(),
+            uuid: "".to_string(),
+            interfaces: vec![],
             con_type: ConnectionType::Other,
+            nm_type: "".to_string(),
             default: false,
+            never_default: false,
             addresses: vec![],
             nameservers: vec![],
-            domains: vec![],
+            domains: Domains::default(),
+            routes: vec![],
+        }
+    }
+}
+
+impl Identity for Connection {
+    fn nm_type(&self) -> &str {
+        &self.nm_type
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+}
+
+/// Weakly typed connection, the same JSON shape as used by config-dns-daemon. Addresses are in the
+/// `address/prefix` form.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConnectionWeak {
+    pub id: String,
+    #[serde(default)]
+    pub uuid: String,
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+    #[serde(rename = "type")]
+    pub con_type: String,
+    pub default: bool,
+    /// Missing means the default route is allowed
+    #[serde(default)]
+    pub never_default: bool,
+    pub addresses: Vec<String>,
+    pub nameservers: Vec<String>,
+    /// Domains in the Network Manager syntax, routing-only domains are prefixed with `~`
+    pub domains: Vec<String>,
+    /// Routes in the `dest/prefix` form
+    #[serde(default)]
+    pub routes: Vec<String>,
+}
+
+impl<'a> From<&'a Connection> for ConnectionWeak {
+    fn from(c: &'a Connection) -> Self {
+        ConnectionWeak {
+            id: c.id.clone(),
+            uuid: c.uuid.clone(),
+            interfaces: c.interfaces.clone(),
+            con_type: c.nm_type.clone(),
+            default: c.default,
+            never_default: c.never_default,
+            addresses: c.addresses.iter().map(|(a, p)| format!("{}/{}", a, p)).collect(),
+            nameservers: c.nameservers.iter().map(|n| n.to_string()).collect(),
+            domains: c.domains.to_nm(),
+            routes: c.routes.iter().map(|(d, p)| format!("{}/{}", d, p)).collect(),
+        }
+    }
+}
+
+impl From<ConnectionWeak> for Connection {
+    /// Addresses and nameservers that can't be parsed are skipped
+    fn from(c: ConnectionWeak) -> Self {
+        let addresses = c.addresses.iter()
+            .filter_map(|a| parse_prefix(a))
+            .collect();
+        let routes = c.routes.iter()
+            .filter_map(|r| parse_prefix(r))
+            .collect();
+        let nameservers = c.nameservers.iter()
+            .filter_map(|a| a.parse::<IpAddr>().ok())
+            .collect();
+        Connection {
+            id: c.id,
+            uuid: c.uuid,
+            interfaces: c.interfaces,
+            con_type: ConnectionType::from_nm(&c.con_type),
+            nm_type: c.con_type,
+            default: c.default,
+            never_default: c.never_default,
+            addresses,
+            nameservers,
+            domains: c.domains.iter().map(|d| d.as_str()).collect(),
+            routes,
         }
     }
 }
@@ -1,9 +1,7 @@
-use dbus::{BusType, Connection, Path, arg};
-use dbus::stdintf::org_freedesktop_dbus::Properties;
-use dbus::arg::RefArg;
-use std::collections::HashMap;
-use std::str::FromStr;
-use std::net::Ipv4Addr;
+use dbus::{BusType, Connection, Message, Path};
+use dbus_connection::nm::{self, ActiveConnection, ConnectionSettings, Device, Ip4Config, Ip6Config, PropertyMap};
+use std::collections::HashSet;
+use std::net::IpAddr;
 
 use super::{ConnectionType, Connections, NetworkMonitor};
 
@@ -28,33 +26,97 @@ impl MonitorState {
     }
 }
 
+/// Properties of the IP4Config, IP6Config and DHCP4Config objects that affect DNS configuration.
+/// A change in any of them means that the connection must be queried again.
+const DNS_PROPERTIES: [&str; 5] =
+    ["Nameservers", "NameserverData", "Domains", "Searches", "Options"];
+
+/// Properties of an ActiveConnection that point to its config objects. NetworkManager may swap
+/// a config object for a new one at a different path, which is only announced here.
+const CONFIG_PROPERTIES: [&str; 3] = ["Ip4Config", "Ip6Config", "Dhcp4Config"];
+
+/// Match rules for the signals emitted by NetworkManager itself.
+const NM_MATCHES: [&str; 2] = [
+    "type='signal',sender='org.freedesktop.NetworkManager',interface='org.freedesktop.NetworkManager'",
+    "type='signal',sender='org.freedesktop.NetworkManager',interface='org.freedesktop.DBus.Properties',member='PropertiesChanged'",
+];
+
+/// Match rule for NetworkManager leaving or (re)joining the bus, e.g. when it is restarted.
+const NAME_OWNER_MATCH: &str = "type='signal',sender='org.freedesktop.DBus',interface='org.freedesktop.DBus',member='NameOwnerChanged',arg0='org.freedesktop.NetworkManager'";
+
 pub struct DbusMonitor {
     state: MonitorState,
     connection: Connection,
-}
-
-fn reverse_u32_order(i: u32) -> u32 {
-    (
-    ((i & 0xff000000) >> 24) |
-    ((i & 0x00ff0000) >>  8) |
-    ((i & 0x0000ff00) <<  8) |
-    ((i & 0x000000ff) << 24)
-    )
+    /// Active connections included in the last snapshot
+    active: Vec<Path<'static>>,
+    /// Config objects belonging to the active connections. Changes of their properties don't show
+    /// up in the ActiveConnections property (e.g. DHCP renewal or VPN reconnect), so they must be
+    /// watched separately.
+    watched: HashSet<String>,
 }
 
 impl DbusMonitor {
     pub fn new() -> DbusMonitor {
-        let connection = Connection::get_private(BusType::System).unwrap();
+        Self::with_connection(Connection::get_private(BusType::System).unwrap())
+    }
+
+    /// Monitor NetworkManager on an already open bus connection, e.g. a private test bus
+    pub fn with_connection(connection: Connection) -> DbusMonitor {
         let state = MonitorState::new();
-        connection.add_match("type='signal',sender='org.freedesktop.NetworkManager',interface='org.freedesktop.NetworkManager'").unwrap();
+        connection.add_match(NAME_OWNER_MATCH).unwrap();
+        for rule in NM_MATCHES.iter() {
+            connection.add_match(rule).unwrap();
+        }
 
         DbusMonitor {
             state,
-            connection
+            connection,
+            active: vec![],
+            watched: HashSet::new(),
         }
     }
 
-    fn query_network_manager(&self, numbers: Vec<u32>) -> Connections {
+    /// NetworkManager (re)appeared on the bus. Everything learned from the previous instance is
+    /// stale, so renew the match rules and query the list of active connections from scratch.
+    fn resync(&mut self) {
+        for rule in NM_MATCHES.iter() {
+            let _ = self.connection.remove_match(rule);
+            if let Err(e) = self.connection.add_match(rule) {
+                error!("Failed to add match rule {}: {}", rule, e);
+            }
+        }
+        self.watched.clear();
+
+        match nm::active_connections(&self.connection) {
+            Ok(paths) => {
+                self.active = paths;
+                trace!("Active connections after resync: {:?}", self.active);
+                self.state = MonitorState::Debouncing;
+            },
+            Err(e) => warn!("Could not query active connections: {}", e),
+        }
+    }
+
+    /// Returns true if the message is a change of DNS related properties on one of the watched
+    /// config objects, or one of the active connections got a new config object.
+    fn is_dns_change(&self, msg: &Message) -> bool {
+        let path = match msg.path() {
+            Some(path) => path,
+            None => return false,
+        };
+        let properties: &[&str] = if self.active.iter().any(|active| *active == path) {
+            &CONFIG_PROPERTIES
+        } else if self.watched.contains(&*path) {
+            &DNS_PROPERTIES
+        } else {
+            return false;
+        };
+        changed_properties(msg)
+            .map(|hashmap| properties.iter().any(|p| hashmap.contains_key(*p)))
+            .unwrap_or(false)
+    }
+
+    fn query_network_manager(&mut self) -> Connections {
         use std::{thread, time};
 
         // FIXME: There is something REALLY SLOW in this system.
@@ -67,53 +129,63 @@ impl DbusMonitor {
         // So why on earth am I using the filter_map ??
         // Well as it turns out, when a connection is removed Network Manager sends a signal with a
         // list of old connections. So one of them will fail to inspect.
-        let con = numbers.iter().filter_map(|i| {
-            let p = self.connection.with_path("org.freedesktop.NetworkManager",
-                                              format!("/org/freedesktop/NetworkManager/ActiveConnection/{}", i),
-                                              5000);
-            //println!("{:?}", p);
+        let mut watched = HashSet::new();
+        let con = self.active.iter().filter_map(|path| {
             // Query the active connection object
-            let interface_name = "org.freedesktop.NetworkManager.Connection.Active";
-
-            let id: String       = p.get(interface_name, "Id").ok()?;
-            let con_type: String = p.get(interface_name, "Type").ok()?;
-            let default: bool    = p.get(interface_name, "Default").ok()?;
-
-            let con_type = ConnectionType::from_str(&con_type);
+            let active = ActiveConnection::query(&self.connection, path.clone()).ok()?;
 
-            // Query the IP4Config object
-            let ipconfig_path: Path = p.get("org.freedesktop.NetworkManager.Connection.Active",
-                                            "Ip4Config").ok()?;
-            //println!("{:?}", ipconfig_path);
-            let p = self.connection.with_path("org.freedesktop.NetworkManager", ipconfig_path, 5000);
-
-            let interface_name = "org.freedesktop.NetworkManager.IP4Config";
-            let mut domains: Vec<String> = p.get(interface_name, "Domains").ok()?;
-            let searches: Vec<String>    = p.get(interface_name, "Searches").ok()?;
-            let nameservers: Vec<u32>    = p.get(interface_name, "Nameservers").ok()?;
-            let addresses: Vec<Vec<u32>>    = p.get(interface_name, "Addresses").ok()?;
+            // Remember the config objects, so that changes of their properties trigger an update
+            for config in active.ip4_config.iter().chain(active.ip6_config.iter()).chain(active.dhcp4_config.iter()) {
+                watched.insert(config.to_string());
+            }
+            let ip4config = active.ip4_config.clone()
+                .and_then(|path| Ip4Config::query(&self.connection, path).ok());
+            let ip6config = active.ip6_config.clone()
+                .and_then(|path| Ip6Config::query(&self.connection, path).ok());
+            // Neither config object is there until the connection is activated
+            if ip4config.is_none() && ip6config.is_none() {
+                return None;
+            }
+            let ip4config = ip4config.unwrap_or_default();
+            let ip6config = ip6config.unwrap_or_default();
 
-            let nameservers: Vec<Ipv4Addr> = nameservers
-                .into_iter()
-                .map(|i| Ipv4Addr::from(reverse_u32_order(i)))
-                .collect();
-            domains.extend(searches);
-            let addresses: Vec<(Ipv4Addr, u8)> = addresses
-                .into_iter()
-                .map(|i| (Ipv4Addr::from(reverse_u32_order(i[0])), i[1] as u8))
+            let interfaces = active.devices.iter()
+                .filter_map(|path| Device::query(&self.connection, path.clone()).ok())
+                .map(|device| if device.ip_interface.is_empty() { device.interface } else { device.ip_interface })
                 .collect();
+            let never_default = active.connection.clone()
+                .and_then(|path| ConnectionSettings::query(&self.connection, path).ok())
+                .is_some_and(|settings| settings.never_default);
 
-            //trace!("{:?}", domains);
-            Some(super::Connection {
-                id,
-                domains,
-                con_type,
-                default,
-                nameservers,
-                addresses,
-            })
+            let mut connection = super::Connection {
+                id: active.id,
+                uuid: active.uuid,
+                interfaces,
+                con_type: ConnectionType::from_nm(&active.con_type),
+                nm_type: active.con_type,
+                default: active.default,
+                never_default,
+                addresses: ip4config.addresses.iter().chain(ip6config.addresses.iter())
+                    .map(|a| (a.address, a.prefix))
+                    .collect(),
+                nameservers: ip4config.nameservers.iter().cloned().map(IpAddr::V4)
+                    .chain(ip6config.nameservers.iter().cloned().map(IpAddr::V6))
+                    .collect(),
+                routes: ip4config.routes.iter().chain(ip6config.routes.iter())
+                    .map(|r| (r.dest, r.prefix))
+                    .collect(),
+                ..Default::default()
+            };
+            // Routing-only domains (`~example.com`) only show up in Searches
+            let domains = ip4config.domains.iter().chain(ip4config.searches.iter())
+                .chain(ip6config.domains.iter()).chain(ip6config.searches.iter());
+            for domain in domains {
+                connection.domains.add(domain);
+            }
+            Some(connection)
         }).collect();
 
+        self.watched = watched;
         Connections {
             con,
         }
@@ -121,90 +193,350 @@ impl DbusMonitor {
 }
 
 impl NetworkMonitor for DbusMonitor {
-    fn wait_for_connections(&mut self) -> Connections {
-        let mut get: Option<HashMap<String, arg::Variant<Box<arg::RefArg + 'static>>>> = None;
+    fn wait_for_connections(&mut self) -> Option<Connections> {
         loop {
             if let Some(msg) = self.connection.incoming(self.state.next_timeout()).next() {
-                self.state = MonitorState::Watching;
                 trace!("{:?}", msg);
-                get = msg.get1();
-                println!("{:?}", get);
-                if let Some(hashmap) = &get {
-                    if hashmap.contains_key("ActiveConnections") {
-                        trace!("Active connections changed");
+                if let Some(new_owner) = name_owner_changed(&msg) {
+                    if new_owner.is_empty() {
+                        warn!("NetworkManager left the bus");
+                        self.watched.clear();
+                    } else {
+                        info!("NetworkManager appeared on the bus as {}, resyncing", new_owner);
+                        self.resync();
+                    }
+                    continue;
+                }
+                let is_manager = msg.path().is_some_and(|p| &*p == nm::NM_PATH);
+                if is_manager {
+                    let paths = changed_properties(&msg)
+                        .and_then(|hashmap| hashmap.get("ActiveConnections").and_then(nm::object_paths));
+                    if let Some(paths) = paths {
+                        trace!("Active connections changed: {:?}", paths);
                         trace!("Debouncing");
+                        self.active = paths;
                         self.state = MonitorState::Debouncing;
                     }
+                } else if self.is_dns_change(&msg) {
+                    trace!("DNS configuration changed in {:?}", msg.path());
+                    trace!("Debouncing");
+                    self.state = MonitorState::Debouncing;
                 }
             } else {
                 if self.state == MonitorState::Debouncing {
                     trace!("Run update");
-
-                    // WARNING: read this code on your own risk!
-                    // It converts dynamically typed D-Bus data into statically typed Rust
-                    // variables, it is very ugly and your head might explode while reading it!
-
-                    // ... but it works ...
-
-                    let maybe_numbers: Option<Vec<u32>> = get
-                        // Get active connections field
-                        // The closure returns Option<Value>
-                        .and_then(|mut hashmap| {
-                            // In order to obtain ownership of the value, I need to remove it
-                            // from the collection
-                            hashmap.remove_entry("ActiveConnections")
-                                .map(|x| x.1)
-                        })
-                        // This closure takes the value from the collection and returns
-                        // Option<Vec<u32>, therefore and_then
-                        .and_then(|value| -> Option<Vec<u32>> {
-                            // unwrap the Variant type (D-Bus defined)
-                            // this returns Option<sth>
-                            value.as_iter()
-                                // so map over the optional value, therefore the closure must
-                                // return the final vector
-                                .map(|variant| -> Vec<u32> {
-                                    // Now I have a list of sth, where the items can contains lists
-                                    // of object paths. Don't ask me why, it just is in this format.
-                                    variant.filter_map(|i| -> Option<Vec<u32>> {
-                                        // Convert the i value into a list if possible, so this will
-                                        // return Option<...>
-                                        i.as_iter()
-                                            // map over the Option
-                                            .map(|list| {
-                                                // This indeed was a list so we want to iterate
-                                                // over it
-                                                list.filter_map(|maybe_objpath| {
-                                                    // Try to convert the items into an object path
-                                                    maybe_objpath.as_str()
-                                                        // if the conversion was successful, try to
-                                                        // process it. The processing itself can
-                                                        // return an error, so use and_then method
-                                                        // instead of simple map.
-                                                        .and_then(|objpath| -> Option<u32> {
-                                                            let path: Vec<&str> = objpath.split("/").collect();
-                                                            u32::from_str(path[path.len()-1]).ok()
-                                                        })
-                                                }).collect()
-                                            })
-                                    }).flatten().collect()
-                                })
-                        });
-                    println!("{:?}", maybe_numbers);
-                    get = None;
-
-                    if let Some(numbers) = maybe_numbers {
-                        self.state = MonitorState::Watching;
-                        return self.query_network_manager(numbers);
-                    }
+                    self.state = MonitorState::Watching;
+                    return Some(self.query_network_manager());
                 }
             }
         }
     }
 }
 
+/// Extract the changed properties from a PropertiesChanged signal. NetworkManager emits both its
+/// own signal with a single dictionary argument and the standard one from
+/// org.freedesktop.DBus.Properties where the dictionary is the second argument.
+fn changed_properties(msg: &Message) -> Option<PropertyMap> {
+    if msg.member().is_none_or(|m| &*m != "PropertiesChanged") {
+        return None;
+    }
+    match msg.get2::<&str, PropertyMap>() {
+        (Some(_), Some(hashmap)) => Some(hashmap),
+        _ => msg.get1(),
+    }
+}
+
+/// Extract the new owner of the NetworkManager bus name from a NameOwnerChanged signal. The owner
+/// is empty if NetworkManager left the bus.
+fn name_owner_changed(msg: &Message) -> Option<String> {
+    if msg.member().is_none_or(|m| &*m != "NameOwnerChanged") {
+        return None;
+    }
+    match msg.read3::<&str, &str, &str>() {
+        Ok(("org.freedesktop.NetworkManager", _, new_owner)) => Some(new_owner.to_string()),
+        _ => None,
+    }
+}
+
 #[test]
-fn test_reverse_u32_order() {
-    let input = 0xAABBCCDD;
-    assert_eq!(reverse_u32_order(input), 0xDDCCBBAA);
+fn test_active_connections_from_properties_changed() {
+    let paths = vec![Path::from("/org/freedesktop/NetworkManager/ActiveConnection/3"),
+                     Path::from("/org/freedesktop/NetworkManager/ActiveConnection/12")];
+    let mut changed: PropertyMap = PropertyMap::new();
+    changed.insert("ActiveConnections".to_string(), ::dbus::arg::Variant(Box::new(paths)));
+    let msg = Message::signal(&"/org/freedesktop/NetworkManager".into(),
+                              &"org.freedesktop.DBus.Properties".into(),
+                              &"PropertiesChanged".into())
+        .append3("org.freedesktop.NetworkManager", changed, Vec::<String>::new());
+    let paths = changed_properties(&msg)
+        .and_then(|hashmap| hashmap.get("ActiveConnections").and_then(nm::object_paths));
+    assert_eq!(paths, Some(vec![Path::from("/org/freedesktop/NetworkManager/ActiveConnection/3"),
+                                Path::from("/org/freedesktop/NetworkManager/ActiveConnection/12")]));
+}
+
+#[test]
+fn test_name_owner_changed() {
+    let msg = Message::signal(&"/org/freedesktop/DBus".into(),
+                              &"org.freedesktop.DBus".into(),
+                              &"NameOwnerChanged".into())
+        .append3("org.freedesktop.NetworkManager", "", ":1.42");
+    assert_eq!(name_owner_changed(&msg), Some(":1.42".to_string()));
+}
+
+#[cfg(test)]
+use dbus_connection::fake_nm::{FakeConnection, FakeIp4Config, FakeIp6Config, FakeNetworkManager, TestBus};
+#[cfg(test)]
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Tests on the fake bus fail instead of passing without running when dbus-daemon is missing
+#[cfg(test)]
+fn start_test_bus() -> TestBus {
+    TestBus::start().expect("dbus-daemon is needed to run the fake NetworkManager")
+}
+
+#[cfg(test)]
+fn home_wifi() -> FakeConnection {
+    FakeConnection {
+        id: "Home".to_string(),
+        con_type: "802-11-wireless".to_string(),
+        default: true,
+        interface: "wlp4s0".to_string(),
+        ip4: Some(FakeIp4Config {
+            addresses: vec![(Ipv4Addr::new(192, 168, 1, 10), 24)],
+            nameservers: vec![Ipv4Addr::new(192, 168, 1, 1)],
+            searches: vec!["home".to_string()],
+            ..Default::default()
+        }),
+        dhcp4_options: vec![("domain_name_servers".to_string(), "192.168.1.1".to_string())],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_dns_only_change() {
+    let bus = start_test_bus();
+    let fake = FakeNetworkManager::start(bus.address()).unwrap();
+    let mut monitor = DbusMonitor::with_connection(bus.connect().unwrap());
+
+    fake.activate(home_wifi());
+    let snapshot = monitor.wait_for_connections().unwrap();
+    assert_eq!(snapshot.con[0].nameservers, vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1))]);
+
+    // The list of active connections stays the same, only the IP4Config changes
+    fake.set_ip4_config("Home", FakeIp4Config {
+        addresses: vec![(Ipv4Addr::new(192, 168, 1, 10), 24)],
+        nameservers: vec![Ipv4Addr::new(192, 168, 1, 2)],
+        searches: vec!["home.arpa".to_string()],
+        ..Default::default()
+    });
+    let snapshot = monitor.wait_for_connections().unwrap();
+    assert_eq!(snapshot.con.len(), 1);
+    assert_eq!(snapshot.con[0].nameservers, vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2))]);
+    assert_eq!(snapshot.con[0].domains.search, vec!["home.arpa".to_string()]);
+}
+
+#[test]
+fn test_dhcp4_renewal() {
+    let bus = start_test_bus();
+    let fake = FakeNetworkManager::start(bus.address()).unwrap();
+    let mut monitor = DbusMonitor::with_connection(bus.connect().unwrap());
+
+    fake.activate(home_wifi());
+    monitor.wait_for_connections().unwrap();
+
+    // Only the Options of the DHCP4Config object are announced
+    fake.renew_dhcp4("Home", FakeIp4Config {
+        addresses: vec![(Ipv4Addr::new(192, 168, 1, 10), 24)],
+        nameservers: vec![Ipv4Addr::new(192, 168, 1, 3)],
+        ..Default::default()
+    }, vec![("domain_name_servers".to_string(), "192.168.1.3".to_string())]);
+    let snapshot = monitor.wait_for_connections().unwrap();
+    assert_eq!(snapshot.con.len(), 1);
+    assert_eq!(snapshot.con[0].nameservers, vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 3))]);
+    assert!(snapshot.con[0].domains.search.is_empty());
+}
+
+#[test]
+fn test_replaced_config_object() {
+    let bus = start_test_bus();
+    let fake = FakeNetworkManager::start(bus.address()).unwrap();
+    let mut monitor = DbusMonitor::with_connection(bus.connect().unwrap());
+
+    fake.activate(home_wifi());
+    monitor.wait_for_connections().unwrap();
+
+    // The IP4Config moves to a new path, only the active connection announces it
+    fake.replace_ip4_config("Home", FakeIp4Config {
+        addresses: vec![(Ipv4Addr::new(192, 168, 1, 10), 24)],
+        nameservers: vec![Ipv4Addr::new(192, 168, 1, 4)],
+        ..Default::default()
+    });
+    let snapshot = monitor.wait_for_connections().unwrap();
+    assert_eq!(snapshot.con[0].nameservers, vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 4))]);
+    assert!(snapshot.con[0].domains.search.is_empty());
+
+    // The new object is watched instead of the old one
+    fake.set_ip4_config("Home", FakeIp4Config {
+        addresses: vec![(Ipv4Addr::new(192, 168, 1, 10), 24)],
+        nameservers: vec![Ipv4Addr::new(192, 168, 1, 5)],
+        ..Default::default()
+    });
+    let snapshot = monitor.wait_for_connections().unwrap();
+    assert_eq!(snapshot.con[0].nameservers, vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 5))]);
+}
+
+#[test]
+fn test_network_manager_restart() {
+    let bus = start_test_bus();
+    let fake = FakeNetworkManager::start(bus.address()).unwrap();
+    let mut monitor = DbusMonitor::with_connection(bus.connect().unwrap());
+
+    fake.activate(home_wifi());
+    let snapshot = monitor.wait_for_connections().unwrap();
+    assert_eq!(snapshot.con[0].id, "Home");
+
+    // NetworkManager leaves the bus and a new instance comes back with a different set of
+    // connections. Nothing but NameOwnerChanged announces them.
+    drop(fake);
+    let office = FakeConnection {
+        id: "Office".to_string(),
+        con_type: "802-3-ethernet".to_string(),
+        default: true,
+        interface: "enp0s25".to_string(),
+        ip4: Some(FakeIp4Config {
+            addresses: vec![(Ipv4Addr::new(10, 10, 0, 10), 24)],
+            nameservers: vec![Ipv4Addr::new(10, 10, 0, 99)],
+            searches: vec!["office.example.com".to_string()],
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let fake = FakeNetworkManager::start_with(bus.address(), vec![office]).unwrap();
+    let snapshot = monitor.wait_for_connections().unwrap();
+    let ids: Vec<&str> = snapshot.con.iter().map(|c| c.id.as_str()).collect();
+    assert_eq!(ids, vec!["Office"]);
+    assert_eq!(snapshot.con[0].nameservers, vec![IpAddr::V4(Ipv4Addr::new(10, 10, 0, 99))]);
+
+    // The match rules are in place again and the config objects of the new instance are watched
+    fake.set_ip4_config("Office", FakeIp4Config {
+        addresses: vec![(Ipv4Addr::new(10, 10, 0, 10), 24)],
+        nameservers: vec![Ipv4Addr::new(10, 10, 0, 88)],
+        ..Default::default()
+    });
+    let snapshot = monitor.wait_for_connections().unwrap();
+    assert_eq!(snapshot.con[0].nameservers, vec![IpAddr::V4(Ipv4Addr::new(10, 10, 0, 88))]);
+
+    fake.activate(home_wifi());
+    let snapshot = monitor.wait_for_connections().unwrap();
+    let ids: Vec<&str> = snapshot.con.iter().map(|c| c.id.as_str()).collect();
+    assert_eq!(ids, vec!["Office", "Home"]);
+}
+
+#[test]
+fn test_ipv6_only_connection() {
+    let bus = start_test_bus();
+    let fake = FakeNetworkManager::start(bus.address()).unwrap();
+    let mut monitor = DbusMonitor::with_connection(bus.connect().unwrap());
+
+    fake.activate(FakeConnection {
+        id: "Office".to_string(),
+        con_type: "802-3-ethernet".to_string(),
+        default: true,
+        interface: "enp0s25".to_string(),
+        ip6: Some(FakeIp6Config {
+            addresses: vec![("2001:db8::10".parse().unwrap(), 64)],
+            nameservers: vec!["2001:db8::53".parse::<Ipv6Addr>().unwrap()],
+            domains: vec!["office.example.com".to_string()],
+            searches: vec!["~corp.example.com".to_string()],
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    let snapshot = monitor.wait_for_connections().unwrap();
+    assert_eq!(snapshot.con.len(), 1);
+    assert_eq!(snapshot.con[0].addresses, vec![("2001:db8::10".parse().unwrap(), 64)]);
+    assert_eq!(snapshot.con[0].nameservers, vec!["2001:db8::53".parse::<IpAddr>().unwrap()]);
+    assert_eq!(snapshot.con[0].domains.search, vec!["office.example.com".to_string()]);
+    assert_eq!(snapshot.con[0].domains.routing, vec!["corp.example.com".to_string()]);
+}
+
+#[test]
+fn test_fake_network_manager_scenario() {
+    let bus = start_test_bus();
+    let fake = FakeNetworkManager::start(bus.address()).unwrap();
+    let mut monitor = DbusMonitor::with_connection(bus.connect().unwrap());
+
+    // Wi-Fi gets connected
+    let mut wifi = home_wifi();
+    wifi.ip4.as_mut().unwrap().searches.push("~.".to_string());
+    fake.activate(wifi.clone());
+    let snapshot = monitor.wait_for_connections().unwrap();
+    assert_eq!(snapshot.con.len(), 1);
+    assert_eq!(snapshot.con[0].id, "Home");
+    assert_eq!(snapshot.con[0].con_type, ConnectionType::WiFi);
+    assert_eq!(snapshot.con[0].nm_type, "802-11-wireless");
+    assert_eq!(snapshot.con[0].interfaces, vec!["wlp4s0".to_string()]);
+    assert!(snapshot.con[0].default);
+    assert!(!snapshot.con[0].never_default);
+    assert_eq!(snapshot.con[0].addresses, vec![(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)), 24)]);
+    assert_eq!(snapshot.con[0].nameservers, vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1))]);
+    assert_eq!(snapshot.con[0].domains.search, vec!["home".to_string()]);
+    assert!(snapshot.con[0].domains.catch_all);
+
+    // VPN goes up
+    fake.activate(FakeConnection {
+        id: "Work VPN".to_string(),
+        con_type: "vpn".to_string(),
+        vpn: true,
+        never_default: true,
+        interface: "tun0".to_string(),
+        ip4: Some(FakeIp4Config {
+            addresses: vec![(Ipv4Addr::new(10, 40, 0, 6), 22)],
+            routes: vec![(Ipv4Addr::new(66, 187, 233, 0), 24)],
+            nameservers: vec![Ipv4Addr::new(10, 38, 5, 26)],
+            domains: vec!["example.com".to_string()],
+            searches: vec!["~corp.example.com".to_string()],
+        }),
+        ..Default::default()
+    });
+    let snapshot = monitor.wait_for_connections().unwrap();
+    let ids: Vec<&str> = snapshot.con.iter().map(|c| c.id.as_str()).collect();
+    assert_eq!(ids, vec!["Home", "Work VPN"]);
+    assert_eq!(snapshot.con[1].con_type, ConnectionType::VPN);
+    assert!(snapshot.con[1].never_default);
+    assert_eq!(snapshot.con[1].nameservers, vec![IpAddr::V4(Ipv4Addr::new(10, 38, 5, 26))]);
+    assert_eq!(snapshot.con[1].domains.search, vec!["example.com".to_string()]);
+    assert_eq!(snapshot.con[1].domains.routing, vec!["corp.example.com".to_string()]);
+    assert!(!snapshot.con[1].domains.catch_all);
+    assert_eq!(snapshot.con[1].routes, vec![(IpAddr::V4(Ipv4Addr::new(66, 187, 233, 0)), 24)]);
+
+    // VPN goes down
+    fake.deactivate("Work VPN");
+    let snapshot = monitor.wait_for_connections().unwrap();
+    let ids: Vec<&str> = snapshot.con.iter().map(|c| c.id.as_str()).collect();
+    assert_eq!(ids, vec!["Home"]);
+
+    // DHCP lease of the Wi-Fi is renewed with a different nameserver
+    let mut renewed = wifi.ip4.clone().unwrap();
+    renewed.nameservers = vec![Ipv4Addr::new(192, 168, 1, 2)];
+    fake.renew_dhcp4("Home", renewed.clone(),
+                     vec![("domain_name_servers".to_string(), "192.168.1.2".to_string())]);
+    let snapshot = monitor.wait_for_connections().unwrap();
+    assert_eq!(snapshot.con.len(), 1);
+    assert_eq!(snapshot.con[0].nameservers, vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2))]);
+    assert!(snapshot.con[0].domains.catch_all);
+
+    // NetworkManager is restarted and comes back with the Wi-Fi only
+    drop(fake);
+    wifi.ip4 = Some(renewed);
+    let fake = FakeNetworkManager::start_with(bus.address(), vec![wifi]).unwrap();
+    let snapshot = monitor.wait_for_connections().unwrap();
+    let ids: Vec<&str> = snapshot.con.iter().map(|c| c.id.as_str()).collect();
+    assert_eq!(ids, vec!["Home"]);
+    assert_eq!(snapshot.con[0].nameservers, vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2))]);
+
+    // Wi-Fi goes down on the new instance
+    fake.deactivate("Home");
+    let snapshot = monitor.wait_for_connections().unwrap();
+    assert!(snapshot.con.is_empty());
 }
@@ -10,15 +10,27 @@
 extern crate serde_derive;
 extern crate docopt;
 extern crate dbus;
+extern crate dbus_connection;
 #[macro_use]
 extern crate log;
 extern crate env_logger;
+extern crate nm_connection;
+extern crate serde_json;
+#[cfg(feature = "libnm")]
+extern crate glib;
+#[cfg(feature = "libnm")]
+extern crate nm;
 
 use docopt::Docopt;
 
 mod dbus_monitor;
 mod connection;
+mod replay_monitor;
+#[cfg(feature = "libnm")]
+mod nm_client_monitor;
 
+use nm_connection::Config;
+use replay_monitor::Recorder;
 pub use connection::*;
 
 const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
@@ -26,19 +38,30 @@ const USAGE: &'static str = "
 dnsconfigd - Dynamic DNS configuration daemon
 
 Usage:
-  dnsconfigd
+  dnsconfigd [--config <file>] [--libnm [--nm-dns-config]] [--record <file>]
+  dnsconfigd [--config <file>] --replay <file>
   dnsconfigd (-h | --help)
   dnsconfigd --version
 
 Options:
-  -h --help     Show this screen.
-  --version     Show version.
+  -h --help         Show this screen.
+  --version         Show version.
+  --config <file>   Read connection exclusion and inclusion rules from a JSON file.
+  --libnm           Monitor NetworkManager using libnm instead of plain D-Bus.
+  --nm-dns-config   Take nameservers and domains from NetworkManager's own DNS configuration.
+  --record <file>   Append every snapshot of connections to a file.
+  --replay <file>   Replay snapshots recorded with --record instead of monitoring D-Bus.
 ";
 
 #[derive(Debug, Deserialize)]
 struct Args {
     flag_help: bool,
     flag_version: bool,
+    flag_config: Option<String>,
+    flag_libnm: bool,
+    flag_nm_dns_config: bool,
+    flag_record: Option<String>,
+    flag_replay: Option<String>,
 }
 
 /// Output of the first stage (monitor)
@@ -59,7 +82,9 @@ pub struct SplitView {}
 /// implementation is available in dbus_monitor which, as the name suggests, implements monitoring
 /// of Network Manager over D-Bus.
 pub trait NetworkMonitor {
-    fn wait_for_connections(&mut self) -> Connections;
+    /// Block until the connections change. `None` means no change will ever come, e.g. the end of
+    /// a replayed recording.
+    fn wait_for_connections(&mut self) -> Option<Connections>;
 }
 
 /// # 2nd stage
@@ -72,21 +97,48 @@ pub trait Backend {
     fn set(view: SplitView) -> Result<(), ()>;
 }
 
-fn run<M/*,C,B*/>(mut monitor: M/*, calc: C, backend: B*/) -> Result<(), &'static str>
+/// Every snapshot reported by the monitor is also appended to `recorder`, before the exclusion
+/// rules are applied.
+fn run<M/*,C,B*/>(mut monitor: M, config: &Config, mut recorder: Option<Recorder>/*, calc: C, backend: B*/)
+    -> Result<(), &'static str>
     where M: NetworkMonitor,
         // C: SplitViewCalculator,
         // B: Backend
 {
-    loop {
-        let connections = monitor.wait_for_connections();
+    while let Some(mut connections) = monitor.wait_for_connections() {
+        if let Some(recorder) = recorder.as_mut() {
+            if let Err(e) = recorder.record(&connections) {
+                warn!("Failed to record connections: {}", e);
+            }
+        }
+        connections.con.retain(|c| {
+            let excluded = config.is_excluded(c);
+            if excluded {
+                debug!("Ignoring connection {} ({:?})", c.id, c.interfaces);
+            }
+            !excluded
+        });
         info!("New connections: {:#?}", connections);
     }
+    Ok(())
+}
+
+#[cfg(feature = "libnm")]
+fn libnm_monitor(dns_config: bool, config: &Config, recorder: Option<Recorder>) -> Result<(), &'static str> {
+    use nm_client_monitor::{NmClientMonitor, Source};
+    let source = if dns_config { Source::DnsConfiguration } else { Source::ActiveConnections };
+    run(NmClientMonitor::new(source), config, recorder)
+}
+
+#[cfg(not(feature = "libnm"))]
+fn libnm_monitor(_dns_config: bool, _config: &Config, _recorder: Option<Recorder>) -> Result<(), &'static str> {
+    Err("dnsconfigd was built without the libnm feature")
 }
 
 fn main() {
     env_logger::init();
 
-    let _args: Args = Docopt::new(USAGE)
+    let args: Args = Docopt::new(USAGE)
         .and_then(|d| {
             d.help(true)
                 .version(VERSION.map(|s| s.to_string()))
@@ -94,11 +146,45 @@ fn main() {
         })
         .unwrap_or_else(|e| e.exit());
 
-    info!("Running the daemon");
+    let config = match args.flag_config {
+        Some(ref path) => match Config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Failed to read configuration {}: {}", path, e);
+                return;
+            },
+        },
+        None => Config::default(),
+    };
+
+    let recorder = match args.flag_record {
+        Some(ref path) => match Recorder::create(path) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                error!("Failed to open {} for recording: {}", path, e);
+                return;
+            },
+        },
+        None => None,
+    };
 
-    let monitor = dbus_monitor::DbusMonitor::new();
+    info!("Running the daemon");
 
-    if let Err(e) = run(monitor) {
+    let result = if let Some(path) = args.flag_replay {
+        match replay_monitor::ReplayMonitor::open(&path) {
+            Ok(monitor) => run(monitor, &config, None),
+            Err(e) => {
+                error!("Failed to read recording {}: {}", path, e);
+                return;
+            },
+        }
+    } else if args.flag_libnm {
+        libnm_monitor(args.flag_nm_dns_config, &config, recorder)
+    } else {
+        run(dbus_monitor::DbusMonitor::new(), &config, recorder)
+    };
+
+    if let Err(e) = result {
         error!("Failed with {:?}", e);
     }
 }
@@ -0,0 +1,248 @@
+//! Monitor based on libnm.
+//!
+//! Instead of talking D-Bus directly, this monitor uses the NMClient GObject which keeps its own
+//! cache of NetworkManager's objects. The client lives in a separate thread running a GLib main
+//! loop; every signal that might change the DNS configuration makes it send a fresh snapshot to
+//! the monitor.
+//!
+//! Connections are either reconstructed from the IP config objects of the active connections, or
+//! taken from NetworkManager's own DNS configuration (`nm_client_get_dns_configuration`), which
+//! already has NM's priorities applied and is what NM itself writes into resolv.conf.
+
+use glib::{MainContext, MainLoop};
+use nm::{ActiveConnection, ActiveConnectionExt, ActiveConnectionExtManual, Client, ClientExt, DeviceExt, DnsEntry,
+         IPConfigExt, RemoteConnectionExt, SettingIPConfig, SettingIPConfigExt};
+use std::net::IpAddr;
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use super::{Connection, ConnectionType, Connections, NetworkMonitor};
+
+/// Signals tend to come in bursts (e.g. a VPN adds a connection and changes the primary one), only
+/// the last snapshot of a burst is reported.
+const DEBOUNCE: Duration = Duration::from_millis(20);
+
+/// Where the monitor takes the DNS configuration from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// IP config objects of the active connections
+    ActiveConnections,
+    /// NMDnsEntry records of NetworkManager's DNS configuration
+    DnsConfiguration,
+}
+
+pub struct NmClientMonitor {
+    snapshots: Receiver<Connections>,
+}
+
+impl NmClientMonitor {
+    pub fn new(source: Source) -> NmClientMonitor {
+        let (tx, snapshots) = channel();
+        thread::spawn(move || run_main_loop(tx, source));
+        NmClientMonitor {
+            snapshots,
+        }
+    }
+}
+
+impl NetworkMonitor for NmClientMonitor {
+    /// `None` once the libnm thread is gone, e.g. because NMClient could not be created
+    fn wait_for_connections(&mut self) -> Option<Connections> {
+        let mut connections = self.snapshots.recv().ok()?;
+        loop {
+            match self.snapshots.recv_timeout(DEBOUNCE) {
+                Ok(newer) => connections = newer,
+                Err(RecvTimeoutError::Timeout) => return Some(connections),
+                Err(RecvTimeoutError::Disconnected) => return Some(connections),
+            }
+        }
+    }
+}
+
+fn connection_from_active(active: &ActiveConnection) -> Connection {
+    let nm_type = active.get_connection_type().unwrap_or_default();
+    let mut connection = Connection {
+        id: active.get_id().unwrap_or_default(),
+        uuid: active.get_uuid().unwrap_or_default(),
+        interfaces: active.get_devices().iter()
+            .filter_map(|d| d.get_ip_iface().or_else(|| d.get_iface()))
+            .collect(),
+        con_type: ConnectionType::from_nm(&nm_type),
+        nm_type,
+        default: active.get_default(),
+        never_default: active.get_connection().is_some_and(|profile| {
+            never_default(&[profile.get_setting_ip4_config(), profile.get_setting_ip6_config()])
+        }),
+        ..Default::default()
+    };
+    // The config objects are missing until the connection is fully activated
+    for ipconfig in active.get_ip4_config().into_iter().chain(active.get_ip6_config()) {
+        connection.addresses.extend(ipconfig.get_addresses().iter()
+            .filter_map(|a| Some((a.get_address()?.parse::<IpAddr>().ok()?, a.get_prefix() as u8))));
+        connection.nameservers.extend(ipconfig.get_nameservers().iter()
+            .filter_map(|n| n.parse::<IpAddr>().ok()));
+        connection.routes.extend(ipconfig.get_routes().iter()
+            .filter_map(|r| Some((r.get_dest()?.parse::<IpAddr>().ok()?, r.get_prefix() as u8))));
+        // Routing-only domains (`~example.com`) only show up in searches
+        for domain in ipconfig.get_domains().iter().chain(ipconfig.get_searches().iter()) {
+            connection.domains.add(domain);
+        }
+    }
+    connection
+}
+
+/// Same rule as `dbus_connection::nm::never_default`: the connection never gets the default
+/// route if none of its enabled IP families may get it.
+fn never_default(settings: &[Option<SettingIPConfig>]) -> bool {
+    let enabled: Vec<&SettingIPConfig> = settings.iter()
+        .filter_map(Option::as_ref)
+        .filter(|s| !matches!(s.get_method().as_deref(), Some("disabled") | Some("ignore")))
+        .collect();
+    !enabled.is_empty() && enabled.iter().all(|s| s.get_never_default())
+}
+
+/// Find the active connection whose device has the given IP interface
+fn active_by_interface(client: &Client, interface: &str) -> Option<ActiveConnection> {
+    client.get_active_connections().into_iter()
+        .find(|active| {
+            active.get_devices().iter()
+                .any(|d| d.get_ip_iface().or_else(|| d.get_iface()).is_some_and(|i| i == interface))
+        })
+}
+
+/// Keep only the entries NetworkManager would use. If any entry has a negative priority, only the
+/// entries with the lowest priority are used; the rest is ordered by priority.
+fn apply_priorities<T, F>(mut entries: Vec<T>, priority: F) -> Vec<T>
+    where F: Fn(&T) -> i32
+{
+    entries.sort_by_key(&priority);
+    if let Some(lowest) = entries.first().map(&priority).filter(|p| *p < 0) {
+        entries.retain(|e| priority(e) == lowest);
+    }
+    entries
+}
+
+/// Build connections from the DNS entries, in the order of their priority. NetworkManager has an
+/// entry for each IP family of a connection, entries of the same interface are merged.
+fn connections_from_dns_entries(client: &Client, entries: &[DnsEntry]) -> Vec<Connection> {
+    let mut connections: Vec<(String, Connection)> = vec![];
+    for entry in entries {
+        let interface = entry.get_interface().unwrap_or_default();
+        let position = match connections.iter().position(|(i, _)| *i == interface) {
+            Some(position) => position,
+            None => {
+                let mut connection = active_by_interface(client, &interface)
+                    .map(|active| connection_from_active(&active))
+                    .unwrap_or_default();
+                if connection.id.is_empty() {
+                    connection.id = interface.clone();
+                }
+                // NetworkManager's view of nameservers and domains takes precedence over the IP
+                // config objects
+                connection.nameservers.clear();
+                connection.domains = Default::default();
+                connections.push((interface, connection));
+                connections.len() - 1
+            },
+        };
+        let connection = &mut connections[position].1;
+        if entry.get_vpn() && !connection.con_type.is_tunnel() {
+            connection.con_type = ConnectionType::VPN;
+        }
+        connection.nameservers.extend(entry.get_nameservers().iter()
+            .filter_map(|n| n.parse::<IpAddr>().ok()));
+        for domain in entry.get_domains() {
+            connection.domains.add(&domain);
+        }
+    }
+    connections.into_iter().map(|(_, connection)| connection).collect()
+}
+
+fn snapshot(client: &Client, source: Source) -> Connections {
+    if let Some(mode) = client.get_dns_mode() {
+        debug!("NetworkManager DNS mode: {}", mode);
+    }
+    if let Some(primary) = client.get_primary_connection() {
+        trace!("Primary connection: {:?}", primary.get_id());
+    }
+    let con = match source {
+        Source::ActiveConnections => client.get_active_connections().iter().map(connection_from_active).collect(),
+        Source::DnsConfiguration => {
+            let entries = apply_priorities(client.get_dns_configuration(), |e| e.get_priority());
+            connections_from_dns_entries(client, &entries)
+        },
+    };
+    Connections {
+        con,
+    }
+}
+
+/// The config objects of an active connection show up once it is activated and may be replaced
+/// later on, neither is announced by the client itself.
+fn watch_active<R: Fn(&Client) + 'static>(client: &Client, active: &ActiveConnection, report: &Rc<R>) {
+    let (c, r) = (client.clone(), report.clone());
+    active.connect_property_ip4_config_notify(move |_| r(&c));
+    let (c, r) = (client.clone(), report.clone());
+    active.connect_property_ip6_config_notify(move |_| r(&c));
+    let (c, r) = (client.clone(), report.clone());
+    active.connect_property_state_notify(move |_| r(&c));
+}
+
+fn run_main_loop(tx: Sender<Connections>, source: Source) {
+    let context = MainContext::new();
+    context.push_thread_default();
+    let main_loop = MainLoop::new(Some(&context), false);
+
+    // NMClient delivers its signals on the thread default context, so it must be created after
+    // the context is pushed
+    let client = match Client::new() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to create NMClient: {}", e);
+            return;
+        },
+    };
+
+    let report = Rc::new(move |client: &Client| {
+        if tx.send(snapshot(client, source)).is_err() {
+            trace!("Monitor is gone");
+        }
+    });
+    report(&client);
+
+    for active in client.get_active_connections() {
+        watch_active(&client, &active, &report);
+    }
+    let r = report.clone();
+    client.connect_active_connection_added(move |client, active| {
+        trace!("Active connection added: {:?}", active.get_id());
+        watch_active(client, active, &r);
+        r(client);
+    });
+    let r = report.clone();
+    client.connect_active_connection_removed(move |client, active| {
+        trace!("Active connection removed: {:?}", active.get_id());
+        r(client);
+    });
+    let r = report.clone();
+    client.connect_property_primary_connection_notify(move |client| r(client));
+    let r = report;
+    client.connect_property_dns_configuration_notify(move |client| r(client));
+
+    main_loop.run();
+}
+
+#[test]
+fn test_apply_priorities() {
+    // (interface, priority)
+    let entries = vec![("wlp4s0", 100), ("tun0", 50), ("enp0s25", 100)];
+    assert_eq!(apply_priorities(entries, |e| e.1), vec![("tun0", 50), ("wlp4s0", 100), ("enp0s25", 100)]);
+
+    // A full-tunnel VPN with a negative priority shadows everything with a higher value
+    let entries = vec![("wlp4s0", 100), ("tun0", -50), ("tun1", -50), ("enp0s25", -10)];
+    assert_eq!(apply_priorities(entries, |e| e.1), vec![("tun0", -50), ("tun1", -50)]);
+
+    assert!(apply_priorities(Vec::<(&str, i32)>::new(), |e| e.1).is_empty());
+}
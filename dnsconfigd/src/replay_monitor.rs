@@ -0,0 +1,167 @@
+//! Recording and replaying of monitor output.
+//!
+//! A recording is a JSON Lines file, each line being one snapshot of connections together with
+//! the delay in milliseconds since the previous snapshot:
+//! ```text
+//! {"delay": 0, "connections": [{"id": "enp0s25", "type": "802-3-ethernet", ...}]}
+//! {"delay": 4210, "connections": [...]}
+//! ```
+//! A plain JSON array of connections (the same input config-dns-daemon takes) is accepted as well
+//! and replayed as a single snapshot.
+
+use serde_json;
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Connection, ConnectionWeak, Connections, NetworkMonitor};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    /// Milliseconds since the previous snapshot
+    delay: u64,
+    connections: Vec<ConnectionWeak>,
+}
+
+fn parse_recording(input: &str) -> Result<VecDeque<Snapshot>, serde_json::Error> {
+    if input.trim_start().starts_with('[') {
+        let connections = serde_json::from_str(input)?;
+        return Ok(vec![Snapshot { delay: 0, connections }].into());
+    }
+    input.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect()
+}
+
+/// Monitor replaying a recorded sequence of snapshots with the original timing
+pub struct ReplayMonitor {
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl ReplayMonitor {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<ReplayMonitor> {
+        let mut input = String::new();
+        File::open(path)?.read_to_string(&mut input)?;
+        Ok(ReplayMonitor::parse(&input)?)
+    }
+
+    pub fn parse(input: &str) -> Result<ReplayMonitor, serde_json::Error> {
+        Ok(ReplayMonitor {
+            snapshots: parse_recording(input)?,
+        })
+    }
+
+    /// Number of snapshots that were not replayed yet
+    pub fn remaining(&self) -> usize {
+        self.snapshots.len()
+    }
+}
+
+impl NetworkMonitor for ReplayMonitor {
+    fn wait_for_connections(&mut self) -> Option<Connections> {
+        match self.snapshots.pop_front() {
+            Some(snapshot) => {
+                thread::sleep(Duration::from_millis(snapshot.delay));
+                trace!("Replaying snapshot, {} more left", self.remaining());
+                Some(Connections {
+                    con: snapshot.connections.into_iter().map(Connection::from).collect(),
+                })
+            },
+            None => {
+                info!("End of the recording");
+                None
+            },
+        }
+    }
+}
+
+/// Appends snapshots produced by a monitor to a recording file
+pub struct Recorder {
+    file: File,
+    last: Option<Instant>,
+}
+
+impl Recorder {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Recorder> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder {
+            file,
+            last: None,
+        })
+    }
+
+    pub fn record(&mut self, connections: &Connections) -> io::Result<()> {
+        let now = Instant::now();
+        let delay = self.last.map_or(0, |last| (now - last).as_millis() as u64);
+        self.last = Some(now);
+        let snapshot = Snapshot {
+            delay,
+            connections: connections.con.iter().map(ConnectionWeak::from).collect(),
+        };
+        serde_json::to_writer(&mut self.file, &snapshot)?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+static TESTING_INPUT: &str = r#"[{"id": "enp0s25", "type": "802-3-ethernet", "default": true, "addresses": ["10.10.0.10/24"], "nameservers": ["10.10.0.99", "10.10.0.88"], "domains": ["afk.redhat.com", "redhat.com"]}, {"id": "Red Hat VPN", "type": "vpn", "default": false, "addresses": ["10.11.111.111/22"], "nameservers": ["10.11.111.10", "10.11.111.11"], "domains": ["redhat.com", "~corp.redhat.com"], "never_default": true}]"#;
+
+#[test]
+fn test_replay_single_snapshot() {
+    use super::ConnectionType;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    let mut monitor = ReplayMonitor::parse(TESTING_INPUT).unwrap();
+    assert_eq!(monitor.remaining(), 1);
+    let snapshot = monitor.wait_for_connections().unwrap();
+    assert_eq!(monitor.remaining(), 0);
+    assert_eq!(snapshot.con.len(), 2);
+    assert_eq!(snapshot.con[0].con_type, ConnectionType::Ethernet);
+    assert_eq!(snapshot.con[0].addresses, vec![(IpAddr::V4(Ipv4Addr::new(10, 10, 0, 10)), 24)]);
+    assert_eq!(snapshot.con[1].con_type, ConnectionType::VPN);
+    assert!(!snapshot.con[0].never_default);
+    assert!(snapshot.con[1].never_default);
+    assert_eq!(snapshot.con[1].nameservers, vec![IpAddr::V4(Ipv4Addr::new(10, 11, 111, 10)),
+                                               IpAddr::V4(Ipv4Addr::new(10, 11, 111, 11))]);
+    assert_eq!(snapshot.con[1].domains.search, vec!["redhat.com".to_string()]);
+    assert_eq!(snapshot.con[1].domains.routing, vec!["corp.redhat.com".to_string()]);
+}
+
+#[test]
+fn test_record_and_replay() {
+    let path = ::std::env::temp_dir().join(format!("dnsconfigd-recording-{}.jsonl", ::std::process::id()));
+    let _ = ::std::fs::remove_file(&path);
+    let recorded = ReplayMonitor::parse(TESTING_INPUT).unwrap().wait_for_connections().unwrap();
+    {
+        let mut recorder = Recorder::create(&path).unwrap();
+        recorder.record(&recorded).unwrap();
+        recorder.record(&Connections { con: vec![] }).unwrap();
+    }
+
+    let mut monitor = ReplayMonitor::open(&path).unwrap();
+    ::std::fs::remove_file(&path).unwrap();
+    assert_eq!(monitor.remaining(), 2);
+    let first = monitor.wait_for_connections().unwrap();
+    let ids: Vec<&str> = first.con.iter().map(|c| c.id.as_str()).collect();
+    assert_eq!(ids, vec!["enp0s25", "Red Hat VPN"]);
+    assert_eq!(first.con[0].nm_type, "802-3-ethernet");
+    assert_eq!(first.con[1].domains.search, recorded.con[1].domains.search);
+    assert_eq!(first.con[1].domains.routing, recorded.con[1].domains.routing);
+    assert!(first.con[1].never_default);
+    assert!(monitor.wait_for_connections().unwrap().con.is_empty());
+    assert!(monitor.wait_for_connections().is_none());
+}
+
+#[test]
+fn test_replay_to_completion() {
+    let recording = format!("{{\"delay\": 0, \"connections\": {}}}\n{{\"delay\": 10, \"connections\": []}}\n", TESTING_INPUT);
+    let monitor = ReplayMonitor::parse(&recording).unwrap();
+    assert_eq!(monitor.remaining(), 2);
+    assert_eq!(super::run(monitor, &::nm_connection::Config::default()), Ok(()));
+}
@@ -0,0 +1,225 @@
+//! Daemon configuration.
+//!
+//! The configuration is a JSON file deciding which connections the daemons take into account.
+//! Connections matching any `exclude` rule are ignored unless they also match an `include` rule:
+//! ```text
+//! {
+//!     "exclude": [{"type": "bridge"}, {"interface": "veth*"}],
+//!     "include": [{"id": "Home bridge"}]
+//! }
+//! ```
+//! Every field of a rule is a glob (`*` matches any string, `?` any single character) and all the
+//! fields present in a rule have to match. `type` is matched against the connection type as
+//! Network Manager names it, the way `nmcli -f connection.type connection show <id>` prints it:
+//! `802-3-ethernet`, `802-11-wireless`, `bridge`, `vpn`, `wireguard`, ... When `exclude` is
+//! missing, the default list hiding bridges and interfaces of virtual machines and containers is
+//! used.
+
+use serde_json;
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// What the rules see of a connection
+pub trait Identity {
+    /// Type exactly as Network Manager reports it (`connection.type`)
+    fn nm_type(&self) -> &str;
+    fn id(&self) -> &str;
+    fn uuid(&self) -> &str;
+    /// IP interfaces of the devices the connection is active on
+    fn interfaces(&self) -> &[String];
+}
+
+/// Match a glob pattern against the whole string
+fn glob_match(pattern: &str, s: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    // Position of the last `*` in the pattern and of the input it currently swallows
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut i) = (0, 0);
+    while i < s.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == s[i]) {
+            p += 1;
+            i += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, i));
+            p += 1;
+        } else if let Some((star_p, star_i)) = star {
+            // Let the last `*` swallow one more character and try again
+            p = star_p + 1;
+            i = star_i + 1;
+            star = Some((star_p, star_i + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Set of connections selected by the Network Manager connection type, id, UUID and interface name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub con_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    /// Matches if any of the connection's interfaces matches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+}
+
+impl Rule {
+    fn interface(pattern: &str) -> Rule {
+        Rule {
+            interface: Some(pattern.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn matches<C: Identity>(&self, connection: &C) -> bool {
+        let field = |pattern: &Option<String>, value: &str| pattern.as_ref().is_none_or(|p| glob_match(p, value));
+        field(&self.con_type, connection.nm_type())
+            && field(&self.id, connection.id())
+            && field(&self.uuid, connection.uuid())
+            && self.interface.as_ref().is_none_or(|p| connection.interfaces().iter().any(|i| glob_match(p, i)))
+    }
+}
+
+/// Bridges and the interfaces libvirt, podman and docker create for their guests. Their
+/// nameservers are the host itself or resolvers the guests are not supposed to see.
+fn default_exclude() -> Vec<Rule> {
+    let mut rules = vec![Rule {
+        con_type: Some("bridge".to_string()),
+        ..Default::default()
+    }];
+    rules.extend(["veth*", "virbr*", "docker*", "br-*", "podman*", "cni-podman*"].iter().map(|p| Rule::interface(p)));
+    rules
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default = "default_exclude")]
+    pub exclude: Vec<Rule>,
+    #[serde(default)]
+    pub include: Vec<Rule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            exclude: default_exclude(),
+            include: vec![],
+        }
+    }
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Config> {
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    pub fn is_excluded<C: Identity>(&self, connection: &C) -> bool {
+        self.exclude.iter().any(|r| r.matches(connection)) && !self.include.iter().any(|r| r.matches(connection))
+    }
+}
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match("veth*", "veth1a2b3c"));
+    assert!(glob_match("veth*", "veth"));
+    assert!(!glob_match("veth*", "eth0"));
+    assert!(glob_match("*", ""));
+    assert!(glob_match("wl?4s0", "wlp4s0"));
+    assert!(glob_match("*-*-vpn", "red-hat-vpn"));
+    assert!(!glob_match("tun", "tun0"));
+}
+
+/// Connection as the rules see it
+#[cfg(test)]
+#[derive(Debug, Default, Clone)]
+struct TestConnection {
+    nm_type: String,
+    id: String,
+    uuid: String,
+    interfaces: Vec<String>,
+}
+
+#[cfg(test)]
+impl Identity for TestConnection {
+    fn nm_type(&self) -> &str {
+        &self.nm_type
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+}
+
+#[test]
+fn test_default_exclusions() {
+    let config = Config::default();
+    let connection = |id: &str, interface: &str| TestConnection {
+        id: id.to_string(),
+        nm_type: "802-3-ethernet".to_string(),
+        interfaces: vec![interface.to_string()],
+        ..Default::default()
+    };
+    assert!(config.is_excluded(&connection("virbr0", "virbr0")));
+    assert!(config.is_excluded(&connection("docker0", "docker0")));
+    assert!(config.is_excluded(&connection("veth", "veth4f2e1c")));
+    assert!(!config.is_excluded(&connection("Wired connection 1", "enp0s25")));
+    let bridge = TestConnection {
+        nm_type: "bridge".to_string(),
+        ..connection("Home bridge", "lan0")
+    };
+    assert!(config.is_excluded(&bridge));
+}
+
+#[test]
+fn test_type_rules() {
+    let config: Config = serde_json::from_str(r#"{"exclude": [{"type": "802-11-wireless"}, {"type": "wire*"}]}"#).unwrap();
+    let connection = |con_type: &str| TestConnection {
+        id: "Connection".to_string(),
+        nm_type: con_type.to_string(),
+        ..Default::default()
+    };
+    assert!(config.is_excluded(&connection("802-11-wireless")));
+    assert!(config.is_excluded(&connection("wireguard")));
+    assert!(!config.is_excluded(&connection("802-3-ethernet")));
+    // Names of the variants are not Network Manager types
+    let config: Config = serde_json::from_str(r#"{"exclude": [{"type": "WiFi"}]}"#).unwrap();
+    assert!(!config.is_excluded(&connection("802-11-wireless")));
+}
+
+#[test]
+fn test_include_overrides_exclude() {
+    let config: Config = serde_json::from_str(r#"{
+        "exclude": [{"type": "vpn"}, {"interface": "enp*"}],
+        "include": [{"id": "Work*", "uuid": "5f6c*"}]
+    }"#).unwrap();
+    assert_eq!(config.exclude.len(), 2);
+    let mut vpn = TestConnection {
+        id: "Work VPN".to_string(),
+        uuid: "5f6c7b2e-1f3c-4a2d-9d3e-0c1b2a3f4e5d".to_string(),
+        nm_type: "vpn".to_string(),
+        ..Default::default()
+    };
+    assert!(!config.is_excluded(&vpn));
+    vpn.uuid = "0d2c9b55-7e8a-4c1f-b3a6-2e4f6d8a0c1e".to_string();
+    assert!(config.is_excluded(&vpn));
+
+    // Without an explicit exclude list the defaults stay in place
+    let config: Config = serde_json::from_str(r#"{"include": [{"interface": "virbr1"}]}"#).unwrap();
+    assert_eq!(config.exclude, default_exclude());
+}
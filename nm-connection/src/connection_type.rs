@@ -0,0 +1,153 @@
+/// Type of a connection, the `connection.type` setting mapped to the kinds of links that matter
+/// for DNS configuration
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub enum ConnectionType {
+    Ethernet,
+    Bond,
+    Team,
+    Vlan,
+    /// MACVLAN and IPVLAN devices on top of a wired link
+    Macvlan,
+    Macsec,
+    Infiniband,
+    /// PPP over Ethernet, most DSL links
+    Pppoe,
+    Adsl,
+    VPN,
+    WireGuard,
+    /// Tun/tap devices, e.g. OpenVPN without the NM plugin or Tailscale
+    Tun,
+    /// GRE, IPIP, SIT and the other IP tunnels
+    IpTunnel,
+    Vxlan,
+    WiFi,
+    Wimax,
+    /// Mobile broadband
+    Gsm,
+    Cdma,
+    Bluetooth,
+    /// IEEE 802.15.4 and 6LoWPAN
+    Wpan,
+    Bridge,
+    /// Open vSwitch bridges, ports and interfaces
+    Ovs,
+    Veth,
+    Dummy,
+    Vrf,
+    WifiP2p,
+    Loopback,
+    Other,
+}
+
+impl ConnectionType {
+    /// Map a NetworkManager connection type (`connection.type`) to the variant
+    pub fn from_nm(s: &str) -> Self {
+        match s {
+            "802-3-ethernet" | "ethernet" => ConnectionType::Ethernet,
+            "bond" => ConnectionType::Bond,
+            "team" => ConnectionType::Team,
+            "vlan" => ConnectionType::Vlan,
+            "macvlan" | "ipvlan" => ConnectionType::Macvlan,
+            "macsec" => ConnectionType::Macsec,
+            "infiniband" => ConnectionType::Infiniband,
+            "pppoe" => ConnectionType::Pppoe,
+            "adsl" => ConnectionType::Adsl,
+            "vpn" => ConnectionType::VPN,
+            "wireguard" => ConnectionType::WireGuard,
+            "tun" => ConnectionType::Tun,
+            "ip-tunnel" => ConnectionType::IpTunnel,
+            "vxlan" => ConnectionType::Vxlan,
+            "802-11-wireless" | "wifi" | "802-11-olpc-mesh" | "olpc-mesh" => ConnectionType::WiFi,
+            "wimax" => ConnectionType::Wimax,
+            "gsm" => ConnectionType::Gsm,
+            "cdma" => ConnectionType::Cdma,
+            "bluetooth" => ConnectionType::Bluetooth,
+            "wpan" | "6lowpan" => ConnectionType::Wpan,
+            "bridge" => ConnectionType::Bridge,
+            s if s.starts_with("ovs-") => ConnectionType::Ovs,
+            "veth" => ConnectionType::Veth,
+            "dummy" => ConnectionType::Dummy,
+            "vrf" => ConnectionType::Vrf,
+            "wifi-p2p" => ConnectionType::WifiP2p,
+            "loopback" => ConnectionType::Loopback,
+            s if s.contains("ethernet") => ConnectionType::Ethernet,
+            s if s.contains("wireless") => ConnectionType::WiFi,
+            s if s.contains("vpn") => ConnectionType::VPN,
+            _ => ConnectionType::Other,
+        }
+    }
+
+    /// Default priority of the type, lower wins when several connections claim the same zone.
+    /// Wired links come first, then VPNs and overlay networks (WireGuard, Tailscale) so that they
+    /// keep their own domains when running over Wi-Fi, then the slower and metered links. Links
+    /// that only exist inside the host come last, their nameservers rarely know anything the
+    /// uplinks don't.
+    pub fn priority(&self) -> u32 {
+        match self {
+            ConnectionType::Ethernet | ConnectionType::Bond | ConnectionType::Team | ConnectionType::Vlan
+                | ConnectionType::Macvlan | ConnectionType::Macsec | ConnectionType::Infiniband
+                | ConnectionType::Pppoe | ConnectionType::Adsl => 0,
+            ConnectionType::VPN | ConnectionType::WireGuard | ConnectionType::Tun | ConnectionType::IpTunnel
+                | ConnectionType::Vxlan => 1,
+            ConnectionType::WiFi => 2,
+            ConnectionType::Wimax | ConnectionType::Gsm | ConnectionType::Cdma | ConnectionType::Bluetooth
+                | ConnectionType::Wpan => 3,
+            ConnectionType::Bridge | ConnectionType::Ovs | ConnectionType::Veth | ConnectionType::Dummy
+                | ConnectionType::Vrf | ConnectionType::WifiP2p | ConnectionType::Loopback => 4,
+            ConnectionType::Other => 5,
+        }
+    }
+
+    /// VPNs and overlay networks tunnelled over another connection
+    pub fn is_tunnel(&self) -> bool {
+        matches!(self, ConnectionType::VPN | ConnectionType::WireGuard | ConnectionType::Tun
+                     | ConnectionType::IpTunnel | ConnectionType::Vxlan)
+    }
+}
+
+#[test]
+fn test_connection_type() {
+    // NM type, variant, priority, tunnel
+    let table = [
+        ("802-3-ethernet", ConnectionType::Ethernet, 0, false),
+        ("bond", ConnectionType::Bond, 0, false),
+        ("team", ConnectionType::Team, 0, false),
+        ("vlan", ConnectionType::Vlan, 0, false),
+        ("macvlan", ConnectionType::Macvlan, 0, false),
+        ("ipvlan", ConnectionType::Macvlan, 0, false),
+        ("macsec", ConnectionType::Macsec, 0, false),
+        ("infiniband", ConnectionType::Infiniband, 0, false),
+        ("pppoe", ConnectionType::Pppoe, 0, false),
+        ("adsl", ConnectionType::Adsl, 0, false),
+        ("vpn", ConnectionType::VPN, 1, true),
+        ("org.freedesktop.NetworkManager.openvpn", ConnectionType::VPN, 1, true),
+        ("wireguard", ConnectionType::WireGuard, 1, true),
+        ("tun", ConnectionType::Tun, 1, true),
+        ("ip-tunnel", ConnectionType::IpTunnel, 1, true),
+        ("vxlan", ConnectionType::Vxlan, 1, true),
+        ("802-11-wireless", ConnectionType::WiFi, 2, false),
+        ("802-11-olpc-mesh", ConnectionType::WiFi, 2, false),
+        ("wimax", ConnectionType::Wimax, 3, false),
+        ("gsm", ConnectionType::Gsm, 3, false),
+        ("cdma", ConnectionType::Cdma, 3, false),
+        ("bluetooth", ConnectionType::Bluetooth, 3, false),
+        ("wpan", ConnectionType::Wpan, 3, false),
+        ("6lowpan", ConnectionType::Wpan, 3, false),
+        ("bridge", ConnectionType::Bridge, 4, false),
+        ("ovs-bridge", ConnectionType::Ovs, 4, false),
+        ("ovs-port", ConnectionType::Ovs, 4, false),
+        ("ovs-interface", ConnectionType::Ovs, 4, false),
+        ("veth", ConnectionType::Veth, 4, false),
+        ("dummy", ConnectionType::Dummy, 4, false),
+        ("vrf", ConnectionType::Vrf, 4, false),
+        ("wifi-p2p", ConnectionType::WifiP2p, 4, false),
+        ("loopback", ConnectionType::Loopback, 4, false),
+        ("generic", ConnectionType::Other, 5, false),
+    ];
+    for &(nm_type, con_type, priority, tunnel) in table.iter() {
+        let mapped = ConnectionType::from_nm(nm_type);
+        assert_eq!(mapped, con_type, "{}", nm_type);
+        assert_eq!(mapped.priority(), priority, "{}", nm_type);
+        assert_eq!(mapped.is_tunnel(), tunnel, "{}", nm_type);
+    }
+}
@@ -0,0 +1,62 @@
+pub type Domain = String;
+
+/// Domains of a connection sorted by what they are used for
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Domains {
+    /// Search domains, queries for them are routed to the connection as well
+    pub search: Vec<Domain>,
+    /// Domains only used for routing of queries (`~example.com` in Network Manager)
+    pub routing: Vec<Domain>,
+    /// The connection wants all queries that don't match a more specific domain (`~.`)
+    pub catch_all: bool,
+}
+
+impl Domains {
+    /// Sort a domain in the Network Manager syntax into search or routing domains
+    pub fn add(&mut self, domain: &str) {
+        let domain = domain.trim_end_matches('.');
+        if domain.starts_with('~') {
+            let domain = domain.trim_start_matches('~');
+            if domain.is_empty() {
+                self.catch_all = true;
+            } else if !self.routing.iter().any(|d| d == domain) {
+                self.routing.push(domain.to_string());
+            }
+        } else if !domain.is_empty() && !self.search.iter().any(|d| d == domain) {
+            self.search.push(domain.to_string());
+        }
+    }
+
+    /// All domains whose queries should go to the connection
+    pub fn all(&self) -> impl Iterator<Item = &Domain> {
+        self.search.iter().chain(self.routing.iter())
+    }
+
+    /// The domains in the Network Manager syntax again
+    pub fn to_nm(&self) -> Vec<String> {
+        self.search.iter().cloned()
+            .chain(self.routing.iter().map(|d| format!("~{}", d)))
+            .chain(if self.catch_all { Some("~.".to_string()) } else { None })
+            .collect()
+    }
+}
+
+impl<'a> ::std::iter::FromIterator<&'a str> for Domains {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(iter: I) -> Self {
+        let mut domains = Domains::default();
+        for domain in iter {
+            domains.add(domain);
+        }
+        domains
+    }
+}
+
+#[test]
+fn test_domains() {
+    let domains: Domains = ["redhat.com.", "~corp.redhat.com", "redhat.com", "~.", ""].iter().cloned().collect();
+    assert_eq!(domains.search, vec!["redhat.com".to_string()]);
+    assert_eq!(domains.routing, vec!["corp.redhat.com".to_string()]);
+    assert!(domains.catch_all);
+    assert_eq!(domains.all().collect::<Vec<_>>(), vec!["redhat.com", "corp.redhat.com"]);
+    assert_eq!(domains.to_nm(), vec!["redhat.com", "~corp.redhat.com", "~."]);
+}
@@ -0,0 +1,37 @@
+//! Network Manager connections as the DNS daemons see them.
+//!
+//! dnsconfigd gets the connections over D-Bus and config-dns-daemon as JSON from a script. The
+//! parts interpreting them and the rules selecting them live here, so that both daemons agree on
+//! them and config-dns-daemon builds without libdbus.
+
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use std::str::FromStr;
+
+mod config;
+mod connection_type;
+mod domains;
+
+pub use config::{Config, Identity, Rule};
+pub use connection_type::ConnectionType;
+pub use domains::{Domain, Domains};
+
+/// Parse `address/prefix`
+pub fn parse_prefix<T: FromStr>(input: &str) -> Option<(T, u8)> {
+    let mut split = input.splitn(2, '/');
+    let addr = split.next()?.parse::<T>().ok()?;
+    let prefix = split.next()?.parse::<u8>().ok()?;
+    Some((addr, prefix))
+}
+
+#[test]
+fn test_parse_prefix() {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    assert_eq!(parse_prefix("10.40.0.6/22"), Some((Ipv4Addr::new(10, 40, 0, 6), 22)));
+    assert_eq!(parse_prefix::<IpAddr>("2620:52::/32"), Some(("2620:52::".parse().unwrap(), 32)));
+    assert_eq!(parse_prefix::<Ipv4Addr>("10.40.0.6"), None);
+    assert_eq!(parse_prefix::<Ipv4Addr>("10.40.0.6/x"), None);
+}
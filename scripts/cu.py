@@ -19,21 +19,28 @@ def get_connections():
     # print the connections
     for c in connections:
         #default = "default" if c.
+        # Bridges, libvirt and container interfaces are left to the daemon's exclusion rules
         id = c.get_id()
-        if "virbr" in id or "docker" in id:
+        # A family that isn't configured (yet) has no config object
+        cfgs = [x for x in (c.get_ip4_config(), c.get_ip6_config()) if x is not None]
+        if not cfgs:
             continue
-        cfg = c.get_ip4_config()
-        if cfg is None:
-            continue
-        addr = [(x.get_address(), x.get_prefix()) for x in cfg.get_addresses()]
         new_conn = {}
         new_conn['id'] = id
+        new_conn['uuid'] = c.get_uuid()
+        new_conn['interfaces'] = [d.get_ip_iface() or d.get_iface() for d in c.get_devices()]
         new_conn['type'] = c.get_connection_type()
         new_conn['default'] = c.get_default()
-        new_conn['addresses'] = [str(x.get_address())+'/'+str(x.get_prefix()) for x in cfg.get_addresses()]
+        # A tunnel none of whose IP families may get the default route is a split tunnel
+        profile = c.get_connection()
+        ip_settings = [s for s in (profile.get_setting_ip4_config(), profile.get_setting_ip6_config())
+                       if s is not None and s.get_method() not in ('disabled', 'ignore')]
+        new_conn['never_default'] = bool(ip_settings) and all(s.get_never_default() for s in ip_settings)
+        new_conn['addresses'] = [str(x.get_address())+'/'+str(x.get_prefix()) for cfg in cfgs for x in cfg.get_addresses()]
         #new_conn['prefix'] = c.get_prefix()
-        new_conn['nameservers'] = cfg.get_nameservers()
-        new_conn['domains'] = list(set(cfg.get_domains()+cfg.get_searches()))
+        new_conn['nameservers'] = [n for cfg in cfgs for n in cfg.get_nameservers()]
+        new_conn['domains'] = list(set(d for cfg in cfgs for d in cfg.get_domains()+cfg.get_searches()))
+        new_conn['routes'] = [r.get_dest()+'/'+str(r.get_prefix()) for cfg in cfgs for r in cfg.get_routes()]
         conn_list.append(new_conn)
 
     return conn_list
@@ -46,6 +53,7 @@ def pretty_print(connections):
         print("   addresses: " + str(c['addresses']))
         print("   nameservers: " + str(c['nameservers']))
         print("   domains: " + str(c['domains']))
+        print("   never-default: " + str(c['never_default']))
 
 if __name__ == "__main__":
     parser = argparse.ArgumentParser(description='View network connections')